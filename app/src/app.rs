@@ -1,6 +1,16 @@
-use crate::clip::Clip;
+use crate::clip::{Clip, LaunchQuantize, LoopMode};
+use crate::compositor::BlendMode;
+use crate::control::Action;
+use crate::grid::{file_name, Grid, Scene, Slot};
+use crate::loader::{Loaded, Loader};
+use crate::media::Filter;
+use crate::midi_map::{MidiControlSurface, MidiTarget};
+use crate::osc::{OscArg, OscMessage, OscServer};
 use crate::state::State;
-use std::path::PathBuf;
+use crate::transition::TransitionKind;
+use crate::watch::{FileEvent, FolderWatcher, Playlist, PlaylistWatcher};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use winit::{
     event::*,
@@ -8,18 +18,120 @@ use winit::{
     keyboard::{KeyCode, PhysicalKey},
     window::Window,
 };
-use crate::LINK;
+use transport::{Transport, TransportSnapshot};
+
+/// How late after a downbeat playback may first be seen and still count as starting on it
+const START_TOLERANCE_BEATS: f64 = 0.25;
+
+/// Most lines of each compile error shown on screen
+const MAX_ERROR_LINES: usize = 12;
+
+/// Tempo range Link accepts
+const MIN_BPM: f64 = 20.0;
+const MAX_BPM: f64 = 999.0;
+
+/// How a file of the media folder plays, from its index in the grid and its path
+pub type SlotFor = Box<dyn Fn(usize, &Path) -> Slot>;
+
+/// What is on screen while the transport is stopped
+pub enum StoppedBehavior {
+    /// keep showing the last frame
+    Freeze,
+    /// show a still image until playback starts
+    Slate(image::RgbaImage),
+    Black,
+}
 
 /// App manages the application state and coordinates between different components
 pub struct App {
-    clip: Clip,
+    grid: Grid,
+    /// one per track of the grid
+    layers: Vec<Layer>,
+    transport: Box<dyn Transport>,
+    loader: Loader,
+    /// clips opened in the background, keyed by file index
+    preloaded: HashMap<usize, Clip>,
+    /// files the loader is still working on
+    in_flight: HashSet<PathBuf>,
+    /// files in flight that were written over since they were requested
+    stale_loads: HashSet<PathBuf>,
     pub state: State<'static>,
-    texture_initialized: bool,
+    /// 0.0 shows only tracks on side A, 1.0 only those on side B
+    crossfader: f32,
     frame_limiter: FrameLimiter,
-    files: Vec<PathBuf>,
-    current_file_index: usize,
+    /// scene the arrow keys move from, the one launched last
+    selected_scene: usize,
+    /// track the number and loop keys act on
+    selected_track: usize,
+    /// effect toggled last, whose parameter MIDI learn maps
+    selected_effect: usize,
+    stopped_behavior: StoppedBehavior,
+    tap_tempo: TapTempo,
+    /// transport state seen on the previous frame
+    was_playing: bool,
+    /// session beat playback last started from, which clips launched without quantization follow
+    start_beat: f64,
+    /// remote control over OSC, if enabled
+    osc: Option<OscServer>,
+    /// MIDI controller mapped to actions, if enabled
+    midi: Option<MidiControlSurface>,
+    /// folder of user effects, if one was given
+    shader_watcher: Option<FolderWatcher>,
+    /// media folder being played, if watched
+    playlist_watcher: Option<PlaylistWatcher>,
+    /// how files turning up in the media folder play
+    slot_for: Option<SlotFor>,
+    /// files of the media folder as last scanned
+    playlist: Vec<PathBuf>,
     last_mouse_activity: Instant,
     cursor_hidden: bool,
+    /// how long the mouse has to be still before the cursor is hidden
+    cursor_timeout: Duration,
+}
+
+/// A track of the grid as it plays
+struct Layer {
+    clip: Option<Clip>,
+    /// scene whose slot is playing, `None` while the track is stopped
+    scene: Option<usize>,
+    /// launch waiting for its beat or for its clip to load
+    pending: Option<Launch>,
+    /// session beat the clip restarted from
+    start_beat: f64,
+    /// clip being transitioned away from
+    outgoing: Option<Outgoing>,
+    /// the playing or pending file was written over and is reopened when the pending launch comes
+    reload: bool,
+}
+
+impl Layer {
+    fn new() -> Self {
+        Self { clip: None, scene: None, pending: None, start_beat: 0.0, outgoing: None, reload: false }
+    }
+
+    fn is_armed(&self) -> bool {
+        self.pending.is_some_and(|launch| launch.beat.is_some())
+    }
+}
+
+/// A clip still playing under the one that replaced it, until the transition is over
+struct Outgoing {
+    clip: Clip,
+    loop_mode: LoopMode,
+    /// session beat the clip restarted from
+    start_beat: f64,
+    kind: TransitionKind,
+    /// session beats the transition runs between
+    from_beat: f64,
+    to_beat: f64,
+}
+
+#[derive(Clone, Copy)]
+struct Launch {
+    /// scene whose slot to play, `None` to stop the track
+    scene: Option<usize>,
+    /// session beat to launch on, `None` for as soon as the clip is ready
+    beat: Option<f64>,
 }
 
 /// Helper struct for frame rate limiting
@@ -51,10 +163,43 @@ impl FrameLimiter {
     }
 }
 
+/// Turns key taps into a tempo from the average interval between them
+struct TapTempo {
+    taps: Vec<Instant>,
+}
+
+impl TapTempo {
+    /// A pause this long starts a new series of taps
+    const RESET_AFTER: Duration = Duration::from_secs(2);
+    const MAX_TAPS: usize = 8;
+
+    fn new() -> Self {
+        Self { taps: Vec::new() }
+    }
+
+    /// Register a tap, returning the tempo once there are at least two
+    fn tap(&mut self) -> Option<f64> {
+        let now = Instant::now();
+        if self.taps.last().is_some_and(|last| now.duration_since(*last) > Self::RESET_AFTER) {
+            self.taps.clear();
+        }
+        self.taps.push(now);
+        if self.taps.len() > Self::MAX_TAPS {
+            self.taps.remove(0);
+        }
+        if self.taps.len() < 2 {
+            return None;
+        }
+
+        let span = now.duration_since(self.taps[0]).as_secs_f64();
+        Some(60.0 * (self.taps.len() - 1) as f64 / span)
+    }
+}
+
 impl App {
-    /// Create a new App instance with the given components
-    pub async fn new(window: &'static Window, clip: Clip, files: Vec<PathBuf>, current_file_index: usize) -> Self {
-        let state = State::new(window).await;
+    /// Create a new App instance with the given components, starting on `first_scene`
+    pub async fn new(window: &'static Window, loader: Loader, transport: Box<dyn Transport>, grid: Grid, first_scene: usize) -> Self {
+        let state = State::new(window, grid.tracks.len()).await;
         let frame_limiter = FrameLimiter::new(60); // 60 FPS target
         
         log::info!("Starting render loop with {} FPS target", frame_limiter.target_fps);
@@ -62,68 +207,843 @@ impl App {
         // Request initial redraw
         state.window().request_redraw();
         
-        Self {
-            clip,
+        let mut app = Self {
+            layers: (0..grid.tracks.len()).map(|_| Layer::new()).collect(),
+            grid,
+            transport,
+            loader,
+            preloaded: HashMap::new(),
+            in_flight: HashSet::new(),
+            stale_loads: HashSet::new(),
             state,
-            texture_initialized: false,
+            crossfader: 0.5,
             frame_limiter,
-            files,
-            current_file_index,
+            selected_scene: first_scene,
+            selected_track: 0,
+            selected_effect: 0,
+            stopped_behavior: StoppedBehavior::Freeze,
+            tap_tempo: TapTempo::new(),
+            was_playing: true,
+            start_beat: 0.0,
+            osc: None,
+            midi: None,
+            shader_watcher: None,
+            playlist_watcher: None,
+            slot_for: None,
+            playlist: Vec::new(),
             last_mouse_activity: Instant::now(),
             cursor_hidden: false,
+            cursor_timeout: Duration::from_secs(1),
+        };
+        for track in 0..app.layers.len() {
+            app.update_mix(track);
+        }
+        // The first scene starts straight away rather than on the next bar
+        for track in 0..app.layers.len() {
+            if app.grid.slot(track, first_scene).is_some() {
+                app.queue_launch(track, Launch { scene: Some(first_scene), beat: None });
+            }
+        }
+        app.preload_neighbours();
+        app
+    }
+
+    pub fn set_target_fps(&mut self, fps: u32) {
+        self.frame_limiter = FrameLimiter::new(fps);
+        log::info!("Render loop now targets {} FPS", fps);
+    }
+
+    pub fn set_cursor_timeout(&mut self, timeout: Duration) {
+        self.cursor_timeout = timeout;
+    }
+
+    pub fn set_stopped_behavior(&mut self, behavior: StoppedBehavior) {
+        self.stopped_behavior = behavior;
+    }
+
+    pub fn set_osc_server(&mut self, osc: Option<OscServer>) {
+        self.osc = osc;
+    }
+
+    pub fn set_midi_control(&mut self, midi: Option<MidiControlSurface>) {
+        self.midi = midi;
+    }
+
+    /// Add every `.wgsl` and ISF `.fs` file in `folder` to the effect chain, named after the
+    /// file, and rebuild them whenever they change
+    pub fn load_shader_folder(&mut self, folder: &Path) -> std::io::Result<()> {
+        for path in shader_files(folder)? {
+            self.state.load_shader(&path);
+        }
+        self.show_shader_errors();
+        self.shader_watcher = Some(FolderWatcher::spawn(vec![folder.to_path_buf()], is_shader));
+        Ok(())
+    }
+
+    /// Rebuild user effects edited since the last frame
+    fn poll_shaders(&mut self) {
+        let mut changed = false;
+        while let Some(event) = self.shader_watcher.as_ref().and_then(FolderWatcher::try_recv) {
+            match event {
+                FileEvent::Changed(path) => {
+                    self.state.load_shader(&path);
+                    changed = true;
+                }
+                FileEvent::Removed(path) => {
+                    self.state.unload_shader(&path);
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            self.show_shader_errors();
+            self.report_state();
+        }
+    }
+
+    /// Follow files being added to, removed from and written over in the media folder the
+    /// grid was made from, `slot_for` saying how new files play
+    pub fn watch_media_folder(&mut self, folder: &Path, filter: Filter, slot_for: SlotFor) {
+        self.playlist = self.grid.files.clone();
+        self.slot_for = Some(slot_for);
+        self.playlist_watcher = Some(PlaylistWatcher::spawn(folder.to_path_buf(), filter));
+    }
+
+    /// Take in the media folder as scanned since the last frame
+    fn poll_playlist(&mut self) {
+        while let Some(Playlist { files, replaced }) = self.playlist_watcher.as_ref().and_then(PlaylistWatcher::try_recv) {
+            self.playlist = files;
+            self.update_files();
+            for path in &replaced {
+                self.reload_file(path);
+            }
+            self.preload_neighbours();
+            self.update_title();
+            self.report_state();
+        }
+    }
+
+    /// Make the grid a scene for each file of the playlist, plus the files still playing,
+    /// which go once they stop. Files keep how they play and what plays carries on.
+    fn update_files(&mut self) {
+        if self.slot_for.is_none() {
+            return;
+        }
+        let mut files = self.playlist.clone();
+        for track in 0..self.layers.len() {
+            if let Some(file) = self.playing_file(track) {
+                files.push(self.grid.files[file].clone());
+            }
+        }
+        files.sort();
+        files.dedup();
+        if files == self.grid.files {
+            return;
+        }
+        if files.is_empty() {
+            log::warn!("The media folder has no video files left, keeping the last ones");
+            return;
+        }
+
+        let old_files = std::mem::take(&mut self.grid.files);
+        let mut old_scenes: Vec<Option<Scene>> = std::mem::take(&mut self.grid.scenes).into_iter().map(Some).collect();
+        let moved: Vec<Option<usize>> = old_files.iter().map(|path| files.iter().position(|file| file == path)).collect();
+        let remap = |old: usize| moved.get(old).copied().flatten();
+        for (old, path) in old_files.iter().enumerate() {
+            if remap(old).is_none() {
+                log::info!("Removed {}", path.display());
+            }
+        }
+
+        // A folder's grid has one scene for each file, in the same order
+        let slot_for = self.slot_for.as_ref().unwrap();
+        for (file, path) in files.iter().enumerate() {
+            let scene = match old_files.iter().position(|old| old == path).and_then(|old| old_scenes[old].take()) {
+                Some(mut scene) => {
+                    for slot in scene.slots.iter_mut().flatten() {
+                        slot.file = file;
+                    }
+                    scene
+                }
+                None => {
+                    log::info!("Added {}", path.display());
+                    Scene {
+                        name: file_name(path).to_string(),
+                        slots: vec![Some(slot_for(file, path))],
+                        quantum: None,
+                        effects: None,
+                    }
+                }
+            };
+            self.grid.scenes.push(scene);
+        }
+
+        for layer in &mut self.layers {
+            layer.scene = layer.scene.and_then(remap);
+            layer.pending = layer.pending.and_then(|launch| match launch.scene {
+                Some(scene) => Some(Launch { scene: Some(remap(scene)?), ..launch }),
+                None => Some(launch),
+            });
+        }
+        // The selected scene's file gone, its place in the list stays selected
+        let selected = &old_files[self.selected_scene];
+        self.selected_scene = remap(self.selected_scene)
+            .unwrap_or_else(|| files.partition_point(|path| path < selected).min(files.len() - 1));
+        self.preloaded = std::mem::take(&mut self.preloaded)
+            .into_iter()
+            .filter_map(|(file, clip)| Some((remap(file)?, clip)))
+            .collect();
+        self.grid.files = files;
+        self.update_armed();
+    }
+
+    /// Reopen a file that was written over: as soon as it's wanted for launches still to
+    /// come, and on its next launch boundary where it is playing
+    fn reload_file(&mut self, path: &Path) {
+        let Some(file) = self.grid.files.iter().position(|known| known == path) else {
+            return;
+        };
+        log::info!("{} was replaced, reloading it", path.display());
+        self.preloaded.remove(&file);
+        if self.in_flight.contains(path) {
+            self.stale_loads.insert(path.to_path_buf());
+        }
+        for track in 0..self.layers.len() {
+            let pending = self.layers[track].pending.map(|launch| {
+                launch.scene.and_then(|scene| self.grid.slot(track, scene)).map(|slot| slot.file)
+            });
+            let playing = self.playing_file(track) == Some(file);
+            if playing || pending.is_some_and(|pending| pending == Some(file)) {
+                // A launch already waiting reopens the file when it comes
+                self.layers[track].reload = true;
+            }
+            if !playing || pending.is_some() {
+                continue;
+            }
+            let scene = self.layers[track].scene;
+            let Some(slot) = scene.and_then(|scene| self.grid.slot(track, scene)).copied() else {
+                continue;
+            };
+            let beat = self.next_launch_beat(slot.launch_quantize);
+            self.queue_launch(track, Launch { scene, beat });
+        }
+    }
+
+    /// Say on screen which effects didn't compile, or clear the message once they all do
+    fn show_shader_errors(&mut self) {
+        let mut lines = Vec::new();
+        for (name, error) in self.state.effects().errors() {
+            lines.push(format!("Effect {} failed to compile, passing the picture through:", name));
+            lines.extend(
+                error
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .take(MAX_ERROR_LINES)
+                    .map(|line| format!("  {}", line)),
+            );
+        }
+        if lines.is_empty() {
+            self.state.hide_message();
+        } else {
+            self.state.show_message(&lines);
+        }
+    }
+
+    /// Use the quantum and effects the selected scene sets, if any
+    pub fn apply_scene_settings(&mut self) {
+        let scene = &self.grid.scenes[self.selected_scene];
+        if let Some(quantum) = scene.quantum {
+            self.transport.set_quantum(quantum);
+            log::info!("Quantum: {}", quantum);
+        }
+        if let Some(effects) = scene.effects.clone() {
+            let names: Vec<String> = self.state.effects().names().map(str::to_string).collect();
+            for name in names {
+                self.state.effects_mut().set_enabled(&name, effects.contains(&name));
+            }
         }
     }
 
-    /// Handle left arrow press - load previous file
+    /// Handle left arrow press - launch the previous scene
     fn on_left_arrow(&mut self) {
-        if self.current_file_index > 0 {
-            self.load_file(self.current_file_index - 1);
+        if self.selected_scene > 0 {
+            self.launch_scene(self.selected_scene - 1);
         } else {
-            log::info!("Already at first file");
+            log::info!("Already at first scene");
         }
     }
 
-    /// Handle right arrow press - load next file
+    /// Handle right arrow press - launch the next scene
     fn on_right_arrow(&mut self) {
-        if self.current_file_index < self.files.len() - 1 {
-            self.load_file(self.current_file_index + 1);
+        if self.selected_scene < self.grid.scenes.len() - 1 {
+            self.launch_scene(self.selected_scene + 1);
         } else {
-            log::info!("Already at last file");
+            log::info!("Already at last scene");
+        }
+    }
+
+    /// Move the track the number keys launch clips on
+    fn select_track(&mut self, delta: isize) {
+        let last = self.layers.len() - 1;
+        self.selected_track = self.selected_track.saturating_add_signed(delta).min(last);
+        log::info!("Selected track {}: {}", self.selected_track + 1, self.grid.tracks[self.selected_track].name);
+        self.update_title();
+    }
+
+    /// Launch every track's slot in `scene`, stopping the tracks whose slot is empty
+    fn launch_scene(&mut self, scene: usize) {
+        if scene >= self.grid.scenes.len() {
+            log::error!("Scene index {} out of bounds (max: {})", scene, self.grid.scenes.len() - 1);
+            return;
+        }
+
+        log::info!("Launching scene {}/{}: {}", scene + 1, self.grid.scenes.len(), self.grid.scenes[scene].name);
+        self.selected_scene = scene;
+        // Before queueing, so clips launch on the scene's own bars
+        self.apply_scene_settings();
+        for track in 0..self.layers.len() {
+            let slot_scene = self.grid.slot(track, scene).map(|_| scene);
+            self.launch_clip(track, slot_scene);
+        }
+        self.preload_neighbours();
+    }
+
+    /// Queue the slot of `track` in `scene` to launch on its quantization boundary, or stop the
+    /// track for `None`. The current clip keeps playing until then, or until the new one is ready
+    /// if loading takes longer.
+    fn launch_clip(&mut self, track: usize, scene: Option<usize>) {
+        if track >= self.layers.len() {
+            log::error!("Track index {} out of bounds (max: {})", track, self.layers.len() - 1);
+            return;
+        }
+        let slot = match scene {
+            Some(scene) => match self.grid.slot(track, scene) {
+                Some(slot) => Some(*slot),
+                None => {
+                    log::warn!("Track {} has no clip in scene {}", track + 1, scene + 1);
+                    return;
+                }
+            },
+            None => None,
+        };
+        if slot.is_none() && self.layers[track].scene.is_none() {
+            // Already stopped, just forget anything that was about to launch
+            self.layers[track].pending = None;
+            self.update_armed();
+            return;
+        }
+
+        let quantize = slot.map_or(self.grid.launch_quantize, |slot| slot.launch_quantize);
+        let beat = self.next_launch_beat(quantize);
+        if let Some(slot) = slot {
+            if self.playing_file(track) != Some(slot.file) && !self.preloaded.contains_key(&slot.file) {
+                log::info!("Loading file {}", self.grid.files[slot.file].display());
+                self.request_preload(slot.file);
+            }
+        }
+        if let Some(beat) = beat {
+            log::info!("Track {} armed to launch on beat {}", track + 1, beat);
+        }
+        self.queue_launch(track, Launch { scene, beat });
+    }
+
+    fn queue_launch(&mut self, track: usize, launch: Launch) {
+        self.layers[track].pending = Some(launch);
+        self.update_armed();
+    }
+
+    /// Beat a launch quantized to `quantize` made now would happen on, `None` for straight away
+    fn next_launch_beat(&mut self, quantize: LaunchQuantize) -> Option<f64> {
+        let transport = self.transport.update_phase_and_beat();
+        // Nothing to wait for when stopped, and timecode decides where clips are by itself
+        if !transport.is_playing || transport.timecode.is_some() {
+            return None;
+        }
+        quantize.next_launch(transport.beat, transport.quantum)
+    }
+
+    /// File of the clip playing on `track`, if any
+    fn playing_file(&self, track: usize) -> Option<usize> {
+        let scene = self.layers[track].scene?;
+        self.grid.slot(track, scene).map(|slot| slot.file)
+    }
+
+    /// Carry out launches whose beat has come and whose clip has loaded. Clips armed on a beat
+    /// start from their first frame there, the rest carry on from the current position.
+    fn launch_pending(&mut self, transport: &TransportSnapshot) {
+        let mut launched = false;
+        for track in 0..self.layers.len() {
+            let Some(launch) = self.layers[track].pending else {
+                continue;
+            };
+            if launch.beat.is_some_and(|beat| transport.is_playing && transport.beat < beat) {
+                continue;
+            }
+
+            let Some(slot) = launch.scene.and_then(|scene| self.grid.slot(track, scene)).copied() else {
+                let layer = &mut self.layers[track];
+                layer.clip = None;
+                layer.scene = None;
+                layer.pending = None;
+                layer.outgoing = None;
+                layer.reload = false;
+                self.state.set_layer_visible(track, false);
+                self.state.set_layer_transition(track, None);
+                log::info!("Stopped track {}", track + 1);
+                launched = true;
+                continue;
+            };
+
+            let file = slot.file;
+            if self.playing_file(track) == Some(file) && !self.layers[track].reload {
+                // Launching the clip that is already playing restarts it
+                self.layers[track].start_beat = launch.beat.unwrap_or(transport.beat);
+            } else {
+                let Some(clip) = self.preloaded.remove(&file) else {
+                    self.request_preload(file);
+                    continue;
+                };
+                let was_stopped = self.layers[track].clip.is_none();
+                let previous_loop_mode = self.layers[track]
+                    .scene
+                    .and_then(|scene| self.grid.slot(track, scene))
+                    .map(|slot| slot.loop_mode);
+                let layer = &mut self.layers[track];
+                layer.reload = false;
+                let previous = layer.clip.replace(clip);
+                let previous_start_beat = layer.start_beat;
+                layer.start_beat = match launch.beat {
+                    Some(beat) => beat,
+                    None if was_stopped => self.start_beat,
+                    None => layer.start_beat,
+                };
+
+                // Timecode jumps around, and a stopped transport never gets through a transition
+                let transition = slot.transition;
+                let timed = transport.is_playing && transport.timecode.is_none();
+                layer.outgoing = match (previous, previous_loop_mode) {
+                    (Some(previous), Some(loop_mode)) if timed && !transition.is_cut() => {
+                        let from_beat = launch.beat.unwrap_or(transport.beat);
+                        Some(Outgoing {
+                            clip: previous,
+                            loop_mode,
+                            start_beat: previous_start_beat,
+                            kind: transition.kind,
+                            from_beat,
+                            to_beat: from_beat + transition.length.beats(transport.quantum),
+                        })
+                    }
+                    _ => None,
+                };
+                match &layer.outgoing {
+                    Some(outgoing) => self.state.begin_layer_transition(track, outgoing.kind),
+                    None => self.state.set_layer_transition(track, None),
+                }
+                self.state.set_layer_visible(track, true);
+                log::info!("Successfully loaded file: {}", self.grid.files[file].display());
+            }
+            self.layers[track].scene = launch.scene;
+            self.layers[track].pending = None;
+            launched = true;
+        }
+
+        if launched {
+            // Removed files that were still playing may have stopped
+            self.update_files();
+            self.update_armed();
+            self.preload_neighbours();
+            self.report_state();
+        }
+    }
+
+    /// Frame the picture while any launch is waiting for its beat
+    fn update_armed(&mut self) {
+        let armed = self.layers.iter().any(Layer::is_armed);
+        self.state.set_armed(armed);
+        self.update_title();
+    }
+
+    /// Show the selected scene and any modes that change what input does in the window title
+    fn update_title(&self) {
+        let mut tags = String::new();
+        if self.layers.len() > 1 {
+            tags.push_str(&format!(" [{}]", self.grid.tracks[self.selected_track].name));
         }
+        if self.layers.iter().any(Layer::is_armed) {
+            tags.push_str(" [ARMED]");
+        }
+        if self.midi.as_ref().is_some_and(MidiControlSurface::is_learning) {
+            tags.push_str(" [LEARN]");
+        }
+        let scene = &self.grid.scenes[self.selected_scene].name;
+        self.state.window().set_title(&format!("Voop Video Player - {}{}", scene, tags));
     }
 
-    /// Load a file by index
-    fn load_file(&mut self, index: usize) {
-        if index >= self.files.len() {
-            log::error!("File index {} out of bounds (max: {})", index, self.files.len() - 1);
+    fn request_preload(&mut self, file: usize) {
+        if self.preloaded.contains_key(&file) || !self.in_flight.insert(self.grid.files[file].clone()) {
             return;
         }
+        self.loader.request(file, self.grid.files[file].clone());
+    }
+
+    /// Files worth having open: those of the scenes either side of the selected one and of
+    /// launches still waiting, unless their track is already playing them
+    fn wanted_files(&self) -> HashSet<usize> {
+        let scene = self.selected_scene;
+        let mut files: HashSet<usize> = [scene.checked_sub(1), Some(scene + 1)]
+            .into_iter()
+            .flatten()
+            .flat_map(|scene| self.grid.scene_files(scene))
+            .collect();
+        for (track, layer) in self.layers.iter().enumerate() {
+            if let Some(file) = self.playing_file(track).filter(|_| !layer.reload) {
+                files.remove(&file);
+            }
+        }
+        for (track, layer) in self.layers.iter().enumerate() {
+            let pending = layer.pending.and_then(|launch| self.grid.slot(track, launch.scene?));
+            if let Some(slot) = pending.filter(|slot| layer.reload || self.playing_file(track) != Some(slot.file)) {
+                files.insert(slot.file);
+            }
+        }
+        files
+    }
+
+    /// Open the neighbouring scenes' files in the background and forget any others
+    fn preload_neighbours(&mut self) {
+        let wanted = self.wanted_files();
+        self.preloaded.retain(|file, _| wanted.contains(file));
+        for file in wanted {
+            self.request_preload(file);
+        }
+    }
+
+    /// Collect clips finished by the loader, keeping the ones still wanted
+    fn poll_loader(&mut self) {
+        while let Some(Loaded { path, clip }) = self.loader.try_recv() {
+            self.in_flight.remove(&path);
+            // The media folder may have changed since the file was requested
+            let Some(index) = self.grid.files.iter().position(|file| *file == path) else {
+                continue;
+            };
+            if self.stale_loads.remove(&path) {
+                if self.wanted_files().contains(&index) {
+                    self.request_preload(index);
+                }
+                continue;
+            }
+            match clip {
+                Ok(clip) => {
+                    if self.wanted_files().contains(&index) {
+                        self.preloaded.insert(index, clip);
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to load file {}: {}", path.display(), e);
+                    for track in 0..self.layers.len() {
+                        let launch = self.layers[track].pending.and_then(|launch| launch.scene);
+                        if launch.and_then(|scene| self.grid.slot(track, scene)).is_some_and(|slot| slot.file == index) {
+                            self.layers[track].pending = None;
+                        }
+                    }
+                    self.update_armed();
+                }
+            }
+        }
+    }
+
+    /// Carry out actions sent by remote controllers
+    fn poll_remote(&mut self) {
+        while let Some(action) = self.osc.as_ref().and_then(OscServer::try_recv) {
+            self.apply(action);
+        }
+        while let Some(action) = self.midi.as_mut().and_then(MidiControlSurface::try_recv) {
+            self.apply(action);
+        }
+    }
+
+    fn toggle_midi_learn(&mut self) {
+        match &mut self.midi {
+            Some(midi) => midi.toggle_learning(),
+            None => {
+                log::warn!("MIDI learn needs a controller, start with --midi-control NAME");
+                return;
+            }
+        }
+        self.update_title();
+    }
 
-        let file_path = &self.files[index];
-        log::info!("Loading file {}/{}: {}", index + 1, self.files.len(), file_path.display());
+    /// While learning, keys pick what the control last touched should do.
+    /// Returns whether the key was used.
+    fn on_learn_key(&mut self, key: KeyCode) -> bool {
+        let Some(midi) = self.midi.as_mut().filter(|midi| midi.is_learning()) else {
+            return false;
+        };
+        let target = match key {
+            KeyCode::ArrowLeft => MidiTarget::PreviousScene,
+            KeyCode::ArrowRight => MidiTarget::NextScene,
+            KeyCode::Enter => MidiTarget::LaunchScene { scene: self.selected_scene },
+            KeyCode::Delete | KeyCode::Backspace => MidiTarget::StopTrack { track: self.selected_track },
+            KeyCode::KeyF => MidiTarget::ToggleFullscreen,
+            KeyCode::KeyO => MidiTarget::Opacity,
+            KeyCode::KeyV => MidiTarget::TrackOpacity { track: self.selected_track },
+            KeyCode::KeyX => MidiTarget::Crossfader,
+            KeyCode::KeyB => MidiTarget::BlendMode {
+                track: self.selected_track,
+                mode: self.grid.tracks[self.selected_track].blend,
+            },
+            KeyCode::KeyP => {
+                let effect = self.state.effects().names().nth(self.selected_effect).unwrap_or_default().to_string();
+                let Some(param) = self.state.effects().params(&effect).first() else {
+                    log::warn!("MIDI learn: effect {} has no parameters", effect);
+                    return true;
+                };
+                MidiTarget::EffectParam { param: param.name.clone(), effect }
+            }
+            _ => match (digit(key), effect_key(key)) {
+                (Some(scene), _) => MidiTarget::LaunchClip { track: self.selected_track, scene },
+                (_, Some(effect)) => match self.state.effects().names().nth(effect) {
+                    Some(effect) => MidiTarget::ToggleEffect { effect: effect.to_string() },
+                    None => return false,
+                },
+                _ => return false,
+            },
+        };
+        midi.learn(target);
+        true
+    }
 
-        match Clip::new(file_path.to_str().unwrap()) {
-            Ok(mut new_clip) => {
-                if let Err(e) = new_clip.cache_all_frames() {
-                    log::error!("Failed to cache frames for {}: {}", file_path.display(), e);
+    fn apply(&mut self, action: Action) {
+        log::debug!("Remote action: {:?}", action);
+        match action {
+            Action::NextScene => self.on_right_arrow(),
+            Action::PreviousScene => self.on_left_arrow(),
+            Action::LaunchScene(scene) => self.launch_scene(scene),
+            Action::LaunchClip { track, scene } => self.launch_clip(track, Some(scene)),
+            Action::StopTrack(track) => self.launch_clip(track, None),
+            Action::ToggleFullscreen => {
+                self.state.toggle_fullscreen();
+                self.report_state();
+            }
+            Action::SetFullscreen(fullscreen) => {
+                self.state.set_fullscreen(fullscreen);
+                self.report_state();
+            }
+            Action::SetOpacity(opacity) => {
+                self.state.set_opacity(opacity);
+                self.report_state();
+            }
+            Action::SetTrackOpacity { track, opacity } => {
+                let Some(settings) = self.grid.tracks.get_mut(track) else {
+                    log::error!("Track index {} out of bounds (max: {})", track, self.layers.len() - 1);
                     return;
+                };
+                settings.opacity = opacity.clamp(0.0, 1.0);
+                self.update_mix(track);
+                self.report_state();
+            }
+            Action::SetBlendMode { track, mode } => self.set_blend_mode(track, mode),
+            Action::SetCrossfader(position) => {
+                self.crossfader = position.clamp(0.0, 1.0);
+                for track in 0..self.layers.len() {
+                    self.update_mix(track);
                 }
-                
-                self.clip = new_clip;
-                self.current_file_index = index;
-                self.texture_initialized = false; // Reset texture for new video dimensions
-                
-                // Update window title
-                let filename = file_path.file_name()
-                    .and_then(|name| name.to_str())
-                    .unwrap_or("Unknown");
-                self.state.window().set_title(&format!("Voop Video Player - {}", filename));
-                
-                log::info!("Successfully loaded file: {}", filename);
+                self.report_state();
+            }
+            Action::ToggleEffect(effect) => {
+                let enabled = !self.state.effects().is_enabled(&effect);
+                self.set_effect(&effect, enabled);
             }
-            Err(e) => {
-                log::error!("Failed to load file {}: {}", file_path.display(), e);
+            Action::SetEffect { effect, enabled } => self.set_effect(&effect, enabled),
+            Action::SetEffectParam { effect, param, value } => {
+                if self.state.effects_mut().set_param(&effect, &param, value) {
+                    self.report_state();
+                } else {
+                    log::warn!("Effect {} has no parameter {}", effect, param);
+                }
             }
+            Action::ReportState => self.report_state(),
+        }
+    }
+
+    fn set_effect(&mut self, effect: &str, enabled: bool) {
+        if !self.state.effects_mut().set_enabled(effect, enabled) {
+            log::warn!("No effect called {}", effect);
+            return;
+        }
+        if let Some(index) = self.state.effects().names().position(|name| name == effect) {
+            self.selected_effect = index;
+        }
+        self.report_state();
+    }
+
+    /// Toggle the effect a function key stands for
+    fn on_effect_key(&mut self, index: usize) {
+        let Some(effect) = self.state.effects().names().nth(index).map(str::to_string) else {
+            return;
+        };
+        let enabled = !self.state.effects().is_enabled(&effect);
+        self.set_effect(&effect, enabled);
+    }
+
+    fn set_blend_mode(&mut self, track: usize, mode: BlendMode) {
+        let Some(settings) = self.grid.tracks.get_mut(track) else {
+            log::error!("Track index {} out of bounds (max: {})", track, self.layers.len() - 1);
+            return;
+        };
+        settings.blend = mode;
+        log::info!("Track {} blends with {}", track + 1, mode.name());
+        self.update_mix(track);
+        self.report_state();
+    }
+
+    /// Give the layer of `track` its blend mode, and its opacity after the crossfader
+    fn update_mix(&mut self, track: usize) {
+        let settings = &self.grid.tracks[track];
+        let gain = settings.crossfade.map_or(1.0, |side| side.gain(self.crossfader));
+        self.state.set_layer_opacity(track, settings.opacity * gain);
+        self.state.set_layer_blend(track, settings.blend);
+    }
+
+    /// Tell remote controllers what is playing and how it is shown
+    fn report_state(&self) {
+        let Some(osc) = &self.osc else {
+            return;
+        };
+        let mut messages = vec![
+            OscMessage::new("/voop/scene/index", vec![OscArg::Int(self.selected_scene as i32)]),
+            OscMessage::new("/voop/scene/count", vec![OscArg::Int(self.grid.scenes.len() as i32)]),
+            OscMessage::new("/voop/scene/name", vec![OscArg::String(self.grid.scenes[self.selected_scene].name.clone())]),
+            OscMessage::new("/voop/track/count", vec![OscArg::Int(self.layers.len() as i32)]),
+        ];
+        for (track, layer) in self.layers.iter().enumerate() {
+            let scene = layer.scene.map_or(-1, |scene| scene as i32);
+            let settings = &self.grid.tracks[track];
+            messages.extend([
+                OscMessage::new("/voop/track/clip", vec![OscArg::Int(track as i32), OscArg::Int(scene)]),
+                OscMessage::new("/voop/track/opacity", vec![OscArg::Int(track as i32), OscArg::Float(settings.opacity)]),
+                OscMessage::new("/voop/track/blend", vec![OscArg::Int(track as i32), OscArg::String(settings.blend.name().to_string())]),
+            ]);
+        }
+        messages.push(OscMessage::new("/voop/crossfader", vec![OscArg::Float(self.crossfader)]));
+        let effects = self.state.effects();
+        for effect in effects.names() {
+            let address = format!("/voop/effect/{}", effect);
+            messages.push(OscMessage::new(&address, vec![OscArg::Int(effects.is_enabled(effect) as i32)]));
+            for param in effects.params(effect) {
+                let address = format!("{}/{}", address, param.name);
+                messages.push(OscMessage::new(&address, vec![OscArg::Float(param.value)]));
+            }
+        }
+        messages.push(OscMessage::new("/voop/opacity", vec![OscArg::Float(self.state.opacity())]));
+        messages.push(OscMessage::new("/voop/fullscreen", vec![OscArg::Int(self.state.is_fullscreen() as i32)]));
+        osc.report(&messages);
+    }
+
+    /// Settings of the clip playing on the selected track
+    fn selected_slot_mut(&mut self) -> Option<&mut Slot> {
+        let track = self.selected_track;
+        let Some(scene) = self.layers[track].scene else {
+            log::info!("Track {} isn't playing", track + 1);
+            return None;
+        };
+        self.grid.slot_mut(track, scene)
+    }
+
+    /// Change how many bars the selected track's clip loops over
+    fn scale_loop_bars(&mut self, factor: f64) {
+        let Some(slot) = self.selected_slot_mut() else {
+            return;
+        };
+        let mode = &mut slot.loop_mode;
+        let bars = ((mode.bars() as f64 * factor).round() as u32).max(1);
+        *mode = mode.with_bars(bars);
+        log::info!("Loop mode: {:?}", mode);
+    }
+
+    /// Switch the selected track's clip between stretching over its bars and native speed
+    fn toggle_native_speed(&mut self) {
+        let Some(slot) = self.selected_slot_mut() else {
+            return;
+        };
+        let mode = &mut slot.loop_mode;
+        *mode = match *mode {
+            LoopMode::Bars(bars) => LoopMode::Native { resync_bars: bars },
+            LoopMode::Native { resync_bars } => LoopMode::Bars(resync_bars),
+            LoopMode::Timecode { .. } => return,
+        };
+        log::info!("Loop mode: {:?}", mode);
+    }
+
+    /// Propose a new tempo to the transport
+    fn set_tempo(&mut self, bpm: f64) {
+        let bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+        self.transport.propose_tempo(bpm);
+        log::info!("Tempo: {:.2} BPM", bpm);
+    }
+
+    fn nudge_tempo(&mut self, delta: f64) {
+        let tempo = self.transport.update_phase_and_beat().tempo;
+        self.set_tempo(tempo + delta);
+    }
+
+    fn on_tap(&mut self) {
+        if let Some(bpm) = self.tap_tempo.tap() {
+            self.set_tempo(bpm);
+        }
+    }
+
+    /// Add or remove beats from the bar
+    fn nudge_quantum(&mut self, delta: f64) {
+        let quantum = (self.transport.update_phase_and_beat().quantum + delta).max(1.0);
+        self.transport.set_quantum(quantum);
+        log::info!("Quantum: {}", quantum);
+    }
+
+    /// Make this instant a downbeat, for when the band counted in somewhere else
+    fn realign_downbeat(&mut self) {
+        let now = self.transport.update_phase_and_beat().timestamp;
+        self.transport.request_beat_at_time(0.0, now);
+        self.restart_layers_at(0.0);
+        log::info!("Requested downbeat now");
+    }
+
+    /// Restart every clip from its first frame at `beat`, finishing any transitions
+    fn restart_layers_at(&mut self, beat: f64) {
+        self.start_beat = beat;
+        for (track, layer) in self.layers.iter_mut().enumerate() {
+            layer.start_beat = beat;
+            if layer.outgoing.take().is_some() {
+                self.state.set_layer_transition(track, None);
+            }
+        }
+    }
+
+    /// React to the transport starting or stopping since the last frame
+    fn follow_start_stop(&mut self, transport: &TransportSnapshot) {
+        if transport.is_playing == self.was_playing {
+            return;
+        }
+        self.was_playing = transport.is_playing;
+
+        if transport.is_playing {
+            // Timecode says exactly where to be, so there is no downbeat to wait for
+            let start_beat = match transport.timecode {
+                Some(_) => transport.beat,
+                None => next_downbeat(transport),
+            };
+            self.restart_layers_at(start_beat);
+            log::info!("Transport started, restarting clips at beat {:.2}", start_beat);
+            self.state.set_blackout(false);
+            self.state.hide_slate();
+            return;
+        }
+
+        log::info!("Transport stopped");
+        match &self.stopped_behavior {
+            StoppedBehavior::Freeze => {}
+            StoppedBehavior::Slate(slate) => self.state.show_slate(slate),
+            StoppedBehavior::Black => self.state.set_blackout(true),
         }
     }
 
@@ -143,6 +1063,21 @@ impl App {
             _ => {}
         }
 
+        // While learning MIDI, some keys assign actions instead of doing them
+        if let WindowEvent::KeyboardInput {
+            event: KeyEvent {
+                physical_key: PhysicalKey::Code(key),
+                state: winit::event::ElementState::Pressed,
+                ..
+            },
+            ..
+        } = event
+        {
+            if self.on_learn_key(*key) {
+                return;
+            }
+        }
+
         // First check for app-level keys before passing to state
         match event {
             WindowEvent::KeyboardInput {
@@ -167,6 +1102,188 @@ impl App {
                 self.on_right_arrow();
                 return; // Don't pass to state
             }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::ArrowUp),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.select_track(1);
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::ArrowDown),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.select_track(-1);
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(key),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } if digit(*key).is_some() => {
+                self.launch_clip(self.selected_track, digit(*key));
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(key),
+                    state: winit::event::ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } if effect_key(*key).is_some() => {
+                if let Some(index) = effect_key(*key) {
+                    self.on_effect_key(index);
+                }
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::Delete | KeyCode::Backspace),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.launch_clip(self.selected_track, None);
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::BracketLeft),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.scale_loop_bars(0.5);
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::BracketRight),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.scale_loop_bars(2.0);
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyB),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                let track = self.selected_track;
+                self.set_blend_mode(track, self.grid.tracks[track].blend.next());
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyN),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.toggle_native_speed();
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyT),
+                    state: winit::event::ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.on_tap();
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::Equal | KeyCode::NumpadAdd),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.nudge_tempo(1.0);
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::Minus | KeyCode::NumpadSubtract),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.nudge_tempo(-1.0);
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::Comma),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.nudge_quantum(-1.0);
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::Period),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.nudge_quantum(1.0);
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyR),
+                    state: winit::event::ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                self.realign_downbeat();
+                return;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyL),
+                    state: winit::event::ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.toggle_midi_learn();
+                return;
+            }
             _ => {}
         }
 
@@ -212,9 +1329,7 @@ impl App {
 
     /// Check if cursor should be hidden based on inactivity
     fn update_cursor_visibility(&mut self) {
-        const CURSOR_HIDE_TIMEOUT: Duration = Duration::from_secs(1);
-        
-        if !self.cursor_hidden && self.last_mouse_activity.elapsed() >= CURSOR_HIDE_TIMEOUT {
+        if !self.cursor_hidden && self.last_mouse_activity.elapsed() >= self.cursor_timeout {
             self.hide_cursor();
         }
     }
@@ -234,20 +1349,23 @@ impl App {
         if self.frame_limiter.should_render() {
             // Update cursor visibility based on mouse inactivity
             self.update_cursor_visibility();
+
+            // Collect clips the loader has finished
+            self.poll_loader();
+            self.poll_remote();
+            self.poll_shaders();
+            self.poll_playlist();
             
-            // Update link timing
-            LINK.lock().unwrap().update_phase_and_beat();
-            
-            // Get current video frame
-            let frame = self.clip.play_video_at_position(LINK.lock().unwrap().phase as f32);
+            // Update transport timing
+            let transport = self.transport.update_phase_and_beat();
+            self.state.effects_mut().set_transport(&transport);
+            self.follow_start_stop(&transport);
+            self.launch_pending(&transport);
 
-            // Initialize texture on first frame
-            if !self.texture_initialized {
-                self.initialize_texture(&frame);
+            // While stopped the screen keeps whatever follow_start_stop left on it
+            if transport.is_playing {
+                self.update_layers(&transport);
             }
-
-            // Update rendering state with new frame
-            self.state.update_texture_with_frame(&frame);
             self.state.update();
             
             // Render frame and handle errors
@@ -260,13 +1378,41 @@ impl App {
         self.state.window().request_redraw();
     }
 
-    /// Initialize texture with video frame dimensions
-    fn initialize_texture(&mut self, frame: &ffmpeg_next::util::frame::Video) {
-        self.state.recreate_texture(
-            frame.width() as u32,
-            frame.height() as u32,
-        );
-        self.texture_initialized = true;
+    /// Give every playing track's layer its frame for this point in the transport,
+    /// and the outgoing clip's frame while it transitions
+    fn update_layers(&mut self, transport: &TransportSnapshot) {
+        for (track, layer) in self.layers.iter_mut().enumerate() {
+            if let Some(outgoing) = &mut layer.outgoing {
+                let progress = (transport.beat - outgoing.from_beat) / (outgoing.to_beat - outgoing.from_beat);
+                if progress >= 1.0 {
+                    layer.outgoing = None;
+                    self.state.set_layer_transition(track, None);
+                } else {
+                    let position = outgoing
+                        .loop_mode
+                        .position(&transport.relative_to(outgoing.start_beat), outgoing.clip.duration_secs());
+                    let frame = outgoing.clip.play_video_at_position(position);
+                    self.state.update_layer_outgoing_frame(track, &frame);
+                    self.state.set_layer_transition(track, Some((outgoing.kind, progress.max(0.0) as f32)));
+                }
+            }
+
+            let loop_mode = layer
+                .scene
+                .and_then(|scene| self.grid.slot(track, scene))
+                .map_or(LoopMode::Bars(1), |slot| slot.loop_mode);
+            let Some(clip) = layer.clip.as_mut() else {
+                continue;
+            };
+            // Hold the first frame until the beat the clip launched on
+            let position = if transport.beat < layer.start_beat {
+                0.0
+            } else {
+                loop_mode.position(&transport.relative_to(layer.start_beat), clip.duration_secs())
+            };
+            let frame = clip.play_video_at_position(position);
+            self.state.update_layer_frame(track, &frame);
+        }
     }
 
     /// Handle rendering errors
@@ -288,3 +1434,81 @@ impl App {
         }
     }
 }
+
+/// Scene index of a number key, `1` being the first
+fn digit(key: KeyCode) -> Option<usize> {
+    let digit = match key {
+        KeyCode::Digit1 => 1,
+        KeyCode::Digit2 => 2,
+        KeyCode::Digit3 => 3,
+        KeyCode::Digit4 => 4,
+        KeyCode::Digit5 => 5,
+        KeyCode::Digit6 => 6,
+        KeyCode::Digit7 => 7,
+        KeyCode::Digit8 => 8,
+        KeyCode::Digit9 => 9,
+        _ => return None,
+    };
+    Some(digit - 1)
+}
+
+/// The effect files in `folder`, in the order they are chained
+pub fn shader_files(folder: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<_> = std::fs::read_dir(folder)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_shader(path))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Whether a file in the shader folder is an effect, in WGSL or ISF
+fn is_shader(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "wgsl" || extension == "fs")
+}
+
+/// Effect index of a function key, `F1` being the first. F11 is left for fullscreen.
+fn effect_key(key: KeyCode) -> Option<usize> {
+    let index = match key {
+        KeyCode::F1 => 0,
+        KeyCode::F2 => 1,
+        KeyCode::F3 => 2,
+        KeyCode::F4 => 3,
+        KeyCode::F5 => 4,
+        KeyCode::F6 => 5,
+        KeyCode::F7 => 6,
+        KeyCode::F8 => 7,
+        _ => return None,
+    };
+    Some(index)
+}
+
+/// Beat of the bar playback starts on: the current one if it only just began, else the next
+fn next_downbeat(transport: &TransportSnapshot) -> f64 {
+    let bar_start = transport.bar as f64 * transport.quantum;
+    if transport.beat - bar_start < START_TOLERANCE_BEATS {
+        bar_start
+    } else {
+        bar_start + transport.quantum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use transport::manual::ManualClock;
+
+    #[test]
+    fn next_downbeat_allows_a_late_start() {
+        // 120 bpm in 4/4, so a beat every half second
+        let mut clock = ManualClock::new(120.0, 4.0);
+        assert_eq!(next_downbeat(&clock.update_phase_and_beat()), 0.0);
+        clock.advance_secs(2.05);
+        assert_eq!(next_downbeat(&clock.update_phase_and_beat()), 4.0);
+        clock.advance_secs(0.1);
+        assert_eq!(next_downbeat(&clock.update_phase_and_beat()), 8.0);
+        clock.advance_secs(1.85);
+        assert_eq!(next_downbeat(&clock.update_phase_and_beat()), 8.0);
+    }
+}
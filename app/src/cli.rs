@@ -0,0 +1,725 @@
+use crate::clip::{LaunchQuantize, LoopMode};
+use crate::config::Config;
+use crate::media::Filter;
+use crate::midi_map;
+use crate::transition::{Transition, TransitionKind, TransitionLength};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use transport::timecode::{FrameRate, Timecode};
+
+/// Frame cache budget when `--cache-mb` is not given
+const DEFAULT_CACHE_MB: usize = 2048;
+
+/// Tempo when neither `--bpm` nor the config file sets one
+const DEFAULT_BPM: f64 = 120.0;
+
+/// Beats per bar when neither `--quantum` nor the config file sets one
+const DEFAULT_QUANTUM: f64 = 4.0;
+
+/// Frame rate timecode offsets are written in when the config file doesn't set one
+const DEFAULT_TIMECODE_FPS: f64 = 25.0;
+
+/// Bars rendered when `--bars` is not given
+const DEFAULT_RENDER_BARS: u32 = 4;
+
+/// Encoder `transcode` uses when `--codec` is not given: ProRes 422, which every frame
+/// of can be decoded on its own, so seeking around a loop is cheap
+const DEFAULT_TRANSCODE_CODEC: &str = "prores_ks";
+
+/// Folder `transcode` writes to when `--output-dir` is not given
+const DEFAULT_TRANSCODE_DIR: &str = "transcoded";
+
+pub const USAGE: &str = "\
+Usage: app [play] [OPTIONS] [PATH | SHOW.toml]
+       app render --output FILE [--scene N] [--bars N] [--codec NAME] [OPTIONS] [PATH | SHOW.toml]
+       app probe FILE...
+       app transcode [--output-dir DIR] [--codec NAME] FILE...
+       app list-outputs
+
+Commands:
+  play          play a video file, a folder of them or a show file (the default)
+  render        render bars of a scene to a video file, without a window or a live transport
+  probe         print what voop makes of video files
+  transcode     re-encode video files to a codec that seeks quickly, ProRes 422 by default
+  list-outputs  print the monitors --monitor picks from
+
+Options:
+  --show FILE                 show file laying clips out in tracks and scenes
+  --config FILE               settings file, voop.toml if there is one
+  --fullscreen                start fullscreen
+  --monitor N                 go fullscreen on monitor N of list-outputs
+  --size WxH                  window size [1280x720], or picture size when rendering [1920x1080]
+  --fps N                     frames drawn per second [60], or rendered [30]
+  --cursor-timeout SECS       hide the cursor after the mouse is still this long [1]
+  --cache-mb N                frame cache budget in megabytes [2048]
+  --cache-all                 decode whole files into the cache up front
+  --include GLOB              only play files of the folder matching, repeatable
+  --exclude GLOB              leave out files of the folder matching, repeatable
+  --transport SOURCE          link, internal, midi, mtc or ltc [link]
+  --bpm N                     tempo proposed on start [120]
+  --quantum N                 beats per bar [4]
+  --midi-port NAME            input to read MIDI clock or MTC from
+  --ltc-input NAME            audio input to read LTC from
+  --ltc-wav FILE              WAV file to play LTC from
+  --loop-bars N               bars each clip loops over [1]
+  --native-speed              play clips at their own speed, resyncing every loop
+  --launch-quantize Q         none, beat, bar or a number of bars [bar]
+  --transition KIND           cut, crossfade, dip_to_black, wipe or luma_wipe [cut]
+  --transition-length LENGTH  `N beats` or `N bars`
+  --luma-matte IMAGE          greyscale image luma wipes follow
+  --shader-dir DIR            folder of WGSL and ISF effects
+  --effect NAME               turn an effect on, repeatable
+  --when-stopped WHAT         freeze, slate or black [freeze]
+  --slate IMAGE               holding slate shown while stopped
+  --osc-port N                UDP port to listen for OSC on
+  --midi-control NAME         MIDI controller to map to actions
+  --midi-map FILE             where MIDI mappings are kept
+  --log-level LEVEL           off, error, warn, info, debug or trace, overriding RUST_LOG
+  -h, --help                  print this
+";
+
+/// The command line, read by [`parse`]
+pub struct Cli {
+    /// how much to log, `RUST_LOG` decides if not set
+    pub log_level: Option<log::LevelFilter>,
+    pub command: Command,
+}
+
+pub enum Command {
+    Play(Options),
+    Render(Options, RenderOptions),
+    Probe(Vec<PathBuf>),
+    Transcode(TranscodeOptions),
+    ListOutputs,
+    Help,
+}
+
+/// Where musical time comes from
+pub enum TransportKind {
+    /// Ableton Link session on the local network
+    Link,
+    /// free-running clock, for rehearsing without a DAW
+    Internal,
+    /// MIDI clock from a hardware sequencer or DAW
+    Midi,
+    /// MIDI time code
+    Mtc,
+    /// SMPTE linear timecode on an audio input, or in a WAV file for rehearsing
+    Ltc,
+}
+
+impl TransportKind {
+    pub fn is_timecode(&self) -> bool {
+        matches!(self, TransportKind::Mtc | TransportKind::Ltc)
+    }
+}
+
+pub enum WhenStopped {
+    Freeze,
+    Slate,
+    Black,
+}
+
+/// Options of `play` and `render`
+pub struct Options {
+    /// video file or folder of videos to play when there is no show file
+    pub path: Option<String>,
+    /// show file laying clips out in tracks and scenes
+    pub show: Option<PathBuf>,
+    /// which files of the folder at `path` are played
+    pub media_filter: Filter,
+    /// start fullscreen
+    pub fullscreen: bool,
+    /// monitor to go fullscreen on, counting from 0 in the order list-outputs prints
+    pub monitor: Option<usize>,
+    /// window size, or picture size when rendering
+    pub size: Option<(u32, u32)>,
+    /// frames drawn, or rendered, per second
+    pub fps: Option<u32>,
+    /// how long the mouse has to be still before the cursor is hidden
+    pub cursor_timeout: Option<Duration>,
+    /// decode whole files into the frame cache up front instead of streaming
+    pub cache_all: bool,
+    /// byte budget of the frame cache shared by all clips, in megabytes
+    pub cache_mb: usize,
+    /// how clips loop against Link until changed from the keyboard
+    pub loop_mode: LoopMode,
+    pub transport: TransportKind,
+    /// tempo proposed on start
+    pub bpm: f64,
+    /// beats per bar
+    pub quantum: f64,
+    /// input port to read MIDI clock or MTC from, matched by name
+    pub midi_port: Option<String>,
+    /// audio input to read LTC from, matched by name
+    pub ltc_input: Option<String>,
+    /// WAV file to play LTC from instead of an audio input
+    pub ltc_wav: Option<PathBuf>,
+    /// frame rate timecode offsets are written in
+    pub timecode_rate: FrameRate,
+    /// SMPTE time each file starts at when chasing timecode, by file name
+    pub timecode_offsets: HashMap<String, f64>,
+    /// when clips start after being switched to
+    pub launch_quantize: LaunchQuantize,
+    /// launch quantization of files that don't use the global one, by file name
+    pub clip_launch_quantize: HashMap<String, LaunchQuantize>,
+    /// how clips take over their track unless the show file says otherwise
+    pub transition: Transition,
+    /// image luma wipes follow, a left to right gradient if not set
+    pub luma_matte: Option<PathBuf>,
+    /// folder of WGSL effects added to the built-in ones
+    pub shader_dir: Option<PathBuf>,
+    /// effects turned on at start
+    pub effects: Vec<String>,
+    /// starting values of effect parameters, by effect and parameter name
+    pub effect_params: HashMap<String, HashMap<String, f32>>,
+    /// what to show while the transport is stopped
+    pub when_stopped: WhenStopped,
+    /// image shown by `--when-stopped slate`
+    pub slate: Option<PathBuf>,
+    /// UDP port to listen for OSC on, no server if not set
+    pub osc_port: Option<u16>,
+    /// controller input port to map to actions, matched by name
+    pub midi_control: Option<String>,
+    /// where MIDI mappings are read from and learned into
+    pub midi_map: PathBuf,
+}
+
+/// What `render` writes, besides the picture size and frame rate in [`Options`]
+pub struct RenderOptions {
+    pub output: PathBuf,
+    /// scene to render, counting from 0
+    pub scene: usize,
+    pub bars: u32,
+    /// encoder by its ffmpeg name, the output format's default if not set
+    pub codec: Option<String>,
+}
+
+pub struct TranscodeOptions {
+    pub files: Vec<PathBuf>,
+    pub output_dir: PathBuf,
+    /// encoder by its ffmpeg name
+    pub codec: String,
+}
+
+/// Read the command line, `args` being the arguments after the program name. Without a
+/// command, the arguments are those of `play`.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Cli, String> {
+    parse_with(args, &|path| Config::load_or_default(path).map_err(|e| e.to_string()))
+}
+
+/// Reads the config file `--config` names, or the default one if `None`
+type ConfigLoader<'a> = &'a dyn Fn(Option<&Path>) -> Result<Config, String>;
+
+/// [`parse`], with the config file read by `load_config`
+fn parse_with(args: impl IntoIterator<Item = String>, load_config: ConfigLoader) -> Result<Cli, String> {
+    let mut args: Vec<String> = args.into_iter().collect();
+
+    // Taken out first, as every command logs
+    let mut log_level = None;
+    if let Some(at) = args.iter().position(|arg| arg == "--log-level") {
+        args.remove(at);
+        let expected = "`off`, `error`, `warn`, `info`, `debug` or `trace`";
+        let level = (at < args.len()).then(|| args.remove(at));
+        log_level = Some(
+            level
+                .and_then(|level| level.parse().ok())
+                .ok_or_else(|| format!("--log-level expects {}", expected))?,
+        );
+    }
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        return Ok(Cli { log_level, command: Command::Help });
+    }
+
+    let command = match args.first().map(String::as_str) {
+        Some("play") => Command::Play(parse_play(args.split_off(1), None, load_config)?),
+        Some("render") => {
+            let mut render = RenderOptions {
+                output: PathBuf::new(),
+                scene: 0,
+                bars: DEFAULT_RENDER_BARS,
+                codec: None,
+            };
+            let options = parse_play(args.split_off(1), Some(&mut render), load_config)?;
+            if render.output.as_os_str().is_empty() {
+                return Err("render expects --output FILE".to_string());
+            }
+            Command::Render(options, render)
+        }
+        Some("probe") => Command::Probe(parse_files("probe", args.split_off(1))?),
+        Some("transcode") => Command::Transcode(parse_transcode(args.split_off(1))?),
+        Some("list-outputs") if args.len() == 1 => Command::ListOutputs,
+        Some("list-outputs") => return Err("list-outputs takes no arguments".to_string()),
+        Some("help") => Command::Help,
+        _ => Command::Play(parse_play(args, None, load_config)?),
+    };
+    Ok(Cli { log_level, command })
+}
+
+/// Read the options of `play`, or of `render` when given somewhere to put its own, falling
+/// back to the config file for everything but the transport and caching
+fn parse_play(
+    args: Vec<String>,
+    mut render: Option<&mut RenderOptions>,
+    load_config: ConfigLoader,
+) -> Result<Options, String> {
+    let mut path = None;
+    let mut show = None;
+    let mut fullscreen = false;
+    let mut monitor = None;
+    let mut size = None;
+    let mut fps = None;
+    let mut cursor_timeout = None;
+    let mut cache_all = false;
+    let mut cache_mb = DEFAULT_CACHE_MB;
+    let mut loop_bars = 1;
+    let mut native_speed = false;
+    let mut transport = TransportKind::Link;
+    let mut bpm = None;
+    let mut quantum = None;
+    let mut config_path = None;
+    let mut midi_port = None;
+    let mut ltc_input = None;
+    let mut ltc_wav = None;
+    let mut when_stopped = WhenStopped::Freeze;
+    let mut slate = None;
+    let mut launch_quantize = None;
+    let mut transition_kind = None;
+    let mut transition_length = None;
+    let mut luma_matte = None;
+    let mut shader_dir = None;
+    let mut effects = Vec::new();
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    let mut osc_port = None;
+    let mut midi_control = None;
+    let mut midi_map = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--show" => show = Some(PathBuf::from(value(&mut args, &arg, "a file path")?)),
+            "--config" => config_path = Some(PathBuf::from(value(&mut args, &arg, "a file path")?)),
+            "--fullscreen" => fullscreen = true,
+            "--monitor" => {
+                let number: usize = number(&mut args, &arg, "a monitor number from list-outputs")?;
+                monitor = Some(number.checked_sub(1).ok_or("monitors are numbered from 1")?);
+            }
+            "--size" => {
+                let text = value(&mut args, &arg, "a size like 1920x1080")?;
+                let parsed = text
+                    .split_once('x')
+                    .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                    .filter(|&(width, height)| width > 0 && height > 0);
+                size = Some(parsed.ok_or("--size expects a size like 1920x1080")?);
+            }
+            "--fps" => fps = Some(positive(&mut args, &arg, "a number of frames per second")?),
+            "--cursor-timeout" => {
+                let secs: f64 = number(&mut args, &arg, "a number of seconds")?;
+                let timeout = Duration::try_from_secs_f64(secs).map_err(|_| "--cursor-timeout expects a number of seconds")?;
+                cursor_timeout = Some(timeout);
+            }
+            "--cache-all" => cache_all = true,
+            "--include" => include.push(value(&mut args, &arg, "a glob")?),
+            "--exclude" => exclude.push(value(&mut args, &arg, "a glob")?),
+            "--cache-mb" => cache_mb = number(&mut args, &arg, "a number of megabytes")?,
+            "--loop-bars" => loop_bars = number(&mut args, &arg, "a number of bars")?,
+            "--native-speed" => native_speed = true,
+            "--transport" => {
+                transport = match value(&mut args, &arg, "a transport")?.as_str() {
+                    "link" => TransportKind::Link,
+                    "internal" => TransportKind::Internal,
+                    "midi" => TransportKind::Midi,
+                    "mtc" => TransportKind::Mtc,
+                    "ltc" => TransportKind::Ltc,
+                    _ => return Err("--transport expects `link`, `internal`, `midi`, `mtc` or `ltc`".to_string()),
+                };
+            }
+            "--midi-port" => midi_port = Some(value(&mut args, &arg, "a port name")?),
+            "--ltc-input" => ltc_input = Some(value(&mut args, &arg, "an audio device name")?),
+            "--ltc-wav" => ltc_wav = Some(PathBuf::from(value(&mut args, &arg, "a WAV file path")?)),
+            "--bpm" => bpm = Some(above_zero(&mut args, &arg, "a tempo above 0")?),
+            "--quantum" => quantum = Some(above_zero(&mut args, &arg, "a number of beats per bar above 0")?),
+            "--when-stopped" => {
+                when_stopped = match value(&mut args, &arg, "what to show")?.as_str() {
+                    "freeze" => WhenStopped::Freeze,
+                    "slate" => WhenStopped::Slate,
+                    "black" => WhenStopped::Black,
+                    _ => return Err("--when-stopped expects `freeze`, `slate` or `black`".to_string()),
+                };
+            }
+            "--slate" => {
+                slate = Some(PathBuf::from(value(&mut args, &arg, "an image path")?));
+                when_stopped = WhenStopped::Slate;
+            }
+            "--launch-quantize" => {
+                let expected = "`none`, `beat`, `bar` or a number of bars";
+                launch_quantize = Some(parsed(&mut args, &arg, expected, LaunchQuantize::parse)?);
+            }
+            "--transition" => {
+                let expected = "`cut`, `crossfade`, `dip_to_black`, `wipe` or `luma_wipe`";
+                transition_kind = Some(parsed(&mut args, &arg, expected, TransitionKind::parse)?);
+            }
+            "--transition-length" => {
+                transition_length = Some(parsed(&mut args, &arg, "`N beats` or `N bars`", TransitionLength::parse)?);
+            }
+            "--luma-matte" => luma_matte = Some(PathBuf::from(value(&mut args, &arg, "an image path")?)),
+            "--shader-dir" => shader_dir = Some(PathBuf::from(value(&mut args, &arg, "a folder path")?)),
+            "--effect" => effects.push(value(&mut args, &arg, "an effect name")?),
+            "--osc-port" => osc_port = Some(number(&mut args, &arg, "a UDP port number")?),
+            "--midi-control" => midi_control = Some(value(&mut args, &arg, "a port name")?),
+            "--midi-map" => midi_map = Some(PathBuf::from(value(&mut args, &arg, "a file path")?)),
+            "--output" if render.is_some() => {
+                let output = PathBuf::from(value(&mut args, &arg, "a file path")?);
+                render.as_deref_mut().unwrap().output = output;
+            }
+            "--scene" if render.is_some() => {
+                let scene: usize = number(&mut args, &arg, "a scene number")?;
+                render.as_deref_mut().unwrap().scene = scene.checked_sub(1).ok_or("scenes are numbered from 1")?;
+            }
+            "--bars" if render.is_some() => {
+                let bars = positive(&mut args, &arg, "a number of bars")?;
+                render.as_deref_mut().unwrap().bars = bars;
+            }
+            "--codec" if render.is_some() => {
+                let codec = value(&mut args, &arg, "an encoder name")?;
+                render.as_deref_mut().unwrap().codec = Some(codec);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ if path.is_some() || show.is_some() => return Err(format!("unexpected argument {}", arg)),
+            // A show file can be given in place of a folder
+            _ if arg.ends_with(".toml") => show = Some(PathBuf::from(arg)),
+            _ => path = Some(arg),
+        }
+    }
+    if path.is_none() && show.is_none() {
+        let command = if render.is_some() { "render" } else { "play" };
+        return Err(format!("{} expects a video file, a folder of them or --show FILE", command));
+    }
+
+    let config = load_config(config_path.as_deref()).map_err(|e| format!("Failed to read config: {}", e))?;
+    if config.bpm.is_some_and(|bpm| !(bpm > 0.0 && bpm.is_finite())) {
+        return Err("The config file's bpm must be above 0".to_string());
+    }
+    if config.quantum.is_some_and(|quantum| !(quantum > 0.0 && quantum.is_finite())) {
+        return Err("The config file's quantum must be above 0".to_string());
+    }
+
+    let timecode_fps = config.timecode_fps.unwrap_or(DEFAULT_TIMECODE_FPS);
+    let timecode_rate =
+        FrameRate::from_fps(timecode_fps).ok_or_else(|| format!("Unsupported timecode frame rate {}", timecode_fps))?;
+    let timecode_offsets = config
+        .timecode_offsets
+        .iter()
+        .map(|(file, offset)| {
+            let secs = Timecode::parse_secs(offset, timecode_rate)
+                .ok_or_else(|| format!("Invalid timecode offset `{}` for {}", offset, file))?;
+            Ok((file.clone(), secs))
+        })
+        .collect::<Result<_, String>>()?;
+
+    let parse_quantize =
+        |text: &str| LaunchQuantize::parse(text).ok_or_else(|| format!("Invalid launch quantization `{}`", text));
+    let launch_quantize = match launch_quantize {
+        Some(quantize) => quantize,
+        None => config.launch_quantize.as_deref().map(parse_quantize).transpose()?.unwrap_or(LaunchQuantize::Bar),
+    };
+    let clip_launch_quantize = config
+        .clip_launch_quantize
+        .iter()
+        .map(|(file, quantize)| Ok((file.clone(), parse_quantize(quantize)?)))
+        .collect::<Result<_, String>>()?;
+
+    let transition = Transition {
+        kind: match transition_kind {
+            Some(kind) => kind,
+            None => match config.transition.as_deref() {
+                Some(text) => TransitionKind::parse(text).ok_or_else(|| format!("Invalid transition `{}`", text))?,
+                None => Transition::CUT.kind,
+            },
+        },
+        length: match transition_length {
+            Some(length) => length,
+            None => match config.transition_length.as_deref() {
+                Some(text) => {
+                    TransitionLength::parse(text).ok_or_else(|| format!("Invalid transition length `{}`", text))?
+                }
+                None => Transition::CUT.length,
+            },
+        },
+    };
+
+    let media_filter = Filter::new(
+        if include.is_empty() { &config.include } else { &include },
+        if exclude.is_empty() { &config.exclude } else { &exclude },
+    )?;
+
+    Ok(Options {
+        path,
+        show,
+        media_filter,
+        fullscreen,
+        monitor,
+        size,
+        fps,
+        cursor_timeout,
+        cache_all,
+        cache_mb,
+        loop_mode: if native_speed {
+            LoopMode::Native { resync_bars: loop_bars.max(1) }
+        } else {
+            LoopMode::Bars(loop_bars.max(1))
+        },
+        transport,
+        bpm: bpm.or(config.bpm).unwrap_or(DEFAULT_BPM),
+        quantum: quantum.or(config.quantum).unwrap_or(DEFAULT_QUANTUM),
+        midi_port,
+        ltc_input,
+        ltc_wav,
+        timecode_rate,
+        timecode_offsets,
+        launch_quantize,
+        clip_launch_quantize,
+        transition,
+        luma_matte: luma_matte.or(config.luma_matte),
+        shader_dir: shader_dir.or(config.shader_dir),
+        effects: if effects.is_empty() { config.effects } else { effects },
+        effect_params: config.effect_params,
+        when_stopped,
+        slate,
+        osc_port: osc_port.or(config.osc_port),
+        midi_control: midi_control.or(config.midi_control),
+        midi_map: midi_map.or(config.midi_map).unwrap_or_else(|| PathBuf::from(midi_map::DEFAULT_MIDI_MAP_PATH)),
+    })
+}
+
+fn parse_transcode(args: Vec<String>) -> Result<TranscodeOptions, String> {
+    let mut output_dir = PathBuf::from(DEFAULT_TRANSCODE_DIR);
+    let mut codec = DEFAULT_TRANSCODE_CODEC.to_string();
+    let mut files = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output-dir" => output_dir = PathBuf::from(value(&mut args, &arg, "a folder path")?),
+            "--codec" => codec = value(&mut args, &arg, "an encoder name")?,
+            _ => files.push(arg),
+        }
+    }
+    Ok(TranscodeOptions { files: parse_files("transcode", files)?, output_dir, codec })
+}
+
+/// The files a command works on, at least one
+fn parse_files(command: &str, args: Vec<String>) -> Result<Vec<PathBuf>, String> {
+    if let Some(option) = args.iter().find(|arg| arg.starts_with('-')) {
+        return Err(format!("unknown option {}", option));
+    }
+    if args.is_empty() {
+        return Err(format!("{} expects at least one file", command));
+    }
+    Ok(args.into_iter().map(PathBuf::from).collect())
+}
+
+/// The argument following `flag`
+fn value(args: &mut impl Iterator<Item = String>, flag: &str, expected: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("{} expects {}", flag, expected))
+}
+
+/// The argument following `flag`, as a number
+fn number<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str, expected: &str) -> Result<T, String> {
+    value(args, flag, expected)?.parse().map_err(|_| format!("{} expects {}", flag, expected))
+}
+
+/// The argument following `flag`, read by `parse`
+fn parsed<T>(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+    expected: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<T, String> {
+    parse(&value(args, flag, expected)?).ok_or_else(|| format!("{} expects {}", flag, expected))
+}
+
+/// The argument following `flag`, as a number above 0
+fn positive(args: &mut impl Iterator<Item = String>, flag: &str, expected: &str) -> Result<u32, String> {
+    match number(args, flag, expected)? {
+        0 => Err(format!("{} expects {}", flag, expected)),
+        value => Ok(value),
+    }
+}
+
+/// The argument following `flag`, as a finite number above 0.0
+fn above_zero(args: &mut impl Iterator<Item = String>, flag: &str, expected: &str) -> Result<f64, String> {
+    let value: f64 = number(args, flag, expected)?;
+    if value > 0.0 && value.is_finite() {
+        Ok(value)
+    } else {
+        Err(format!("{} expects {}", flag, expected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// The command line `line`, with an empty config file
+    fn cli(line: &str) -> Result<Cli, String> {
+        parse_with(line.split_whitespace().map(String::from), &|_| Ok(Config::default()))
+    }
+
+    fn play(line: &str) -> Options {
+        match cli(line).unwrap_or_else(|e| panic!("{}", e)).command {
+            Command::Play(options) => options,
+            _ => panic!("`{}` isn't play", line),
+        }
+    }
+
+    fn error(line: &str) -> String {
+        cli(line).err().unwrap_or_else(|| panic!("`{}` was accepted", line))
+    }
+
+    #[test]
+    fn a_bare_path_is_played() {
+        let options = play("clips");
+        assert_eq!(options.path.as_deref(), Some("clips"));
+        assert!(options.show.is_none());
+        assert_eq!(play("play clips").path.as_deref(), Some("clips"));
+        assert_eq!(error(""), "play expects a video file, a folder of them or --show FILE");
+        assert_eq!(error("play --fullscreen"), "play expects a video file, a folder of them or --show FILE");
+        assert_eq!(error("render --output out.mov"), "render expects a video file, a folder of them or --show FILE");
+    }
+
+    #[test]
+    fn a_toml_path_is_a_show_file() {
+        let options = play("set.toml");
+        assert_eq!(options.show, Some(PathBuf::from("set.toml")));
+        assert!(options.path.is_none());
+        assert_eq!(play("play --show set.toml").show, Some(PathBuf::from("set.toml")));
+        assert_eq!(error("set.toml clips"), "unexpected argument clips");
+        assert_eq!(error("clips more"), "unexpected argument more");
+    }
+
+    #[test]
+    fn play_options() {
+        let options = play("--fullscreen --monitor 2 --size 800x600 --fps 50 --bpm 96.5 --quantum 3 --loop-bars 2 clips");
+        assert!(options.fullscreen);
+        assert_eq!(options.monitor, Some(1));
+        assert_eq!(options.size, Some((800, 600)));
+        assert_eq!(options.fps, Some(50));
+        assert_eq!(options.bpm, 96.5);
+        assert_eq!(options.quantum, 3.0);
+        assert_eq!(options.loop_mode, LoopMode::Bars(2));
+        let options = play("clips");
+        assert_eq!((options.bpm, options.quantum), (DEFAULT_BPM, DEFAULT_QUANTUM));
+        assert_eq!(options.loop_mode, LoopMode::Bars(1));
+    }
+
+    #[test]
+    fn render_options() {
+        let cli = cli("render --output out.mov --scene 2 --bars 8 --codec prores_ks --size 640x360 set.toml").unwrap();
+        let Command::Render(options, render) = cli.command else {
+            panic!("not render");
+        };
+        assert_eq!(options.show, Some(PathBuf::from("set.toml")));
+        assert_eq!(options.size, Some((640, 360)));
+        assert_eq!(render.output, PathBuf::from("out.mov"));
+        assert_eq!(render.scene, 1);
+        assert_eq!(render.bars, 8);
+        assert_eq!(render.codec.as_deref(), Some("prores_ks"));
+
+        assert_eq!(error("render set.toml"), "render expects --output FILE");
+        assert_eq!(error("render --output out.mov --scene 0 set.toml"), "scenes are numbered from 1");
+        assert_eq!(error("render --output out.mov --bars 0 set.toml"), "--bars expects a number of bars");
+        // Only render writes files
+        assert_eq!(error("--output out.mov clips"), "unknown option --output");
+    }
+
+    #[test]
+    fn probe_transcode_and_list_outputs() {
+        let Command::Probe(files) = cli("probe a.mov b.mp4").unwrap().command else {
+            panic!("not probe");
+        };
+        assert_eq!(files, [PathBuf::from("a.mov"), PathBuf::from("b.mp4")]);
+        assert_eq!(error("probe"), "probe expects at least one file");
+        assert_eq!(error("probe --deep a.mov"), "unknown option --deep");
+
+        let Command::Transcode(transcode) = cli("transcode --codec dnxhd --output-dir out a.mov").unwrap().command else {
+            panic!("not transcode");
+        };
+        assert_eq!(transcode.files, [PathBuf::from("a.mov")]);
+        assert_eq!(transcode.output_dir, PathBuf::from("out"));
+        assert_eq!(transcode.codec, "dnxhd");
+        let Command::Transcode(transcode) = cli("transcode a.mov").unwrap().command else {
+            panic!("not transcode");
+        };
+        assert_eq!((transcode.output_dir, transcode.codec), (PathBuf::from(DEFAULT_TRANSCODE_DIR), DEFAULT_TRANSCODE_CODEC.into()));
+        assert_eq!(error("transcode"), "transcode expects at least one file");
+
+        assert!(matches!(cli("list-outputs").unwrap().command, Command::ListOutputs));
+        assert_eq!(error("list-outputs 2"), "list-outputs takes no arguments");
+        assert!(matches!(cli("help").unwrap().command, Command::Help));
+        assert!(matches!(cli("render --bogus -h").unwrap().command, Command::Help));
+    }
+
+    #[test]
+    fn log_level_goes_anywhere() {
+        assert_eq!(cli("--log-level debug clips").unwrap().log_level, Some(log::LevelFilter::Debug));
+        assert_eq!(cli("clips --log-level warn").unwrap().log_level, Some(log::LevelFilter::Warn));
+        assert_eq!(cli("probe a.mov --log-level off").unwrap().log_level, Some(log::LevelFilter::Off));
+        assert_eq!(cli("render --log-level trace --output o.mov s.toml").unwrap().log_level, Some(log::LevelFilter::Trace));
+        assert_eq!(cli("clips").unwrap().log_level, None);
+        let expected = "--log-level expects `off`, `error`, `warn`, `info`, `debug` or `trace`";
+        assert_eq!(error("clips --log-level"), expected);
+        assert_eq!(error("--log-level loud clips"), expected);
+    }
+
+    #[test]
+    fn unknown_options_and_missing_values() {
+        assert_eq!(error("--frobnicate clips"), "unknown option --frobnicate");
+        assert_eq!(error("render --output o.mov --frobnicate s.toml"), "unknown option --frobnicate");
+        assert_eq!(error("--show"), "--show expects a file path");
+        assert_eq!(error("clips --fps"), "--fps expects a number of frames per second");
+        assert_eq!(error("--fps fast clips"), "--fps expects a number of frames per second");
+        assert_eq!(error("render --output"), "--output expects a file path");
+        assert_eq!(error("--transport carrier-pigeon"), "--transport expects `link`, `internal`, `midi`, `mtc` or `ltc`");
+    }
+
+    #[test]
+    fn numbers_out_of_range() {
+        assert_eq!(error("--monitor 0 clips"), "monitors are numbered from 1");
+        assert_eq!(error("--size 0x10 clips"), "--size expects a size like 1920x1080");
+        assert_eq!(error("--size 1920 clips"), "--size expects a size like 1920x1080");
+        assert_eq!(error("--fps 0 clips"), "--fps expects a number of frames per second");
+        assert_eq!(error("--bpm 0 clips"), "--bpm expects a tempo above 0");
+        assert_eq!(error("--bpm -120 clips"), "--bpm expects a tempo above 0");
+        assert_eq!(error("--bpm NaN clips"), "--bpm expects a tempo above 0");
+        assert_eq!(error("--quantum 0 clips"), "--quantum expects a number of beats per bar above 0");
+        assert_eq!(error("--quantum -4 clips"), "--quantum expects a number of beats per bar above 0");
+    }
+
+    #[test]
+    fn config_fills_in_what_the_command_line_leaves_out() {
+        let asked = RefCell::new(Vec::new());
+        let load_config = |path: Option<&Path>| {
+            asked.borrow_mut().push(path.map(Path::to_path_buf));
+            Ok(Config { bpm: Some(140.0), quantum: Some(3.0), ..Config::default() })
+        };
+        let options = |line: &str| match parse_with(line.split_whitespace().map(String::from), &load_config) {
+            Ok(Cli { command: Command::Play(options), .. }) => options,
+            _ => panic!("`{}` isn't play", line),
+        };
+        assert_eq!((options("clips").bpm, options("clips").quantum), (140.0, 3.0));
+        assert_eq!(options("--bpm 100 --config live.toml clips").bpm, 100.0);
+        assert_eq!(asked.borrow().last(), Some(&Some(PathBuf::from("live.toml"))));
+        assert_eq!(asked.borrow().first(), Some(&None));
+
+        let zero_bpm = |_: Option<&Path>| Ok(Config { bpm: Some(0.0), ..Config::default() });
+        let result = parse_with(["clips".to_string()], &zero_bpm);
+        assert_eq!(result.err().as_deref(), Some("The config file's bpm must be above 0"));
+        let broken = |_: Option<&Path>| Err("line 1".to_string());
+        let result = parse_with(["clips".to_string()], &broken);
+        assert_eq!(result.err().as_deref(), Some("Failed to read config: line 1"));
+    }
+}
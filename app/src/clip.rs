@@ -1,25 +1,166 @@
+use crate::frame_cache::{Frame, FrameKey, SharedFrameCache};
+use crate::media;
+use crate::yuv::Layout;
 use ffmpeg_next as ffmpeg;
 use log::{info, debug};
+use std::collections::VecDeque;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use transport::TransportSnapshot;
+
+/// Number of frames decoded ahead of the playhead when streaming
+pub const DEFAULT_STREAM_FRAMES: usize = 32;
+
+/// Frame rate assumed when a container reports none
+const FALLBACK_FRAME_RATE: f64 = 25.0;
 
 pub struct Size {
     width: u32,
     height: u32,
 }
 
+/// How a clip's playback follows the transport
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopMode {
+    /// Stretch the whole clip over this many bars
+    Bars(u32),
+    /// Play at the file's own speed, restarting on the downbeat every `resync_bars` bars
+    Native { resync_bars: u32 },
+    /// Play once from `offset_secs` of SMPTE time, holding the first and last frames outside it
+    Timecode { offset_secs: f64 },
+}
+
+impl LoopMode {
+    pub fn bars(&self) -> u32 {
+        match *self {
+            LoopMode::Bars(bars) | LoopMode::Native { resync_bars: bars } => bars,
+            LoopMode::Timecode { .. } => 1,
+        }
+    }
+
+    pub fn with_bars(&self, bars: u32) -> LoopMode {
+        let bars = bars.max(1);
+        match self {
+            LoopMode::Bars(_) => LoopMode::Bars(bars),
+            LoopMode::Native { .. } => LoopMode::Native { resync_bars: bars },
+            LoopMode::Timecode { .. } => *self,
+        }
+    }
+
+    /// Normalized position of a `clip_secs` long clip at the transport's current beat,
+    /// or its timecode in timecode mode
+    pub fn position(&self, transport: &TransportSnapshot, clip_secs: f64) -> f32 {
+        let tempo = transport.tempo;
+        let span = transport.quantum * self.bars() as f64;
+        let beat_in_loop = transport.beat.rem_euclid(span);
+        match self {
+            LoopMode::Bars(_) => (beat_in_loop / span) as f32,
+            LoopMode::Native { .. } => {
+                if clip_secs <= 0.0 || tempo <= 0.0 {
+                    return 0.0;
+                }
+                let secs = beat_in_loop * 60.0 / tempo;
+                // Clips shorter than the loop simply repeat until the next resync
+                (secs / clip_secs).fract() as f32
+            }
+            LoopMode::Timecode { offset_secs } => match transport.timecode {
+                Some(secs) if clip_secs > 0.0 => ((secs - offset_secs) / clip_secs).clamp(0.0, 1.0) as f32,
+                _ => 0.0,
+            },
+        }
+    }
+}
+
+/// When a clip switched to starts playing, like Ableton's launch quantization
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LaunchQuantize {
+    /// straight away
+    None,
+    Beat,
+    Bar,
+    /// on the next multiple of this many bars
+    Bars(u32),
+}
+
+impl LaunchQuantize {
+    /// Reads `none`, `beat`, `bar` or a number of bars
+    pub fn parse(text: &str) -> Option<LaunchQuantize> {
+        match text {
+            "none" => Some(LaunchQuantize::None),
+            "beat" => Some(LaunchQuantize::Beat),
+            "bar" => Some(LaunchQuantize::Bar),
+            _ => match text.parse().ok()? {
+                0 => None,
+                1 => Some(LaunchQuantize::Bar),
+                bars => Some(LaunchQuantize::Bars(bars)),
+            },
+        }
+    }
+
+    /// The first beat on the launch grid at or after `beat`, or `None` to launch straight away
+    pub fn next_launch(&self, beat: f64, quantum: f64) -> Option<f64> {
+        let step = match *self {
+            LaunchQuantize::None => return None,
+            LaunchQuantize::Beat => 1.0,
+            LaunchQuantize::Bar => quantum,
+            LaunchQuantize::Bars(bars) => quantum * bars as f64,
+        };
+        Some((beat / step).ceil() * step)
+    }
+}
+
+/// swscale context, only ever used by whichever thread currently owns the clip
+struct Scaler(ffmpeg::software::scaling::Context);
+
+// SAFETY: the context is moved to another thread together with its clip, never shared
+unsafe impl Send for Scaler {}
+
+/// A decoded frame shown from `pts` until `end`, both in stream time base units
+struct Buffered {
+    pts: i64,
+    end: i64,
+    frame: Frame,
+}
+
 pub struct Clip {
+    id: u64,
     ctx: ffmpeg::format::context::Input,
     video_stream_index: usize,
-    total_frames: f32,
+    /// seconds per stream time base unit
+    time_base: f64,
+    start_time: i64,
+    /// length of the stream in time base units
+    duration: i64,
+    /// nominal frame duration in time base units, used where timestamps are missing
+    frame_duration: i64,
     decoder: ffmpeg::codec::decoder::Video,
-    scaler: ffmpeg::software::scaling::Context,
+    /// converts frames to RGBA when the compositor can't take them as decoded
+    scaler: Option<Scaler>,
     pub size: Size,
-    frames: Vec<ffmpeg::util::frame::Video>,
+    cache: SharedFrameCache,
+    read_ahead: usize,
+    buffer: VecDeque<Buffered>,
+    /// last decoded frame, waiting for the next one to tell where it ends
+    held: Option<(i64, ffmpeg::util::frame::Video)>,
+    next_pts: i64,
+    /// the demuxer has run out of packets and the decoder was told so
+    eof: bool,
+    /// every frame, the decoder's and the held one, has been handed out
+    drained: bool,
 }
 
 impl Clip {
-    pub fn new(path: &str) -> Result<Clip, ffmpeg::Error> {
-        let ctx = ffmpeg::format::input(&path)?;
-        let input = ctx.streams().best(ffmpeg::media::Type::Video).unwrap();
+    pub fn new(path: &Path, cache: SharedFrameCache) -> Result<Clip, ffmpeg::Error> {
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        // A file written over gets an id of its own, so frames of what it held before aren't reused
+        if let Ok(modified) = std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            modified.hash(&mut hasher);
+        }
+        let id = hasher.finish();
+
+        let mut ctx = media::open(path)?;
+        let input = ctx.streams().best(ffmpeg::media::Type::Video).ok_or(ffmpeg::Error::StreamNotFound)?;
         let video_stream_index = input.index();
 
         let context_decoder = ffmpeg::codec::context::Context::from_parameters(input.parameters())?;
@@ -27,74 +168,490 @@ impl Clip {
         let width = decoder.width();
         let height = decoder.height();
 
-        let total_frames = input.frames() as f32;
-        let scaler = ffmpeg::software::scaling::Context::get(
-            decoder.format(),
-            width,
-            height,
-            ffmpeg::format::Pixel::RGBA,
-            width,
-            height,
-            ffmpeg::software::scaling::Flags::BILINEAR,
-        )
-        .unwrap();
+        let time_base = f64::from(input.time_base());
+        let frame_rate = [input.avg_frame_rate(), input.rate()]
+            .into_iter()
+            .map(f64::from)
+            .find(|rate| rate.is_finite() && *rate > 0.0)
+            .unwrap_or(FALLBACK_FRAME_RATE);
+        let frame_duration = ((1.0 / frame_rate / time_base).round() as i64).max(1);
+        let start_time = input.start_time().max(0);
+        // Streams without a duration of their own (common for MKV/WebM) fall back to the
+        // container's, which is in AV_TIME_BASE microseconds
+        let stream_duration = input.duration();
+        let duration = match stream_duration {
+            d if d > 0 => d,
+            _ if ctx.duration() > 0 => (ctx.duration() as f64 / 1_000_000.0 / time_base) as i64,
+            _ => scan_duration(&mut ctx, video_stream_index, start_time)?,
+        };
+        debug!("Stream duration {duration}, start {start_time}, time base {time_base}, {frame_rate:.3} fps");
+
+        // NV12 and planar YUV frames are kept as they are and converted to RGB on the GPU
+        let scaler = match Layout::of(decoder.format()) {
+            Some(_) => None,
+            None => {
+                debug!("Converting {:?} frames to RGBA", decoder.format());
+                let scaler = ffmpeg::software::scaling::Context::get(
+                    decoder.format(),
+                    width,
+                    height,
+                    ffmpeg::format::Pixel::RGBA,
+                    width,
+                    height,
+                    ffmpeg::software::scaling::Flags::BILINEAR,
+                )?;
+                Some(Scaler(scaler))
+            }
+        };
 
         Ok(Clip {
+            id,
             ctx,
             video_stream_index,
-            total_frames,
+            time_base,
+            start_time,
+            duration,
+            frame_duration,
             decoder,
             scaler,
             size: Size { width, height },
-            frames: Vec::new(),
+            cache,
+            read_ahead: DEFAULT_STREAM_FRAMES,
+            buffer: VecDeque::new(),
+            held: None,
+            next_pts: start_time,
+            eof: false,
+            drained: false,
         })
     }
 
-    pub fn play_video_at_position(&self, position: f32) -> ffmpeg::util::frame::Video {
-        let frame_number = self.total_frames as f32 * position;
+    /// Length of the clip in seconds
+    pub fn duration_secs(&self) -> f64 {
+        self.duration as f64 * self.time_base
+    }
+
+    /// Roughly how many frames the clip has, from its length and frame rate
+    pub fn frame_count(&self) -> usize {
+        (self.duration + self.frame_duration - 1).max(0) as usize / self.frame_duration as usize
+    }
+
+    pub fn play_video_at_position(&mut self, position: f32) -> Frame {
+        let pts = position_to_pts(position, self.start_time, self.duration);
+
+        debug!("Getting frame at pts {pts} of {}, at position {position}", self.duration);
 
-        debug!("Getting frame {frame_number} from {} of frames, at position {position}", self.total_frames);
+        // Frames decoded earlier, possibly by a previous instance of this clip
+        if let Some(frame) = self.cache.lock().unwrap().get(self.id, pts) {
+            return frame;
+        }
 
-        self.frames[frame_number as usize].clone()
+        self.stream_frame(pts)
     }
 
+    /// Decode the whole file into the shared frame cache up front, for short loops.
+    /// Frames evicted later are decoded again on demand.
     pub fn cache_all_frames(&mut self) -> Result<(), ffmpeg::Error> {
-        // let mut packet = ffmpeg::Packet::empty();
-        let mut decoded = ffmpeg::util::frame::Video::empty();
+        self.seek_to_pts(self.start_time)?;
+        let mut count = 0;
+        while let Some(buffered) = self.decode_next(self.start_time)? {
+            let key = self.key(buffered.pts);
+            let mut cache = self.cache.lock().unwrap();
+            if !cache.contains(&key) {
+                cache.insert(key, buffered.end, buffered.frame);
+            }
+            count += 1;
+        }
+        let cache = self.cache.lock().unwrap();
+        info!(
+            "Cached {} frames, frame cache at {} of {} MB",
+            count,
+            cache.used_bytes() / (1024 * 1024),
+            cache.budget_bytes() / (1024 * 1024)
+        );
+        Ok(())
+    }
 
-        // Read packets from the input file
-        for (stream, packet) in self.ctx.packets() {
-            if stream.index() != self.video_stream_index {
-                continue;
+    fn key(&self, pts: i64) -> FrameKey {
+        FrameKey { clip: self.id, pts }
+    }
+
+    /// Return the frame shown at `pts` from the ring buffer, decoding forward or seeking as needed
+    fn stream_frame(&mut self, pts: i64) -> Frame {
+        let capacity = self.read_ahead;
+        let front = self.buffer.front().map(|b| b.pts);
+        let back = self.buffer.back().map(|b| b.end);
+
+        // Phase jumped backwards (usually a loop wrap) or far ahead of what we have
+        let behind = front.is_some_and(|f| pts < f) || (front.is_none() && pts < self.next_pts);
+        let too_far = back.is_some_and(|b| pts > b + capacity as i64 * self.frame_duration);
+        if behind || too_far {
+            if let Err(e) = self.seek_to_pts(pts) {
+                log::error!("Failed to seek to pts {pts}: {e}");
             }
-            info!("Reading packet from stream {}", stream.index());
-
-            // Send the packet to the decoder
-            self.decoder.send_packet(&packet)?;
-
-            // Receive all frames the decoder can produce from this packet
-            while self.decoder.receive_frame(&mut decoded).is_ok() {
-                // Clone the frame and store it (Video frame doesn't implement Copy)
-                let mut rgb = ffmpeg::util::frame::Video::empty();
-                rgb.set_format(ffmpeg::format::Pixel::RGBA);
-                rgb.set_width(self.size.width);
-                rgb.set_height(self.size.height);
-                self.scaler.run(&decoded, &mut rgb)?;
-                self.frames.push(rgb);
+        }
+
+        // Drop frames the playhead has passed
+        while self.buffer.len() > 1 && self.buffer.front().is_some_and(|b| b.end <= pts) {
+            self.buffer.pop_front();
+        }
+
+        // Decode until the playhead is covered, then top up the read-ahead
+        // Frames the decoder holds back and the held one still come out after end of file
+        while !self.drained && (self.buffer.back().is_none_or(|b| b.end <= pts) || self.buffer.len() < capacity) {
+            match self.decode_next(pts) {
+                Ok(Some(buffered)) => {
+                    let key = self.key(buffered.pts);
+                    let frame = self.cache.lock().unwrap().insert(key, buffered.end, buffered.frame);
+                    self.buffer.push_back(Buffered { frame, ..buffered });
+                }
+                Ok(None) => self.drained = true,
+                Err(e) => {
+                    log::error!("Failed to decode frame: {e}");
+                    break;
+                }
             }
         }
 
-        // Flush the decoder
-        self.decoder.send_eof()?;
-        while self.decoder.receive_frame(&mut decoded).is_ok() {
-            let mut rgb = ffmpeg::util::frame::Video::empty();
-            rgb.set_format(ffmpeg::format::Pixel::RGBA);
-            rgb.set_width(self.size.width);
-            rgb.set_height(self.size.height);
-            self.scaler.run(&decoded, &mut rgb)?;
-            self.frames.push(rgb);
+        let shown = self
+            .buffer
+            .iter()
+            .find(|b| b.pts <= pts && pts < b.end)
+            .or(self.buffer.back())
+            .map(|b| b.frame.clone());
+        match shown {
+            Some(frame) => frame,
+            None => {
+                log::warn!("No decoded frame available for pts {pts}");
+                Frame::new(ffmpeg::util::frame::Video::new(
+                    ffmpeg::format::Pixel::RGBA,
+                    self.size.width,
+                    self.size.height,
+                ))
+            }
         }
-        info!("Cached {} frames", self.frames.len());
+    }
+
+    /// Seek to the keyframe at or before `pts` and reset the decoder
+    fn seek_to_pts(&mut self, pts: i64) -> Result<(), ffmpeg::Error> {
+        let timestamp = (pts as f64 * self.time_base * 1_000_000.0) as i64;
+        debug!("Seeking to pts {pts} ({:.3}s)", timestamp as f64 / 1_000_000.0);
+
+        self.ctx.seek(timestamp, ..timestamp)?;
+        self.decoder.flush();
+        self.buffer.clear();
+        self.held = None;
+        self.eof = false;
+        self.drained = false;
+        // Frames without timestamps are counted from here, refined by the first timestamped one
+        self.next_pts = pts;
         Ok(())
     }
+
+    /// Decode and, if need be, convert the next frame still visible at or after `from`, or `None` at
+    /// end of stream. Frames that end before `from` are skipped without being converted.
+    fn decode_next(&mut self, from: i64) -> Result<Option<Buffered>, ffmpeg::Error> {
+        loop {
+            let next = self.decode_raw()?;
+            // A frame lasts until the next one starts, the last one until the end of the stream
+            let end = match &next {
+                Some((pts, _)) => *pts,
+                None => {
+                    let last_end = self.held.as_ref().map_or(0, |(pts, _)| pts + self.frame_duration);
+                    last_end.max(self.start_time + self.duration)
+                }
+            };
+
+            let Some((pts, held)) = std::mem::replace(&mut self.held, next) else {
+                if self.eof {
+                    return Ok(None);
+                }
+                continue;
+            };
+            if end <= pts || end <= from {
+                continue;
+            }
+            let frame = Frame::new(self.convert(held)?);
+            return Ok(Some(Buffered { pts, end, frame }));
+        }
+    }
+
+    /// Decode the next video frame with its presentation timestamp, or `None` at end of stream
+    fn decode_raw(&mut self) -> Result<Option<(i64, ffmpeg::util::frame::Video)>, ffmpeg::Error> {
+        let mut decoded = ffmpeg::util::frame::Video::empty();
+        loop {
+            if self.decoder.receive_frame(&mut decoded).is_ok() {
+                let pts = decoded.timestamp().unwrap_or(self.next_pts);
+                self.next_pts = pts + self.frame_duration;
+                return Ok(Some((pts, decoded)));
+            }
+            if self.eof {
+                return Ok(None);
+            }
+
+            // Read packets from the input file until one belongs to our stream
+            match self.ctx.packets().next() {
+                Some((stream, packet)) => {
+                    if stream.index() != self.video_stream_index {
+                        continue;
+                    }
+                    self.decoder.send_packet(&packet)?;
+                }
+                None => {
+                    // Flush the decoder
+                    self.decoder.send_eof()?;
+                    self.eof = true;
+                }
+            }
+        }
+    }
+
+    /// The frame as the compositor takes it
+    fn convert(&mut self, decoded: ffmpeg::util::frame::Video) -> Result<ffmpeg::util::frame::Video, ffmpeg::Error> {
+        let Some(scaler) = &mut self.scaler else {
+            return Ok(decoded);
+        };
+        let mut rgb = ffmpeg::util::frame::Video::empty();
+        rgb.set_format(ffmpeg::format::Pixel::RGBA);
+        rgb.set_width(self.size.width);
+        rgb.set_height(self.size.height);
+        scaler.0.run(&decoded, &mut rgb)?;
+        Ok(rgb)
+    }
+}
+
+/// Map a normalized loop position to the presentation time it falls on, in time base units
+pub fn position_to_pts(position: f32, start_time: i64, duration: i64) -> i64 {
+    let position = position.clamp(0.0, 1.0) as f64;
+    // Position 1.0 still falls inside the last frame
+    let offset = ((duration as f64 * position) as i64).min(duration - 1).max(0);
+    start_time + offset
+}
+
+/// Find the stream length by reading packet timestamps, for files whose headers don't say.
+/// Only demuxes, so it is much cheaper than decoding.
+fn scan_duration(
+    ctx: &mut ffmpeg::format::context::Input,
+    stream_index: usize,
+    start_time: i64,
+) -> Result<i64, ffmpeg::Error> {
+    let mut end = start_time;
+    for (stream, packet) in ctx.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        if let Some(pts) = packet.pts() {
+            end = end.max(pts + packet.duration().max(1));
+        }
+    }
+    ctx.seek(0, ..0)?;
+    debug!("Scanned stream duration: {}", end - start_time);
+    Ok(end - start_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame_cache::FrameCache;
+    use ffmpeg::{codec, encoder, format, Rational};
+    use transport::{manual::ManualClock, Transport};
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{actual} is not {expected}");
+    }
+
+    #[test]
+    fn bars_stretch_the_clip_over_the_loop() {
+        // 120 bpm in 4/4: a beat every half second, two bars every 4 seconds
+        let mut clock = ManualClock::new(120.0, 4.0);
+        let mode = LoopMode::Bars(2);
+        assert_near(mode.position(&clock.update_phase_and_beat(), 3.0), 0.0);
+        clock.advance_secs(1.0);
+        assert_near(mode.position(&clock.update_phase_and_beat(), 3.0), 0.25);
+        clock.advance_secs(2.5);
+        assert_near(mode.position(&clock.update_phase_and_beat(), 3.0), 0.875);
+        clock.advance_secs(1.0);
+        assert_near(mode.position(&clock.update_phase_and_beat(), 3.0), 0.125);
+        clock.set_beat(-1.0);
+        assert_near(mode.position(&clock.update_phase_and_beat(), 3.0), 0.875);
+    }
+
+    #[test]
+    fn native_plays_at_file_speed_and_resyncs_on_the_bar() {
+        let mut clock = ManualClock::new(120.0, 4.0);
+        let mode = LoopMode::Native { resync_bars: 1 };
+        clock.advance_secs(1.0);
+        assert_near(mode.position(&clock.update_phase_and_beat(), 1.5), 1.0 / 1.5);
+        // A clip shorter than the bar starts over before it ends
+        clock.advance_secs(0.75);
+        assert_near(mode.position(&clock.update_phase_and_beat(), 1.5), 0.25 / 1.5);
+        // The bar is 2 seconds, so half a second into the next one the clip is half a second in
+        clock.advance_secs(0.75);
+        assert_near(mode.position(&clock.update_phase_and_beat(), 1.5), 0.5 / 1.5);
+        clock.set_tempo(0.0);
+        assert_near(mode.position(&clock.update_phase_and_beat(), 1.5), 0.0);
+    }
+
+    #[test]
+    fn timecode_plays_once_from_its_offset() {
+        let clock = ManualClock::new(120.0, 4.0).update_phase_and_beat();
+        let mode = LoopMode::Timecode { offset_secs: 10.0 };
+        let at = |secs| TransportSnapshot { timecode: Some(secs), ..clock };
+        assert_near(mode.position(&at(12.0), 4.0), 0.5);
+        assert_near(mode.position(&at(5.0), 4.0), 0.0);
+        assert_near(mode.position(&at(20.0), 4.0), 1.0);
+        assert_near(mode.position(&clock, 4.0), 0.0);
+    }
+
+    #[test]
+    fn launches_wait_for_the_next_step_of_the_clock() {
+        let mut clock = ManualClock::new(120.0, 4.0);
+        clock.advance_secs(0.3);
+        let beat = clock.update_phase_and_beat().beat;
+        assert_eq!(LaunchQuantize::None.next_launch(beat, 4.0), None);
+        assert_eq!(LaunchQuantize::Beat.next_launch(beat, 4.0), Some(1.0));
+        assert_eq!(LaunchQuantize::Bar.next_launch(beat, 4.0), Some(4.0));
+        assert_eq!(LaunchQuantize::Bars(2).next_launch(beat, 4.0), Some(8.0));
+        clock.advance_secs(2.0);
+        let beat = clock.update_phase_and_beat().beat;
+        assert_eq!(LaunchQuantize::Bar.next_launch(beat, 4.0), Some(8.0));
+        assert_eq!(LaunchQuantize::Bars(2).next_launch(beat, 4.0), Some(8.0));
+    }
+
+    #[test]
+    fn position_to_pts_spans_the_stream() {
+        assert_eq!(position_to_pts(0.0, 0, 1000), 0);
+        assert_eq!(position_to_pts(0.5, 0, 1000), 500);
+        // The end of the loop is still inside the last frame
+        assert_eq!(position_to_pts(1.0, 0, 1000), 999);
+        assert_eq!(position_to_pts(1.5, 0, 1000), 999);
+        assert_eq!(position_to_pts(-0.5, 0, 1000), 0);
+    }
+
+    #[test]
+    fn position_to_pts_of_an_empty_stream_is_its_start() {
+        assert_eq!(position_to_pts(0.0, 0, 0), 0);
+        assert_eq!(position_to_pts(1.0, 0, 0), 0);
+        assert_eq!(position_to_pts(0.5, 42, 0), 42);
+    }
+
+    #[test]
+    fn position_to_pts_counts_from_the_start_time() {
+        assert_eq!(position_to_pts(0.0, 3000, 1000), 3000);
+        assert_eq!(position_to_pts(0.25, 3000, 1000), 3250);
+        assert_eq!(position_to_pts(1.0, 3000, 1000), 3999);
+    }
+
+    #[test]
+    fn launch_quantize_parses_names_and_bar_counts() {
+        assert_eq!(LaunchQuantize::parse("none"), Some(LaunchQuantize::None));
+        assert_eq!(LaunchQuantize::parse("beat"), Some(LaunchQuantize::Beat));
+        assert_eq!(LaunchQuantize::parse("bar"), Some(LaunchQuantize::Bar));
+        assert_eq!(LaunchQuantize::parse("1"), Some(LaunchQuantize::Bar));
+        assert_eq!(LaunchQuantize::parse("4"), Some(LaunchQuantize::Bars(4)));
+        assert_eq!(LaunchQuantize::parse("0"), None);
+        assert_eq!(LaunchQuantize::parse("-2"), None);
+        assert_eq!(LaunchQuantize::parse("Bar"), None);
+        assert_eq!(LaunchQuantize::parse(""), None);
+    }
+
+    #[test]
+    fn next_launch_keeps_beats_already_on_the_grid() {
+        assert_eq!(LaunchQuantize::Beat.next_launch(3.0, 4.0), Some(3.0));
+        assert_eq!(LaunchQuantize::Bar.next_launch(8.0, 4.0), Some(8.0));
+        assert_eq!(LaunchQuantize::Bar.next_launch(8.01, 4.0), Some(12.0));
+        assert_eq!(LaunchQuantize::Bars(2).next_launch(0.0, 4.0), Some(0.0));
+        assert_eq!(LaunchQuantize::None.next_launch(8.0, 4.0), None);
+        assert_eq!(LaunchQuantize::None.next_launch(8.5, 4.0), None);
+    }
+
+    #[test]
+    fn next_launch_follows_the_quantum() {
+        // Bars of three beats
+        assert_eq!(LaunchQuantize::Beat.next_launch(4.2, 3.0), Some(5.0));
+        assert_eq!(LaunchQuantize::Bar.next_launch(4.2, 3.0), Some(6.0));
+        assert_eq!(LaunchQuantize::Bars(2).next_launch(4.2, 3.0), Some(6.0));
+        assert_eq!(LaunchQuantize::Bars(2).next_launch(6.5, 3.0), Some(12.0));
+        assert_eq!(LaunchQuantize::Bars(4).next_launch(12.0, 3.0), Some(12.0));
+    }
+
+    #[test]
+    fn next_launch_before_the_first_beat() {
+        // Beats before 0, as during a count-in
+        assert_eq!(LaunchQuantize::Beat.next_launch(-1.5, 4.0), Some(-1.0));
+        assert_eq!(LaunchQuantize::Bar.next_launch(-1.5, 4.0), Some(0.0));
+        assert_eq!(LaunchQuantize::Bar.next_launch(-4.0, 4.0), Some(-4.0));
+        assert_eq!(LaunchQuantize::Bars(2).next_launch(-5.0, 3.0), Some(0.0));
+        assert_eq!(LaunchQuantize::Bars(2).next_launch(-7.0, 3.0), Some(-6.0));
+    }
+
+    /// Write a 64x64 FFV1 Matroska file with a frame starting at each of `starts`, in
+    /// milliseconds, lasting until the next or `end`. Frame `i` has every luma sample at `luma(i)`.
+    fn write_vfr_mkv(path: &Path, starts: &[i64], end: i64) -> Result<(), ffmpeg::Error> {
+        let time_base = Rational(1, 1000);
+        let mut output = format::output(&path)?;
+        let codec = encoder::find_by_name("ffv1").ok_or(ffmpeg::Error::EncoderNotFound)?;
+        let mut stream = output.add_stream(codec)?;
+        let mut encoder = codec::context::Context::from_parameters(stream.parameters())?.encoder().video()?;
+        encoder.set_width(64);
+        encoder.set_height(64);
+        encoder.set_format(ffmpeg::format::Pixel::YUV420P);
+        encoder.set_time_base(time_base);
+        encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        let mut encoder = encoder.open_as(codec)?;
+        stream.set_parameters(&encoder);
+        output.write_header()?;
+        let stream_time_base = output.stream(0).ok_or(ffmpeg::Error::StreamNotFound)?.time_base();
+
+        let mut durations = starts.windows(2).map(|pair| pair[1] - pair[0]).chain([end - starts[starts.len() - 1]]);
+        let mut packet = ffmpeg::Packet::empty();
+        let mut write_packets = |encoder: &mut encoder::Video, output: &mut format::context::Output| {
+            while encoder.receive_packet(&mut packet).is_ok() {
+                packet.set_stream(0);
+                packet.set_duration(durations.next().unwrap_or(0));
+                packet.rescale_ts(time_base, stream_time_base);
+                packet.write_interleaved(output)?;
+            }
+            Ok::<_, ffmpeg::Error>(())
+        };
+        for (i, start) in starts.iter().enumerate() {
+            let mut frame = ffmpeg::util::frame::Video::new(ffmpeg::format::Pixel::YUV420P, 64, 64);
+            frame.data_mut(0).fill(luma(i));
+            frame.data_mut(1).fill(128);
+            frame.data_mut(2).fill(128);
+            frame.set_pts(Some(*start));
+            encoder.send_frame(&frame)?;
+            write_packets(&mut encoder, &mut output)?;
+        }
+        encoder.send_eof()?;
+        write_packets(&mut encoder, &mut output)?;
+        output.write_trailer()
+    }
+
+    fn luma(frame: usize) -> u8 {
+        16 + frame as u8 * 40
+    }
+
+    #[test]
+    fn variable_frame_rate_mkv_shows_the_frame_on_screen_at_each_position() {
+        ffmpeg::init().unwrap();
+        let path = std::env::temp_dir().join(format!("voop-vfr-{}.mkv", std::process::id()));
+        write_vfr_mkv(&path, &[0, 40, 120, 130, 300], 500).unwrap();
+
+        {
+            let ctx = media::open(&path).unwrap();
+            let stream = ctx.streams().best(ffmpeg::media::Type::Video).unwrap();
+            assert_eq!(stream.frames(), 0, "Matroska has no frame count");
+        }
+        let mut clip = Clip::new(&path, FrameCache::shared(64 * 1024 * 1024)).unwrap();
+        assert!((clip.duration_secs() - 0.5).abs() < 0.001, "duration {}", clip.duration_secs());
+
+        // Seconds into the clip, with the frame on screen then; going back makes the clip seek
+        let expected = [(0.0, 0), (0.02, 0), (0.08, 1), (0.125, 2), (0.2, 3), (0.45, 4), (0.5, 4), (0.08, 1), (0.0, 0)];
+        for (secs, frame) in expected {
+            let position = (secs / clip.duration_secs()) as f32;
+            let picture = clip.play_video_at_position(position);
+            assert_eq!(picture.data(0)[0], luma(frame), "frame at {secs} s");
+        }
+        std::fs::remove_file(&path).ok();
+    }
 }
@@ -0,0 +1,188 @@
+// Blends one layer over everything below it, mid-transition between two clips if it is
+// changing. The backdrop holds premultiplied colour, layers come from the decoder with straight alpha.
+@group(0) @binding(0) var samp     : sampler;
+@group(0) @binding(1) var backdrop : texture_2d<f32>;
+
+// How to read a picture's planes, matches `PictureParams` in compositor.rs
+struct PictureParams {
+    format   : u32,
+    // luma weights of red and blue, which set the YUV matrix
+    kr       : f32,
+    kb       : f32,
+    // samples less the offset, times the scale, give luma from 0.0 to 1.0 and chroma
+    // from -0.5 to 0.5 whatever the range and depth
+    y_offset : f32,
+    y_scale  : f32,
+    c_offset : f32,
+    c_scale  : f32,
+    _padding : f32,
+};
+
+// Matches `LayerParams` in compositor.rs
+struct LayerParams {
+    // size of the pictures relative to the screen once fitted to their aspect ratio
+    scale          : vec2<f32>,
+    outgoing_scale : vec2<f32>,
+    opacity        : f32,
+    blend          : u32,
+    transition     : u32,
+    // 0.0 to 1.0 through the transition from the outgoing picture
+    progress       : f32,
+    incoming       : PictureParams,
+    outgoing       : PictureParams,
+};
+@group(1) @binding(0) var layer_tex : texture_2d<f32>;
+@group(1) @binding(1) var<uniform> layer : LayerParams;
+@group(1) @binding(2) var outgoing_tex : texture_2d<f32>;
+// Luma wipes reveal the new picture where the matte is darkest first
+@group(1) @binding(3) var matte_tex : texture_2d<f32>;
+// Chroma planes of YUV pictures, the first plane being the textures above
+@group(1) @binding(4) var layer_tex_1 : texture_2d<f32>;
+@group(1) @binding(5) var layer_tex_2 : texture_2d<f32>;
+@group(1) @binding(6) var outgoing_tex_1 : texture_2d<f32>;
+@group(1) @binding(7) var outgoing_tex_2 : texture_2d<f32>;
+
+// Matches `BlendMode` in compositor.rs
+const BLEND_NORMAL     : u32 = 0u;
+const BLEND_ADD        : u32 = 1u;
+const BLEND_MULTIPLY   : u32 = 2u;
+const BLEND_SCREEN     : u32 = 3u;
+const BLEND_DIFFERENCE : u32 = 4u;
+const BLEND_LUMA_KEY   : u32 = 5u;
+
+// Matches `Layout::code` in yuv.rs
+const LAYOUT_RGBA   : u32 = 0u;
+const LAYOUT_NV12   : u32 = 1u;
+
+// Matches `TransitionKind` in transition.rs
+const TRANSITION_CUT          : u32 = 0u;
+const TRANSITION_CROSSFADE    : u32 = 1u;
+const TRANSITION_DIP_TO_BLACK : u32 = 2u;
+const TRANSITION_WIPE         : u32 = 3u;
+const TRANSITION_LUMA_WIPE    : u32 = 4u;
+
+// Luma below the threshold is keyed out, fading in over the softness above it
+const KEY_THRESHOLD : f32 = 0.1;
+const KEY_SOFTNESS  : f32 = 0.15;
+
+// Width of the edge of a wipe, as a fraction of the picture or of the matte's range
+const WIPE_SOFTNESS : f32 = 0.05;
+
+const LUMA : vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+struct VSOut {
+    @builtin(position) pos : vec4<f32>,
+    @location(0)        uv  : vec2<f32>,
+};
+
+@vertex
+fn vs_main(@location(0) in_pos : vec2<f32>,
+           @location(1) in_uv  : vec2<f32>) -> VSOut {
+    var out : VSOut;
+    out.pos = vec4<f32>(in_pos, 0.0, 1.0);
+    out.uv  = in_uv;
+    return out;
+}
+
+// Separable blend of straight colours, as in the W3C compositing spec
+fn blend(mode : u32, cs : vec3<f32>, cb : vec3<f32>) -> vec3<f32> {
+    switch mode {
+        case BLEND_MULTIPLY:   { return cs * cb; }
+        case BLEND_SCREEN:     { return cs + cb - cs * cb; }
+        case BLEND_DIFFERENCE: { return abs(cs - cb); }
+        default:               { return cs; }
+    }
+}
+
+fn srgb_to_linear(c : vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+// Linear colour with straight alpha of a picture in any layout. RGBA textures are sRGB
+// and linearized by the sampler, YUV is turned into sRGB first.
+fn picture(plane_0 : texture_2d<f32>, plane_1 : texture_2d<f32>, plane_2 : texture_2d<f32>,
+           p : PictureParams, uv : vec2<f32>) -> vec4<f32> {
+    let first = textureSample(plane_0, samp, uv);
+    if (p.format == LAYOUT_RGBA) {
+        return first;
+    }
+    var chroma = textureSample(plane_1, samp, uv).rg;
+    if (p.format != LAYOUT_NV12) {
+        chroma = vec2<f32>(chroma.r, textureSample(plane_2, samp, uv).r);
+    }
+    let y = (first.r - p.y_offset) * p.y_scale;
+    let u = (chroma.x - p.c_offset) * p.c_scale;
+    let v = (chroma.y - p.c_offset) * p.c_scale;
+    let kg = 1.0 - p.kr - p.kb;
+    let rgb = vec3<f32>(
+        y + 2.0 * (1.0 - p.kr) * v,
+        y - (2.0 * p.kb * (1.0 - p.kb) * u + 2.0 * p.kr * (1.0 - p.kr) * v) / kg,
+        y + 2.0 * (1.0 - p.kb) * u,
+    );
+    return vec4<f32>(srgb_to_linear(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0))), 1.0);
+}
+
+// A picture fitted to the screen, premultiplied. Letterboxing around it stays transparent.
+fn fitted(color : vec4<f32>, picture_uv : vec2<f32>) -> vec4<f32> {
+    let inside = all(picture_uv >= vec2<f32>(0.0)) && all(picture_uv <= vec2<f32>(1.0));
+    let a = color.a * select(0.0, 1.0, inside);
+    return vec4<f32>(color.rgb * a, a);
+}
+
+// How much of the new picture shows at `position` along a wipe, 0.0 to 1.0
+fn wipe(position : f32) -> f32 {
+    let edge = layer.progress * (1.0 + WIPE_SOFTNESS);
+    return 1.0 - smoothstep(edge - WIPE_SOFTNESS, edge, position);
+}
+
+// The layer's picture part way through its transition, premultiplied
+fn transitioned(uv : vec2<f32>) -> vec4<f32> {
+    let incoming_uv = (uv - 0.5) / layer.scale + 0.5;
+    let incoming = fitted(picture(layer_tex, layer_tex_1, layer_tex_2, layer.incoming, incoming_uv), incoming_uv);
+    if (layer.transition == TRANSITION_CUT) {
+        return incoming;
+    }
+    let outgoing_uv = (uv - 0.5) / layer.outgoing_scale + 0.5;
+    let outgoing = fitted(picture(outgoing_tex, outgoing_tex_1, outgoing_tex_2, layer.outgoing, outgoing_uv), outgoing_uv);
+    let p = layer.progress;
+    switch layer.transition {
+        case TRANSITION_DIP_TO_BLACK: {
+            // Down to black over the first half, up into the new picture over the second
+            if (p < 0.5) {
+                return vec4<f32>(outgoing.rgb * (1.0 - 2.0 * p), outgoing.a);
+            }
+            return vec4<f32>(incoming.rgb * (2.0 * p - 1.0), incoming.a);
+        }
+        case TRANSITION_WIPE: {
+            return mix(outgoing, incoming, wipe(uv.x));
+        }
+        case TRANSITION_LUMA_WIPE: {
+            let matte = dot(textureSample(matte_tex, samp, uv).rgb, LUMA);
+            return mix(outgoing, incoming, wipe(matte));
+        }
+        default: {
+            return mix(outgoing, incoming, p);
+        }
+    }
+}
+
+@fragment
+fn fs_main(in : VSOut) -> @location(0) vec4<f32> {
+    let b = textureSample(backdrop, samp, in.uv);
+    let picture = transitioned(in.uv);
+    let s = vec4<f32>(select(vec3<f32>(0.0), picture.rgb / picture.a, picture.a > 0.0), picture.a);
+
+    var a = s.a * layer.opacity;
+    if (layer.blend == BLEND_LUMA_KEY) {
+        let luma = dot(s.rgb, LUMA);
+        a = a * smoothstep(KEY_THRESHOLD, KEY_THRESHOLD + KEY_SOFTNESS, luma);
+    }
+    let alpha = a + b.a * (1.0 - a);
+
+    if (layer.blend == BLEND_ADD) {
+        return vec4<f32>(b.rgb + s.rgb * a, alpha);
+    }
+    let cb = select(vec3<f32>(0.0), b.rgb / b.a, b.a > 0.0);
+    let rgb = s.rgb * a * (1.0 - b.a) + b.rgb * (1.0 - a) + a * b.a * blend(layer.blend, s.rgb, cb);
+    return vec4<f32>(rgb, alpha);
+}
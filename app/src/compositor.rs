@@ -0,0 +1,697 @@
+use crate::transition::TransitionKind;
+use crate::vertex::{Quad, Vertex};
+use crate::yuv::{Colors, Layout};
+use serde::{Deserialize, Serialize};
+
+/// Format layers are composited in, linear and with headroom so stacked adds don't band
+pub const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// How a layer combines with the layers below it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Add,
+    Multiply,
+    Screen,
+    Difference,
+    /// dark parts of the layer are see-through
+    LumaKey,
+}
+
+impl BlendMode {
+    /// In the order the blend key cycles through them
+    const ALL: [BlendMode; 6] = [
+        BlendMode::Normal,
+        BlendMode::Add,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Difference,
+        BlendMode::LumaKey,
+    ];
+
+    pub fn parse(text: &str) -> Option<BlendMode> {
+        BlendMode::ALL.into_iter().find(|mode| mode.name() == text)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BlendMode::Normal => "normal",
+            BlendMode::Add => "add",
+            BlendMode::Multiply => "multiply",
+            BlendMode::Screen => "screen",
+            BlendMode::Difference => "difference",
+            BlendMode::LumaKey => "luma_key",
+        }
+    }
+
+    pub fn next(self) -> BlendMode {
+        let index = BlendMode::ALL.iter().position(|mode| *mode == self).unwrap_or(0);
+        BlendMode::ALL[(index + 1) % BlendMode::ALL.len()]
+    }
+
+    /// Number composite.wgsl switches on
+    fn code(self) -> u32 {
+        self as u32
+    }
+}
+
+/// How composite.wgsl reads a picture, laid out like `PictureParams` there
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct PictureParams {
+    /// see [`Layout::code`]
+    format: u32,
+    /// luma weights of red and blue
+    kr: f32,
+    kb: f32,
+    /// offsets and scales of luma and chroma samples, see [`Colors::ranges`]
+    y_offset: f32,
+    y_scale: f32,
+    c_offset: f32,
+    c_scale: f32,
+    _padding: f32,
+}
+
+/// Per-layer values composite.wgsl reads, laid out like `LayerParams` there
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LayerParams {
+    scale: [f32; 2],
+    outgoing_scale: [f32; 2],
+    opacity: f32,
+    blend: u32,
+    transition: u32,
+    progress: f32,
+    incoming: PictureParams,
+    outgoing: PictureParams,
+}
+
+/// Textures sized to the video or image they hold, one per plane
+struct Picture {
+    planes: Vec<(wgpu::Texture, wgpu::TextureView)>,
+    width: u32,
+    height: u32,
+    layout: Layout,
+    colors: Colors,
+    /// 10-bit samples are kept in 16-bit textures rather than cut down to 8 bits
+    deep: bool,
+}
+
+impl Picture {
+    fn new(device: &wgpu::Device, width: u32, height: u32, layout: Layout, deep: bool) -> Picture {
+        let planes = (0..layout.plane_count())
+            .map(|index| {
+                let (plane_width, plane_height) = layout.plane_size(index, width, height);
+                let format = match (layout, index) {
+                    (Layout::Rgba, _) => wgpu::TextureFormat::Rgba8UnormSrgb,
+                    (Layout::Nv12, 1) => wgpu::TextureFormat::Rg8Unorm,
+                    (Layout::Yuv422P10, _) if deep => wgpu::TextureFormat::R16Unorm,
+                    _ => wgpu::TextureFormat::R8Unorm,
+                };
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    size: wgpu::Extent3d { width: plane_width, height: plane_height, depth_or_array_layers: 1 },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                    label: Some("layer_texture"),
+                    view_formats: &[],
+                });
+                let view = texture.create_view(&Default::default());
+                (texture, view)
+            })
+            .collect();
+        Picture { planes, width, height, layout, colors: Colors::RGB, deep }
+    }
+
+    fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, image: Pixels) -> Picture {
+        let mut picture = Picture::new(device, image.width, image.height, image.layout, false);
+        picture.colors = image.colors;
+        image.write_to(queue, &picture);
+        picture
+    }
+
+    /// Replace the pixels, making new textures if the size or layout changed. Returns whether it did.
+    fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, image: Pixels, deep: bool) -> bool {
+        let remade = self.width != image.width
+            || self.height != image.height
+            || self.layout != image.layout
+            || self.deep != deep;
+        if remade {
+            *self = Picture::new(device, image.width, image.height, image.layout, deep);
+        }
+        self.colors = image.colors;
+        image.write_to(queue, self);
+        remade
+    }
+
+    /// View of plane `index`, or of the last plane for layouts with fewer
+    fn view(&self, index: usize) -> &wgpu::TextureView {
+        &self.planes[index.min(self.planes.len() - 1)].1
+    }
+
+    fn params(&self) -> PictureParams {
+        let (kr, kb) = self.colors.matrix.kr_kb();
+        let [y_offset, y_scale, c_offset, c_scale] = match self.deep && self.layout.bits() > 8 {
+            true => self.colors.ranges(self.layout.bits(), 16),
+            false => self.colors.ranges(8, 8),
+        };
+        PictureParams { format: self.layout.code(), kr, kb, y_offset, y_scale, c_offset, c_scale, _padding: 0.0 }
+    }
+
+    /// Size relative to a target of this size once fitted inside it, keeping the aspect ratio
+    fn scale(&self, target_width: u32, target_height: u32) -> [f32; 2] {
+        let video_aspect = self.width as f32 / self.height as f32;
+        let target_aspect = target_width as f32 / target_height as f32;
+        if video_aspect > target_aspect {
+            // Wider than the target - fit to width, letterbox top/bottom
+            [1.0, target_aspect / video_aspect]
+        } else {
+            // Taller than the target - fit to height, pillarbox left/right
+            [video_aspect / target_aspect, 1.0]
+        }
+    }
+}
+
+/// A track's pictures and how they are mixed. While it changes clips it holds the
+/// outgoing clip's picture too.
+struct LayerTexture {
+    incoming: Picture,
+    outgoing: Picture,
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    opacity: f32,
+    blend: BlendMode,
+    visible: bool,
+    /// the transition under way and how far through it is
+    transition: Option<(TransitionKind, f32)>,
+}
+
+impl LayerTexture {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, matte: &Picture, incoming: Picture) -> LayerTexture {
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("layer_params_buffer"),
+            size: std::mem::size_of::<LayerParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let outgoing = Picture::new(device, 1, 1, Layout::Rgba, false);
+        let bind_group = layer_bind_group(device, layout, &incoming, &outgoing, matte, &params_buffer);
+        LayerTexture {
+            incoming,
+            outgoing,
+            params_buffer,
+            bind_group,
+            opacity: 1.0,
+            blend: BlendMode::Normal,
+            visible: false,
+            transition: None,
+        }
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, matte: &Picture) {
+        self.bind_group = layer_bind_group(device, layout, &self.incoming, &self.outgoing, matte, &self.params_buffer);
+    }
+
+    fn params(&self, target_width: u32, target_height: u32) -> LayerParams {
+        let (transition, progress) = self.transition.unwrap_or((TransitionKind::Cut, 1.0));
+        LayerParams {
+            scale: self.incoming.scale(target_width, target_height),
+            outgoing_scale: self.outgoing.scale(target_width, target_height),
+            opacity: self.opacity,
+            blend: self.blend.code(),
+            transition: transition.code(),
+            progress,
+            incoming: self.incoming.params(),
+            outgoing: self.outgoing.params(),
+        }
+    }
+}
+
+fn layer_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    incoming: &Picture,
+    outgoing: &Picture,
+    matte: &Picture,
+    params_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    fn view(binding: u32, view: &wgpu::TextureView) -> wgpu::BindGroupEntry<'_> {
+        wgpu::BindGroupEntry { binding, resource: wgpu::BindingResource::TextureView(view) }
+    }
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            view(0, incoming.view(0)),
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: params_buffer.as_entire_binding(),
+            },
+            view(2, outgoing.view(0)),
+            view(3, matte.view(0)),
+            view(4, incoming.view(1)),
+            view(5, incoming.view(2)),
+            view(6, outgoing.view(1)),
+            view(7, outgoing.view(2)),
+        ],
+        label: Some("layer_bind_group"),
+    })
+}
+
+/// Pixels of a picture as laid out in memory, one slice per plane with rows `stride` bytes apart
+pub struct Pixels<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub layout: Layout,
+    pub colors: Colors,
+    /// data and stride of each plane
+    pub planes: Vec<(&'a [u8], u32)>,
+}
+
+impl<'a> Pixels<'a> {
+    /// A still image, tightly packed
+    pub fn rgba(width: u32, height: u32, data: &'a [u8]) -> Pixels<'a> {
+        Pixels { width, height, layout: Layout::Rgba, colors: Colors::RGB, planes: vec![(data, width * 4)] }
+    }
+
+    fn write_to(&self, queue: &wgpu::Queue, picture: &Picture) {
+        for (index, ((texture, _), (data, stride))) in picture.planes.iter().zip(&self.planes).enumerate() {
+            let (width, height) = self.layout.plane_size(index, self.width, self.height);
+            let row_size = width * self.layout.bytes_per_pixel(index);
+            let rows = (0..height).map(|y| {
+                let row_start = (y * stride) as usize;
+                &data[row_start..row_start + row_size as usize]
+            });
+            // Rows padded by the decoder have to be packed first, and 10-bit samples cut down
+            // to 8 bits where there are no 16-bit textures
+            let packed: Vec<u8>;
+            let (data, bytes_per_row) = if texture.format() == wgpu::TextureFormat::R8Unorm && self.layout.bits() > 8 {
+                packed = rows
+                    .flat_map(|row| row.chunks_exact(2).map(|sample| (u16::from_le_bytes([sample[0], sample[1]]) >> 2) as u8))
+                    .collect();
+                (&packed[..], width)
+            } else if *stride == row_size {
+                (*data, row_size)
+            } else {
+                packed = rows.flatten().copied().collect();
+                (&packed[..], row_size)
+            };
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                data,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+                wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            );
+        }
+    }
+}
+
+/// A texture a pass renders into, which the next pass samples
+pub struct Target {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Target {
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, sampler: &wgpu::Sampler, width: u32, height: u32) -> Target {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TARGET_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            label: Some("composite_target"),
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+            ],
+            label: Some("composite_target_bind_group"),
+        });
+        Target { texture, view, bind_group }
+    }
+}
+
+/// Stacks layers bottom to top, each blended onto the ones below it with premultiplied alpha.
+/// Passes ping-pong between two targets, the last one written holds the finished picture.
+pub struct Compositor {
+    sampler: wgpu::Sampler,
+    /// sampler and texture, shared by the targets and by whatever samples the result
+    target_layout: wgpu::BindGroupLayout,
+    layer_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    targets: [Target; 2],
+    width: u32,
+    height: u32,
+    layers: Vec<LayerTexture>,
+    /// shown instead of the layers while set
+    slate: Option<LayerTexture>,
+    /// shown on top of everything else while set, effects included
+    overlay: Option<LayerTexture>,
+    overlay_target: Target,
+    /// image luma wipes follow
+    matte: Picture,
+    /// whether the device has 16-bit textures for 10-bit video
+    deep_samples: bool,
+}
+
+impl Compositor {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32, layer_count: usize) -> Compositor {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let target_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(1),
+            ],
+            label: Some("texture_bind_group_layout"),
+        });
+        let layer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(2),
+                texture_entry(3),
+                texture_entry(4),
+                texture_entry(5),
+                texture_entry(6),
+                texture_entry(7),
+            ],
+            label: Some("layer_bind_group_layout"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("composite_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("composite.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("composite_pipeline_layout"),
+            bind_group_layouts: &[&target_layout, &layer_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("composite_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[Vertex::layout()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                // The shader does the blending itself, reading the backdrop from the other target
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: TARGET_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let (width, height) = (width.max(1), height.max(1));
+        let targets = [
+            Target::new(device, &target_layout, &sampler, width, height),
+            Target::new(device, &target_layout, &sampler, width, height),
+        ];
+        let overlay_target = Target::new(device, &target_layout, &sampler, width, height);
+        let matte = default_matte(device, queue);
+        let layers = (0..layer_count)
+            .map(|_| LayerTexture::new(device, &layer_layout, &matte, Picture::new(device, 1, 1, Layout::Rgba, false)))
+            .collect();
+        Compositor {
+            sampler,
+            target_layout,
+            layer_layout,
+            pipeline,
+            targets,
+            width,
+            height,
+            layers,
+            slate: None,
+            overlay: None,
+            overlay_target,
+            matte,
+            deep_samples: device.features().contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM),
+        }
+    }
+
+    /// Layout of the bind group [`Compositor::render`] returns
+    pub fn output_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.target_layout
+    }
+
+    /// Composite at a new output size
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.width = width.max(1);
+        self.height = height.max(1);
+        self.targets = [
+            Target::new(device, &self.target_layout, &self.sampler, self.width, self.height),
+            Target::new(device, &self.target_layout, &self.sampler, self.width, self.height),
+        ];
+        self.overlay_target = Target::new(device, &self.target_layout, &self.sampler, self.width, self.height);
+    }
+
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, layer: usize, image: Pixels) {
+        if let Some(texture) = self.layers.get_mut(layer) {
+            if texture.incoming.upload(device, queue, image, self.deep_samples) {
+                texture.rebind(device, &self.layer_layout, &self.matte);
+            }
+        }
+    }
+
+    /// Show the frame of the clip a layer is transitioning away from
+    pub fn upload_outgoing(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, layer: usize, image: Pixels) {
+        if let Some(texture) = self.layers.get_mut(layer) {
+            if texture.outgoing.upload(device, queue, image, self.deep_samples) {
+                texture.rebind(device, &self.layer_layout, &self.matte);
+            }
+        }
+    }
+
+    /// Start a transition on a layer: the picture it shows now becomes the outgoing one
+    pub fn begin_transition(&mut self, device: &wgpu::Device, layer: usize, kind: TransitionKind) {
+        if let Some(texture) = self.layers.get_mut(layer) {
+            std::mem::swap(&mut texture.incoming, &mut texture.outgoing);
+            texture.rebind(device, &self.layer_layout, &self.matte);
+            texture.transition = Some((kind, 0.0));
+        }
+    }
+
+    /// Move a layer's transition along, or end it with `None`
+    pub fn set_transition(&mut self, layer: usize, transition: Option<(TransitionKind, f32)>) {
+        if let Some(texture) = self.layers.get_mut(layer) {
+            texture.transition = transition.map(|(kind, progress)| (kind, progress.clamp(0.0, 1.0)));
+        }
+    }
+
+    /// Use `image` for luma wipes
+    pub fn set_matte(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, image: Pixels) {
+        self.matte = Picture::from_image(device, queue, image);
+        for texture in &mut self.layers {
+            texture.rebind(device, &self.layer_layout, &self.matte);
+        }
+    }
+
+    /// Whether a layer takes part, hidden ones are skipped entirely
+    pub fn set_visible(&mut self, layer: usize, visible: bool) {
+        if let Some(texture) = self.layers.get_mut(layer) {
+            texture.visible = visible;
+        }
+    }
+
+    pub fn set_opacity(&mut self, layer: usize, opacity: f32) {
+        if let Some(texture) = self.layers.get_mut(layer) {
+            texture.opacity = opacity.clamp(0.0, 1.0);
+        }
+    }
+
+    pub fn set_blend(&mut self, layer: usize, blend: BlendMode) {
+        if let Some(texture) = self.layers.get_mut(layer) {
+            texture.blend = blend;
+        }
+    }
+
+    /// Show a still image over black instead of the layers
+    pub fn show_slate(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, image: Pixels) {
+        let picture = Picture::from_image(device, queue, image);
+        let mut slate = LayerTexture::new(device, &self.layer_layout, &self.matte, picture);
+        slate.visible = true;
+        self.slate = Some(slate);
+    }
+
+    pub fn hide_slate(&mut self) {
+        self.slate = None;
+    }
+
+    /// Show an image with straight alpha over the finished picture, see [`Compositor::render_overlay`]
+    pub fn show_overlay(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, image: Pixels) {
+        let picture = Picture::from_image(device, queue, image);
+        let mut overlay = LayerTexture::new(device, &self.layer_layout, &self.matte, picture);
+        overlay.visible = true;
+        self.overlay = Some(overlay);
+    }
+
+    pub fn hide_overlay(&mut self) {
+        self.overlay = None;
+    }
+
+    /// Record the passes compositing every visible layer, returning the finished picture
+    pub fn render(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, quad: &Quad) -> &wgpu::BindGroup {
+        // Everything is blended over opaque black
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("composite_clear_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.targets[0].view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        let layers: Vec<&LayerTexture> = match &self.slate {
+            Some(slate) => vec![slate],
+            None => self.layers.iter().filter(|layer| layer.visible && layer.opacity > 0.0).collect(),
+        };
+        let mut current = 0;
+        for layer in layers {
+            let params = layer.params(self.width, self.height);
+            queue.write_buffer(&layer.params_buffer, 0, bytemuck::bytes_of(&params));
+
+            let next = 1 - current;
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("composite_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.targets[next].view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, &self.targets[current].bind_group, &[]);
+            rpass.set_bind_group(1, &layer.bind_group, &[]);
+            quad.draw(&mut rpass);
+            current = next;
+        }
+        &self.targets[current].bind_group
+    }
+
+    /// Record the pass drawing the overlay over `input`, a picture laid out like the one
+    /// [`Compositor::render`] returns. Returns `input` itself when there is no overlay.
+    pub fn render_overlay<'a>(
+        &'a self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        quad: &Quad,
+        input: &'a wgpu::BindGroup,
+    ) -> &'a wgpu::BindGroup {
+        let Some(overlay) = &self.overlay else {
+            return input;
+        };
+        let params = overlay.params(self.width, self.height);
+        queue.write_buffer(&overlay.params_buffer, 0, bytemuck::bytes_of(&params));
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("overlay_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.overlay_target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, input, &[]);
+        rpass.set_bind_group(1, &overlay.bind_group, &[]);
+        quad.draw(&mut rpass);
+        &self.overlay_target.bind_group
+    }
+}
+
+/// A left to right gradient, so a luma wipe without a matte of its own is a soft wipe
+fn default_matte(device: &wgpu::Device, queue: &wgpu::Queue) -> Picture {
+    let data: Vec<u8> = (0..=255u8).flat_map(|luma| [luma, luma, luma, 255]).collect();
+    Picture::from_image(device, queue, Pixels::rgba(256, 1, &data))
+}
+
+pub fn texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    }
+}
@@ -0,0 +1,79 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Config file read when `--config` is not given
+pub const DEFAULT_CONFIG_PATH: &str = "voop.toml";
+
+/// Settings read from a TOML file. Command line flags take precedence.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// tempo in BPM proposed on start
+    pub bpm: Option<f64>,
+    /// beats per bar
+    pub quantum: Option<f64>,
+    /// frame rate of the `HH:MM:SS:FF` offsets below, 25 if not set
+    pub timecode_fps: Option<f64>,
+    /// SMPTE time each clip starts at when chasing timecode, by file name,
+    /// as `HH:MM:SS:FF` or seconds
+    pub timecode_offsets: HashMap<String, String>,
+    /// when clips start after being switched to: `none`, `beat`, `bar` or a number of bars
+    pub launch_quantize: Option<String>,
+    /// launch quantization of single clips, by file name
+    pub clip_launch_quantize: HashMap<String, String>,
+    /// how clips take over their track: `cut`, `crossfade`, `dip_to_black`, `wipe` or `luma_wipe`
+    pub transition: Option<String>,
+    /// `N beats` or `N bars`
+    pub transition_length: Option<String>,
+    /// greyscale image luma wipes follow, darkest parts first
+    pub luma_matte: Option<PathBuf>,
+    /// folder of WGSL effects added to the built-in ones
+    pub shader_dir: Option<PathBuf>,
+    /// globs of the files in a media folder to play, everything ffmpeg reads if empty
+    pub include: Vec<String>,
+    /// globs of the files in a media folder to leave out
+    pub exclude: Vec<String>,
+    /// effects turned on at start, by name
+    pub effects: Vec<String>,
+    /// starting values of effect parameters, by effect and parameter name
+    pub effect_params: HashMap<String, HashMap<String, f32>>,
+    /// UDP port to listen for OSC on
+    pub osc_port: Option<u16>,
+    /// MIDI input port of the controller mapped to actions, matched by name
+    pub midi_control: Option<String>,
+    /// file MIDI mappings are read from and learned into
+    pub midi_map: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        toml::from_str(&text).map_err(ConfigError::Parse)
+    }
+
+    /// The config at `path`, or at [`DEFAULT_CONFIG_PATH`] if that exists, else defaults
+    pub fn load_or_default(path: Option<&Path>) -> Result<Config, ConfigError> {
+        match path {
+            Some(path) => Config::load(path),
+            None if Path::new(DEFAULT_CONFIG_PATH).is_file() => Config::load(Path::new(DEFAULT_CONFIG_PATH)),
+            None => Ok(Config::default()),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use crate::compositor::BlendMode;
+
+/// Something a remote controller asked the app to do
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    NextScene,
+    PreviousScene,
+    /// launch the scene at this index, counted from 0
+    LaunchScene(usize),
+    /// launch one slot of the grid, both counted from 0
+    LaunchClip { track: usize, scene: usize },
+    /// stop the track at this index, counted from 0
+    StopTrack(usize),
+    ToggleFullscreen,
+    SetFullscreen(bool),
+    /// 0.0 is black, 1.0 fully visible
+    SetOpacity(f32),
+    /// 0.0 to 1.0, for the track at this index
+    SetTrackOpacity { track: usize, opacity: f32 },
+    SetBlendMode { track: usize, mode: BlendMode },
+    /// 0.0 is all of side A, 1.0 all of side B
+    SetCrossfader(f32),
+    /// turn an effect on if it is off and off if it is on
+    ToggleEffect(String),
+    SetEffect { effect: String, enabled: bool },
+    /// 0.0 to 1.0
+    SetEffectParam { effect: String, param: String, value: f32 },
+    /// send the current state back to remote controllers
+    ReportState,
+}
@@ -0,0 +1,467 @@
+use crate::compositor::{texture_entry, Target, TARGET_FORMAT};
+use crate::isf;
+use crate::vertex::{Quad, Vertex};
+use std::collections::HashMap;
+use std::time::Instant;
+use transport::TransportSnapshot;
+
+/// Most parameters an effect can have
+pub const MAX_PARAMS: usize = 16;
+
+const PRELUDE: &str = include_str!("effects/prelude.wgsl");
+
+/// Stands in for an effect that doesn't compile, leaving the picture as it is
+const PASSTHROUGH: &str = "fn effect(uv : vec2<f32>) -> vec4<f32> { return input(uv); }";
+
+/// Effects that come with voop, in the order they are chained
+const BUILT_IN: &[(&str, &str)] = &[
+    ("rgb_split", include_str!("effects/rgb_split.wgsl")),
+    ("kaleidoscope", include_str!("effects/kaleidoscope.wgsl")),
+    ("mirror", include_str!("effects/mirror.wgsl")),
+    ("pixelate", include_str!("effects/pixelate.wgsl")),
+    ("invert", include_str!("effects/invert.wgsl")),
+    ("posterize", include_str!("effects/posterize.wgsl")),
+    ("feedback", include_str!("effects/feedback.wgsl")),
+];
+
+/// Names of the effects that come with voop, in the order they are chained
+pub fn built_in_names() -> impl Iterator<Item = &'static str> {
+    BUILT_IN.iter().map(|(name, _)| *name)
+}
+
+/// Values every effect pass reads, laid out like `Musical` in effects/prelude.wgsl
+/// and effects/isf_prelude.glsl
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct Musical {
+    time: f32,
+    beat: f32,
+    bar: f32,
+    phase: f32,
+    tempo: f32,
+    quantum: f32,
+    width: f32,
+    height: f32,
+    /// seconds since the previous frame
+    delta: f32,
+    /// frames since voop started
+    frame: f32,
+    _padding: [f32; 2],
+    params: [f32; MAX_PARAMS],
+}
+
+/// A value an effect can be tuned with, 0.0 to 1.0
+#[derive(Clone, Debug)]
+pub struct EffectParam {
+    pub name: String,
+    pub value: f32,
+}
+
+/// Parameters listed on the `// params:` line of an effect's source, with their defaults
+fn parse_params(source: &str) -> Vec<EffectParam> {
+    let Some(line) = source.lines().find_map(|line| line.trim().strip_prefix("// params:")) else {
+        return Vec::new();
+    };
+    line.split(',')
+        .filter_map(|param| {
+            let (name, value) = param.split_once('=').unwrap_or((param, "0"));
+            let name = name.trim();
+            (!name.is_empty()).then(|| EffectParam {
+                name: name.to_string(),
+                value: value.trim().parse::<f32>().unwrap_or(0.0).clamp(0.0, 1.0),
+            })
+        })
+        .take(MAX_PARAMS)
+        .collect()
+}
+
+/// One pass of the chain
+struct Effect {
+    name: String,
+    params: Vec<EffectParam>,
+    enabled: bool,
+    pipeline: wgpu::RenderPipeline,
+    /// why the effect's own source didn't compile, it passes the picture through if set
+    error: Option<String>,
+    uniforms_buffer: wgpu::Buffer,
+    /// the uniforms and the history texture
+    bind_group: wgpu::BindGroup,
+}
+
+/// Post-processing passes run over the composited picture, each one turned on and off on
+/// its own. Every pass gets the transport's musical time so it can move with the beat.
+pub struct EffectChain {
+    sampler: wgpu::Sampler,
+    input_layout: wgpu::BindGroupLayout,
+    effect_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    /// vertex stage every pass shares
+    vertex: wgpu::ShaderModule,
+    effects: Vec<Effect>,
+    targets: [Target; 2],
+    /// what the chain put out on the previous frame, for feedback
+    history: Target,
+    width: u32,
+    height: u32,
+    started: Instant,
+    music: Musical,
+}
+
+impl EffectChain {
+    /// Chain reading pictures bound with `input_layout`, a sampler and a texture
+    pub fn new(device: &wgpu::Device, input_layout: &wgpu::BindGroupLayout, width: u32, height: u32) -> EffectChain {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let input_layout = input_layout.clone();
+        let effect_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+            ],
+            label: Some("effect_bind_group_layout"),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("effect_pipeline_layout"),
+            bind_group_layouts: &[&input_layout, &effect_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vertex = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("effect_vertex_shader"),
+            source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", PASSTHROUGH, PRELUDE).into()),
+        });
+
+        let (width, height) = (width.max(1), height.max(1));
+        let mut chain = EffectChain {
+            targets: [
+                Target::new(device, &input_layout, &sampler, width, height),
+                Target::new(device, &input_layout, &sampler, width, height),
+            ],
+            history: Target::new(device, &input_layout, &sampler, width, height),
+            sampler,
+            input_layout,
+            effect_layout,
+            pipeline_layout,
+            vertex,
+            effects: Vec::new(),
+            width,
+            height,
+            started: Instant::now(),
+            music: Musical::default(),
+        };
+        for (name, source) in BUILT_IN {
+            chain.load(device, name, source);
+        }
+        chain
+    }
+
+    /// Build the effect called `name` from WGSL `source`, written against effects/prelude.wgsl,
+    /// adding it to the end of the chain or replacing the one already called that. A replaced
+    /// effect stays on if it was, and keeps the values of the parameters it still has.
+    pub fn load(&mut self, device: &wgpu::Device, name: &str, source: &str) {
+        // The prelude goes last so line numbers in errors are those of the effect's own source
+        let wgsl = format!("{}\n{}", source, PRELUDE);
+        self.install(device, name, Ok(wgsl), parse_params(source));
+    }
+
+    /// Like [`EffectChain::load`], from an ISF shader, see [`isf::translate`]
+    pub fn load_isf(&mut self, device: &wgpu::Device, name: &str, source: &str) {
+        match isf::translate(source) {
+            Ok(isf) => self.install(device, name, Ok(isf.wgsl), isf.params),
+            Err(error) => self.install(device, name, Err(error), Vec::new()),
+        }
+    }
+
+    /// Take the effect called `name` out of the chain, or go back to the built-in one of
+    /// that name if the effect replaced it. Returns whether there was one.
+    pub fn remove(&mut self, device: &wgpu::Device, name: &str) -> bool {
+        if let Some((name, source)) = BUILT_IN.iter().find(|(built_in, _)| *built_in == name) {
+            self.load(device, name, source);
+            return true;
+        }
+        let count = self.effects.len();
+        self.effects.retain(|effect| effect.name != name);
+        self.effects.len() != count
+    }
+
+    /// Add or replace an effect running `wgsl`, or passing the picture through if it
+    /// couldn't be translated
+    fn install(&mut self, device: &wgpu::Device, name: &str, wgsl: Result<String, String>, mut params: Vec<EffectParam>) {
+        let (pipeline, error) = match wgsl {
+            Ok(wgsl) => self.build(device, name, &wgsl),
+            Err(error) => {
+                log::error!("Effect {} failed to compile: {}", name, error);
+                (self.pipeline(device, name, &self.passthrough(device)), Some(error))
+            }
+        };
+        if let Some(effect) = self.effects.iter_mut().find(|effect| effect.name == name) {
+            for param in &mut params {
+                if let Some(old) = effect.params.iter().find(|old| old.name == param.name) {
+                    param.value = old.value;
+                }
+            }
+            effect.params = params;
+            effect.pipeline = pipeline;
+            effect.error = error;
+            return;
+        }
+        let uniforms_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("effect_uniforms_buffer"),
+            size: std::mem::size_of::<Musical>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.effect_bind_group(device, &uniforms_buffer);
+        self.effects.push(Effect {
+            name: name.to_string(),
+            params,
+            enabled: false,
+            pipeline,
+            error,
+            uniforms_buffer,
+            bind_group,
+        });
+    }
+
+    /// The pipeline of a fragment shader with its entry point `fs_main`, or of [`PASSTHROUGH`]
+    /// along with the compiler's complaint if `wgsl` is broken
+    fn build(&self, device: &wgpu::Device, name: &str, wgsl: &str) -> (wgpu::RenderPipeline, Option<String>) {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(name),
+            source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+        });
+        let pipeline = self.pipeline(device, name, &module);
+        match pollster::block_on(device.pop_error_scope()) {
+            None => (pipeline, None),
+            Some(error) => {
+                log::error!("Effect {} failed to compile: {}", name, error);
+                (self.pipeline(device, name, &self.passthrough(device)), Some(error.to_string()))
+            }
+        }
+    }
+
+    fn passthrough(&self, device: &wgpu::Device) -> wgpu::ShaderModule {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("passthrough"),
+            source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", PASSTHROUGH, PRELUDE).into()),
+        })
+    }
+
+    fn pipeline(&self, device: &wgpu::Device, name: &str, fragment: &wgpu::ShaderModule) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(name),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &self.vertex,
+                entry_point: Some("vs_main"),
+                buffers: &[Vertex::layout()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: fragment,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: TARGET_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    fn effect_bind_group(&self, device: &wgpu::Device, uniforms_buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.effect_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniforms_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.history.view),
+                },
+            ],
+            label: Some("effect_bind_group"),
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.width = width.max(1);
+        self.height = height.max(1);
+        self.targets = [
+            Target::new(device, &self.input_layout, &self.sampler, self.width, self.height),
+            Target::new(device, &self.input_layout, &self.sampler, self.width, self.height),
+        ];
+        self.history = Target::new(device, &self.input_layout, &self.sampler, self.width, self.height);
+        for index in 0..self.effects.len() {
+            self.effects[index].bind_group = self.effect_bind_group(device, &self.effects[index].uniforms_buffer);
+        }
+    }
+
+    /// Names of the effects in the order they are chained
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.effects.iter().map(|effect| effect.name.as_str())
+    }
+
+    /// Effects whose source didn't compile and why, by name
+    pub fn errors(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.effects
+            .iter()
+            .filter_map(|effect| Some((effect.name.as_str(), effect.error.as_deref()?)))
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.effects.iter().any(|effect| effect.enabled && effect.name == name)
+    }
+
+    /// Turn an effect on or off, returning whether there is one called `name`
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let Some(effect) = self.effects.iter_mut().find(|effect| effect.name == name) else {
+            return false;
+        };
+        effect.enabled = enabled;
+        log::info!("Effect {} {}", name, if enabled { "on" } else { "off" });
+        true
+    }
+
+    /// Give parameters their starting values and turn on the effects in `enabled`, failing
+    /// on names no effect has
+    pub fn configure(&mut self, params: &HashMap<String, HashMap<String, f32>>, enabled: &[String]) -> Result<(), String> {
+        for (effect, params) in params {
+            for (param, value) in params {
+                if !self.set_param(effect, param, *value) {
+                    return Err(format!("Unknown effect parameter `{}` of `{}`", param, effect));
+                }
+            }
+        }
+        for effect in enabled {
+            if !self.set_enabled(effect, true) {
+                return Err(format!("Unknown effect `{}`", effect));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn params(&self, name: &str) -> &[EffectParam] {
+        self.effects
+            .iter()
+            .find(|effect| effect.name == name)
+            .map_or(&[], |effect| &effect.params)
+    }
+
+    /// Set a parameter, 0.0 to 1.0, returning whether the effect has one called `param`
+    pub fn set_param(&mut self, name: &str, param: &str, value: f32) -> bool {
+        let param = self
+            .effects
+            .iter_mut()
+            .filter(|effect| effect.name == name)
+            .flat_map(|effect| effect.params.iter_mut())
+            .find(|known| known.name == param);
+        match param {
+            Some(param) => {
+                param.value = value.clamp(0.0, 1.0);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take the musical time effects move with from the transport
+    pub fn set_transport(&mut self, transport: &TransportSnapshot) {
+        self.set_transport_at(transport, self.started.elapsed().as_secs_f32());
+    }
+
+    /// Take the musical time from the transport, `time` seconds after the chain started,
+    /// for drawing frames faster or slower than they are shown
+    pub fn set_transport_at(&mut self, transport: &TransportSnapshot, time: f32) {
+        self.music = Musical {
+            time,
+            delta: time - self.music.time,
+            frame: self.music.frame + 1.0,
+            beat: transport.beat as f32,
+            bar: transport.bar as f32,
+            phase: transport.phase as f32,
+            tempo: transport.tempo as f32,
+            quantum: transport.quantum as f32,
+            ..self.music
+        };
+    }
+
+    /// Record the passes of every enabled effect over `input`, returning the result
+    pub fn render<'a>(
+        &'a self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        quad: &Quad,
+        input: &'a wgpu::BindGroup,
+    ) -> &'a wgpu::BindGroup {
+        let mut current = input;
+        let mut written = None;
+        for effect in self.effects.iter().filter(|effect| effect.enabled) {
+            let mut music = Musical { width: self.width as f32, height: self.height as f32, ..self.music };
+            for (slot, param) in music.params.iter_mut().zip(&effect.params) {
+                *slot = param.value;
+            }
+            queue.write_buffer(&effect.uniforms_buffer, 0, bytemuck::bytes_of(&music));
+
+            let next = match written {
+                Some(0) => 1,
+                _ => 0,
+            };
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("effect_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.targets[next].view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            rpass.set_pipeline(&effect.pipeline);
+            rpass.set_bind_group(0, current, &[]);
+            rpass.set_bind_group(1, &effect.bind_group, &[]);
+            quad.draw(&mut rpass);
+            drop(rpass);
+
+            current = &self.targets[next].bind_group;
+            written = Some(next);
+        }
+
+        // Keep this frame's result for feedback on the next one
+        if let Some(last) = written {
+            encoder.copy_texture_to_texture(
+                self.targets[last].texture.as_image_copy(),
+                self.history.texture.as_image_copy(),
+                wgpu::Extent3d { width: self.width, height: self.height, depth_or_array_layers: 1 },
+            );
+        }
+        current
+    }
+}
@@ -0,0 +1,7 @@
+// Leaves trails by blending in the previous frame, slightly zoomed
+// params: amount = 0.8, zoom = 0.2
+fn effect(uv : vec2<f32>) -> vec4<f32> {
+    let zoomed = (uv - 0.5) * (1.0 - param(1u) * 0.05) + 0.5;
+    let previous = history(zoomed) * param(0u) * 0.98;
+    return max(input(uv), previous);
+}
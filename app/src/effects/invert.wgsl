@@ -0,0 +1,10 @@
+// Inverts colours, or strobes between inverted and not on the beat
+// params: amount = 1.0, strobe = 0.0
+fn effect(uv : vec2<f32>) -> vec4<f32> {
+    let color = input(uv);
+    var amount = param(0u);
+    if (param(1u) > 0.0 && fract(music.beat) > param(1u) * 0.5) {
+        amount = 0.0;
+    }
+    return vec4<f32>(mix(color.rgb, color.a - color.rgb, amount), color.a);
+}
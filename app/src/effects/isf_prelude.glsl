@@ -0,0 +1,57 @@
+#version 450
+// Put in front of every ISF shader, in place of what ISF hosts provide. Bindings match
+// effects/prelude.wgsl, inputs are #defined after this from the shader's JSON header.
+
+layout(set = 0, binding = 0) uniform sampler isf_sampler;
+layout(set = 0, binding = 1) uniform texture2D isf_input;
+
+// Matches `Musical` in effects.rs and effects/prelude.wgsl
+layout(set = 1, binding = 0) uniform Musical {
+    float time;
+    float beat;
+    float bar;
+    float phase;
+    float tempo;
+    float quantum;
+    float width;
+    float height;
+    float delta;
+    float frame;
+    vec4 params[4];
+} isf_music;
+layout(set = 1, binding = 1) uniform texture2D isf_history;
+
+layout(location = 0) in vec2 isf_uv;
+layout(location = 0) out vec4 isf_out;
+
+// What the shader writes to gl_FragColor
+vec4 isf_color;
+
+float isf_param(int n) {
+    return isf_music.params[n / 4][n % 4];
+}
+
+// Image 1 is the output of the whole chain on the previous frame, any other the picture so far.
+// ISF coordinates start at the bottom left.
+vec4 isf_pixel(int image, vec2 coord) {
+    vec2 uv = vec2(coord.x, 1.0 - coord.y);
+    if (image == 1) {
+        return textureLod(sampler2D(isf_history, isf_sampler), uv, 0.0);
+    }
+    return textureLod(sampler2D(isf_input, isf_sampler), uv, 0.0);
+}
+
+#define TIME isf_music.time
+#define TIMEDELTA isf_music.delta
+#define FRAMEINDEX int(isf_music.frame)
+#define PASSINDEX 0
+#define RENDERSIZE vec2(isf_music.width, isf_music.height)
+#define isf_FragNormCoord vec2(isf_uv.x, 1.0 - isf_uv.y)
+#define vv_FragNormCoord isf_FragNormCoord
+#define inputImage 0
+#define IMG_NORM_PIXEL(image, coord) isf_pixel(image, coord)
+#define IMG_PIXEL(image, coord) isf_pixel(image, (coord) / RENDERSIZE)
+#define IMG_THIS_NORM_PIXEL(image) isf_pixel(image, isf_FragNormCoord)
+#define IMG_THIS_PIXEL(image) isf_pixel(image, isf_FragNormCoord)
+#define IMG_SIZE(image) RENDERSIZE
+#define gl_FragColor isf_color
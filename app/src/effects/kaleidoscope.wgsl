@@ -0,0 +1,13 @@
+// Folds the picture into mirrored segments around the centre, turning once a bar with spin
+// params: segments = 0.4, spin = 0.0
+const PI : f32 = 3.14159265;
+
+fn effect(uv : vec2<f32>) -> vec4<f32> {
+    let segments = floor(2.0 + param(0u) * 10.0);
+    let wedge = 2.0 * PI / segments;
+    let p = uv - 0.5;
+    var angle = atan2(p.y, p.x) + param(1u) * music.phase * 2.0 * PI;
+    angle = abs(angle - wedge * floor(angle / wedge) - wedge * 0.5);
+    let folded = vec2<f32>(cos(angle), sin(angle)) * length(p);
+    return input(folded + 0.5);
+}
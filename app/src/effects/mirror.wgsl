@@ -0,0 +1,9 @@
+// Mirrors the left half onto the right, and the top half onto the bottom as well with both
+// params: both = 0.0
+fn effect(uv : vec2<f32>) -> vec4<f32> {
+    var mirrored = vec2<f32>(0.5 - abs(uv.x - 0.5), uv.y);
+    if (param(0u) >= 0.5) {
+        mirrored.y = 0.5 - abs(uv.y - 0.5);
+    }
+    return input(mirrored);
+}
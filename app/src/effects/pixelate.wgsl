@@ -0,0 +1,7 @@
+// Blocky pixels, bigger on the beat with pulse
+// params: size = 0.2, pulse = 0.0
+fn effect(uv : vec2<f32>) -> vec4<f32> {
+    let size = 2.0 + param(0u) * 62.0 * (1.0 + param(1u) * beat_pulse());
+    let cells = vec2<f32>(music.width, music.height) / size;
+    return input((floor(uv * cells) + 0.5) / cells);
+}
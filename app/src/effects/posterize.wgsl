@@ -0,0 +1,7 @@
+// Cuts colours down to a few levels per channel
+// params: levels = 0.2
+fn effect(uv : vec2<f32>) -> vec4<f32> {
+    let color = input(uv);
+    let levels = floor(2.0 + param(0u) * 14.0);
+    return vec4<f32>(floor(color.rgb * levels + 0.5) / levels, color.a);
+}
@@ -0,0 +1,69 @@
+// Shared by every effect pass. An effect defines `fn effect(uv : vec2<f32>) -> vec4<f32>`
+// returning premultiplied colour, reads the picture so far with `input(uv)`, and lists
+// its parameters with their defaults on a line like `// params: amount = 0.5, speed = 0.0`.
+
+// Matches `Musical` in effects.rs and effects/isf_prelude.glsl
+struct Musical {
+    // seconds since voop started
+    time    : f32,
+    // beats since the start of the session
+    beat    : f32,
+    bar     : f32,
+    // position within the bar, 0.0 to 1.0
+    phase   : f32,
+    // BPM
+    tempo   : f32,
+    // beats per bar
+    quantum : f32,
+    // size of the picture in pixels
+    width   : f32,
+    height  : f32,
+    // seconds since the previous frame
+    delta   : f32,
+    // frames since voop started
+    frame   : f32,
+    // the effect's parameters, 0.0 to 1.0, read with `param(n)`
+    params  : array<vec4<f32>, 4>,
+};
+
+@group(0) @binding(0) var samp      : sampler;
+@group(0) @binding(1) var input_tex : texture_2d<f32>;
+@group(1) @binding(0) var<uniform> music : Musical;
+// the output of the whole chain on the previous frame
+@group(1) @binding(1) var history_tex : texture_2d<f32>;
+
+fn param(n : u32) -> f32 {
+    return music.params[n / 4u][n % 4u];
+}
+
+fn input(uv : vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(input_tex, samp, uv, 0.0);
+}
+
+fn history(uv : vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(history_tex, samp, uv, 0.0);
+}
+
+// 1.0 on the beat, falling to 0.0 just before the next one
+fn beat_pulse() -> f32 {
+    return 1.0 - fract(music.beat);
+}
+
+struct VSOut {
+    @builtin(position) pos : vec4<f32>,
+    @location(0)        uv  : vec2<f32>,
+};
+
+@vertex
+fn vs_main(@location(0) in_pos : vec2<f32>,
+           @location(1) in_uv  : vec2<f32>) -> VSOut {
+    var out : VSOut;
+    out.pos = vec4<f32>(in_pos, 0.0, 1.0);
+    out.uv  = in_uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in : VSOut) -> @location(0) vec4<f32> {
+    return effect(in.uv);
+}
@@ -0,0 +1,9 @@
+// Pulls the red and blue channels apart, further on every beat
+// params: amount = 0.3, pulse = 0.5
+fn effect(uv : vec2<f32>) -> vec4<f32> {
+    let offset = vec2<f32>(param(0u) * 0.02 * (1.0 + 2.0 * param(1u) * beat_pulse()), 0.0);
+    let red = input(uv - offset);
+    let center = input(uv);
+    let blue = input(uv + offset);
+    return vec4<f32>(red.r, center.g, blue.b, max(max(red.a, center.a), blue.a));
+}
@@ -0,0 +1,173 @@
+use ffmpeg::format::Pixel;
+use ffmpeg::software::scaling;
+use ffmpeg::util::frame;
+use ffmpeg::{codec, encoder, format, media, Packet, Rational};
+use ffmpeg_next as ffmpeg;
+use log::debug;
+use std::path::Path;
+
+/// Encodes pictures of one size into a new video file at a constant frame rate
+pub struct VideoWriter {
+    output: format::context::Output,
+    encoder: encoder::Video,
+    /// pixel format the encoder takes
+    format: Pixel,
+    width: u32,
+    height: u32,
+    time_base: Rational,
+    stream_time_base: Rational,
+    /// converts frames the encoder can't take as they are, with the format and size it was made for
+    scaler: Option<(Pixel, u32, u32, scaling::Context)>,
+    /// RGBA picture `write_rgba` copies into
+    rgba: frame::Video,
+    frame_count: i64,
+}
+
+impl VideoWriter {
+    /// Start a file `width` by `height` at `frame_rate`, encoded with the encoder called
+    /// `codec`, or the default one of the format the file extension names
+    pub fn create(
+        path: &Path,
+        width: u32,
+        height: u32,
+        frame_rate: Rational,
+        codec: Option<&str>,
+    ) -> Result<VideoWriter, ffmpeg::Error> {
+        let mut output = format::output(&path)?;
+        let codec = match codec {
+            Some(name) => encoder::find_by_name(name),
+            None => encoder::find(output.format().codec(&path, media::Type::Video)),
+        }
+        .ok_or(ffmpeg::Error::EncoderNotFound)?;
+        // The first format an encoder lists is its preferred one
+        let format = codec
+            .video()?
+            .formats()
+            .and_then(|mut formats| formats.next())
+            .ok_or(ffmpeg::Error::EncoderNotFound)?;
+        let global_header = output.format().flags().contains(format::Flags::GLOBAL_HEADER);
+        let time_base = frame_rate.invert();
+
+        let mut stream = output.add_stream(codec)?;
+        let mut encoder = codec::context::Context::from_parameters(stream.parameters())?.encoder().video()?;
+        encoder.set_width(width);
+        encoder.set_height(height);
+        encoder.set_format(format);
+        encoder.set_time_base(time_base);
+        encoder.set_frame_rate(Some(frame_rate));
+        if global_header {
+            encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = encoder.open_as(codec)?;
+        stream.set_parameters(&encoder);
+        let stream_index = stream.index();
+        output.write_header()?;
+        let stream_time_base = output.stream(stream_index).ok_or(ffmpeg::Error::StreamNotFound)?.time_base();
+        debug!("Writing {} as {} {:?} {}x{}", path.display(), codec.name(), format, width, height);
+
+        Ok(VideoWriter {
+            output,
+            encoder,
+            format,
+            width,
+            height,
+            time_base,
+            stream_time_base,
+            scaler: None,
+            rgba: frame::Video::new(Pixel::RGBA, width, height),
+            frame_count: 0,
+        })
+    }
+
+    /// Add `frame` as the next picture, converted to the size and pixel format of the file
+    pub fn write(&mut self, frame: &frame::Video) -> Result<(), ffmpeg::Error> {
+        let source = (frame.format(), frame.width(), frame.height());
+        let mut picture = if source == (self.format, self.width, self.height) {
+            frame.clone()
+        } else {
+            if self.scaler.as_ref().is_none_or(|(format, width, height, _)| (*format, *width, *height) != source) {
+                let scaler = scaling::Context::get(
+                    source.0,
+                    source.1,
+                    source.2,
+                    self.format,
+                    self.width,
+                    self.height,
+                    scaling::Flags::BILINEAR,
+                )?;
+                self.scaler = Some((source.0, source.1, source.2, scaler));
+            }
+            let mut converted = frame::Video::empty();
+            self.scaler.as_mut().unwrap().3.run(frame, &mut converted)?;
+            converted
+        };
+        picture.set_pts(Some(self.frame_count));
+        self.frame_count += 1;
+        self.encoder.send_frame(&picture)?;
+        self.write_packets()
+    }
+
+    /// Add a picture of tightly packed RGBA rows the size of the file
+    pub fn write_rgba(&mut self, pixels: &[u8]) -> Result<(), ffmpeg::Error> {
+        let row = self.width as usize * 4;
+        let stride = self.rgba.stride(0);
+        let data = self.rgba.data_mut(0);
+        for (y, source) in pixels.chunks_exact(row).take(self.height as usize).enumerate() {
+            data[y * stride..y * stride + row].copy_from_slice(source);
+        }
+        let rgba = std::mem::replace(&mut self.rgba, frame::Video::empty());
+        let written = self.write(&rgba);
+        self.rgba = rgba;
+        written
+    }
+
+    /// Flush the encoder and finish the file
+    pub fn finish(mut self) -> Result<(), ffmpeg::Error> {
+        self.encoder.send_eof()?;
+        self.write_packets()?;
+        self.output.write_trailer()
+    }
+
+    fn write_packets(&mut self) -> Result<(), ffmpeg::Error> {
+        let mut packet = Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(0);
+            packet.rescale_ts(self.time_base, self.stream_time_base);
+            packet.write_interleaved(&mut self.output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Re-encode the video of `input` into `output` with the encoder called `codec`, at the
+/// same size and frame rate. Sound is left out, voop doesn't play it, and frames are
+/// retimed to a constant rate.
+pub fn transcode(input: &Path, output: &Path, codec: &str) -> Result<(), ffmpeg::Error> {
+    let mut ctx = crate::media::open(input)?;
+    let (index, frame_rate, mut decoder) = {
+        let stream = ctx.streams().best(media::Type::Video).ok_or(ffmpeg::Error::StreamNotFound)?;
+        let frame_rate = [stream.avg_frame_rate(), stream.rate()]
+            .into_iter()
+            .find(|rate| rate.numerator() > 0 && rate.denominator() > 0)
+            .unwrap_or(Rational(25, 1));
+        let decoder = codec::context::Context::from_parameters(stream.parameters())?.decoder().video()?;
+        (stream.index(), frame_rate, decoder)
+    };
+    let mut writer = VideoWriter::create(output, decoder.width(), decoder.height(), frame_rate, Some(codec))?;
+
+    let mut frame = frame::Video::empty();
+    for (stream, packet) in ctx.packets() {
+        if stream.index() != index {
+            continue;
+        }
+        decoder.send_packet(&packet)?;
+        while decoder.receive_frame(&mut frame).is_ok() {
+            writer.write(&frame)?;
+        }
+    }
+    decoder.send_eof()?;
+    while decoder.receive_frame(&mut frame).is_ok() {
+        writer.write(&frame)?;
+    }
+    writer.finish()
+}
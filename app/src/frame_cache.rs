@@ -0,0 +1,207 @@
+use ffmpeg_next as ffmpeg;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+pub type Frame = Arc<ffmpeg::util::frame::Video>;
+
+/// Frame cache shared by every clip the app has opened
+pub type SharedFrameCache = Arc<Mutex<FrameCache>>;
+
+/// Identifies a decoded frame: which clip it came from and its presentation timestamp.
+/// Ordered by clip, then timestamp, so a clip's frames are contiguous.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FrameKey {
+    pub clip: u64,
+    pub pts: i64,
+}
+
+struct Entry {
+    frame: Frame,
+    /// timestamp where the next frame starts
+    end: i64,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// Decoded frames of several clips kept under a byte budget,
+/// evicting the least recently used frames first
+pub struct FrameCache {
+    budget: usize,
+    used: usize,
+    tick: u64,
+    entries: BTreeMap<FrameKey, Entry>,
+    // last_used tick -> key, oldest first
+    lru: BTreeMap<u64, FrameKey>,
+}
+
+impl FrameCache {
+    pub fn new(budget_bytes: usize) -> FrameCache {
+        FrameCache {
+            budget: budget_bytes,
+            used: 0,
+            tick: 0,
+            entries: BTreeMap::new(),
+            lru: BTreeMap::new(),
+        }
+    }
+
+    pub fn shared(budget_bytes: usize) -> SharedFrameCache {
+        Arc::new(Mutex::new(FrameCache::new(budget_bytes)))
+    }
+
+    /// The frame of `clip` on screen at `pts`: the latest one starting at or before it,
+    /// provided it lasts until `pts`
+    pub fn get(&mut self, clip: u64, pts: i64) -> Option<Frame> {
+        let (key, entry) = self.entries.range_mut(..=FrameKey { clip, pts }).next_back()?;
+        if key.clip != clip || pts >= entry.end {
+            return None;
+        }
+
+        self.tick += 1;
+        self.lru.remove(&entry.last_used);
+        entry.last_used = self.tick;
+        self.lru.insert(self.tick, *key);
+        Some(entry.frame.clone())
+    }
+
+    pub fn contains(&self, key: &FrameKey) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Store a frame shown until `end`, evicting old ones to stay within budget.
+    /// Frames bigger than the whole budget are handed back without being cached.
+    pub fn insert(&mut self, key: FrameKey, end: i64, frame: Frame) -> Frame {
+        let bytes = frame_bytes(&frame);
+        if bytes > self.budget {
+            return frame;
+        }
+
+        self.remove(&key);
+        while self.used + bytes > self.budget {
+            if !self.evict_oldest() {
+                break;
+            }
+        }
+
+        self.tick += 1;
+        self.used += bytes;
+        self.lru.insert(self.tick, key);
+        self.entries.insert(key, Entry { frame: frame.clone(), end, bytes, last_used: self.tick });
+        frame
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.used
+    }
+
+    pub fn budget_bytes(&self) -> usize {
+        self.budget
+    }
+
+    fn remove(&mut self, key: &FrameKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.last_used);
+            self.used -= entry.bytes;
+        }
+    }
+
+    fn evict_oldest(&mut self) -> bool {
+        match self.lru.pop_first() {
+            Some((_, key)) => {
+                if let Some(entry) = self.entries.remove(&key) {
+                    self.used -= entry.bytes;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Size of a frame's pixel data across all planes
+pub fn frame_bytes(frame: &ffmpeg::util::frame::Video) -> usize {
+    (0..frame.planes()).map(|plane| frame.data(plane).len()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffmpeg::format::Pixel;
+
+    fn frame() -> Frame {
+        Arc::new(ffmpeg::util::frame::Video::new(Pixel::GRAY8, 64, 36))
+    }
+
+    fn key(clip: u64, pts: i64) -> FrameKey {
+        FrameKey { clip, pts }
+    }
+
+    /// A cache with room for `frames` frames like [`frame`]
+    fn cache(frames: usize) -> FrameCache {
+        FrameCache::new(frame_bytes(&frame()) * frames)
+    }
+
+    #[test]
+    fn the_oldest_frames_go_first() {
+        let mut cache = cache(3);
+        for pts in 0..5 {
+            cache.insert(key(1, pts), pts + 1, frame());
+            assert!(cache.used_bytes() <= cache.budget_bytes());
+        }
+        assert_eq!(cache.used_bytes(), cache.budget_bytes());
+        assert!(!cache.contains(&key(1, 0)));
+        assert!(!cache.contains(&key(1, 1)));
+        assert!((2..5).all(|pts| cache.contains(&key(1, pts))));
+    }
+
+    #[test]
+    fn getting_a_frame_keeps_it() {
+        let mut cache = cache(3);
+        for pts in 0..3 {
+            cache.insert(key(1, pts), pts + 1, frame());
+        }
+        assert!(cache.get(1, 0).is_some());
+        cache.insert(key(1, 3), 4, frame());
+        assert!(cache.contains(&key(1, 0)));
+        assert!(!cache.contains(&key(1, 1)));
+        assert!(cache.contains(&key(1, 2)));
+        assert!(cache.used_bytes() <= cache.budget_bytes());
+    }
+
+    #[test]
+    fn inserting_a_frame_again_replaces_it() {
+        let mut cache = cache(3);
+        cache.insert(key(1, 0), 1, frame());
+        cache.insert(key(1, 0), 1, frame());
+        assert_eq!(cache.used_bytes(), frame_bytes(&frame()));
+    }
+
+    #[test]
+    fn frames_are_found_by_the_time_they_cover() {
+        let mut cache = cache(4);
+        let first = cache.insert(key(1, 0), 40, frame());
+        let second = cache.insert(key(1, 40), 120, frame());
+        let other = cache.insert(key(2, 0), 40, frame());
+
+        assert!(Arc::ptr_eq(&cache.get(1, 0).unwrap(), &first));
+        assert!(Arc::ptr_eq(&cache.get(1, 39).unwrap(), &first));
+        assert!(Arc::ptr_eq(&cache.get(1, 40).unwrap(), &second));
+        assert!(Arc::ptr_eq(&cache.get(1, 119).unwrap(), &second));
+        assert!(cache.get(1, 120).is_none(), "past the last frame");
+        assert!(Arc::ptr_eq(&cache.get(2, 10).unwrap(), &other));
+        assert!(cache.get(2, 50).is_none());
+        // The frames before are another clip's
+        assert!(cache.get(2, -1).is_none());
+        assert!(cache.get(3, 10).is_none());
+    }
+
+    #[test]
+    fn frames_bigger_than_the_budget_are_not_kept() {
+        let mut cache = FrameCache::new(frame_bytes(&frame()) - 1);
+        let big = frame();
+        assert!(Arc::ptr_eq(&cache.insert(key(1, 0), 1, big.clone()), &big));
+        assert!(!cache.contains(&key(1, 0)));
+        assert!(cache.get(1, 0).is_none());
+        assert_eq!(cache.used_bytes(), 0);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::clip::{LaunchQuantize, LoopMode};
+use crate::compositor::BlendMode;
+use crate::transition::Transition;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// A clip in the grid and how it plays
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slot {
+    /// index into [`Grid::files`]
+    pub file: usize,
+    pub loop_mode: LoopMode,
+    pub launch_quantize: LaunchQuantize,
+    /// how the clip takes over from the one its track played before
+    pub transition: Transition,
+}
+
+/// Side of the crossfader a track is on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossfadeSide {
+    A,
+    B,
+}
+
+impl CrossfadeSide {
+    /// How much of the track the crossfader lets through at `position`, 0.0 being all A.
+    /// Both sides are at full level in the middle.
+    pub fn gain(self, position: f32) -> f32 {
+        match self {
+            CrossfadeSide::A => (2.0 * (1.0 - position)).min(1.0),
+            CrossfadeSide::B => (2.0 * position).min(1.0),
+        }
+    }
+}
+
+/// A column of the grid, composited as a layer over the tracks before it
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub name: String,
+    pub blend: BlendMode,
+    /// 0.0 to 1.0
+    pub opacity: f32,
+    /// `None` for a track the crossfader doesn't touch
+    pub crossfade: Option<CrossfadeSide>,
+}
+
+impl Track {
+    pub fn new(name: &str) -> Track {
+        Track {
+            name: name.to_string(),
+            blend: BlendMode::Normal,
+            opacity: 1.0,
+            crossfade: None,
+        }
+    }
+}
+
+/// A row of the grid, launched as a whole
+#[derive(Clone, Debug)]
+pub struct Scene {
+    pub name: String,
+    /// one per track, `None` for an empty slot that stops its track
+    pub slots: Vec<Option<Slot>>,
+    /// beats per bar from when the scene is launched, unchanged if not set
+    pub quantum: Option<f64>,
+    /// the only effects on while the scene plays, unchanged if not set
+    pub effects: Option<Vec<String>>,
+}
+
+/// Clips arranged like Ableton's session view: tracks play on top of each other,
+/// and launching a scene launches the slot of every track in it
+#[derive(Clone, Debug)]
+pub struct Grid {
+    /// every file in the grid, each listed once
+    pub files: Vec<PathBuf>,
+    /// bottom to top
+    pub tracks: Vec<Track>,
+    pub scenes: Vec<Scene>,
+    /// when tracks stop for an empty slot
+    pub launch_quantize: LaunchQuantize,
+}
+
+impl Grid {
+    /// One track with a scene for each file, the way a folder of clips plays
+    pub fn from_files(files: Vec<PathBuf>, slot_for: impl Fn(usize, &Path) -> Slot, launch_quantize: LaunchQuantize) -> Grid {
+        let scenes = files
+            .iter()
+            .enumerate()
+            .map(|(index, file)| Scene {
+                name: file_name(file).to_string(),
+                slots: vec![Some(slot_for(index, file))],
+                quantum: None,
+                effects: None,
+            })
+            .collect();
+        Grid {
+            files,
+            tracks: vec![Track::new("Main")],
+            scenes,
+            launch_quantize,
+        }
+    }
+
+    pub fn slot(&self, track: usize, scene: usize) -> Option<&Slot> {
+        self.scenes.get(scene)?.slots.get(track)?.as_ref()
+    }
+
+    pub fn slot_mut(&mut self, track: usize, scene: usize) -> Option<&mut Slot> {
+        self.scenes.get_mut(scene)?.slots.get_mut(track)?.as_mut()
+    }
+
+    /// Files of every slot in `scene`
+    pub fn scene_files(&self, scene: usize) -> impl Iterator<Item = usize> + '_ {
+        self.scenes
+            .get(scene)
+            .into_iter()
+            .flat_map(|scene| scene.slots.iter().flatten().map(|slot| slot.file))
+    }
+}
+
+pub fn file_name(path: &Path) -> &str {
+    path.file_name().and_then(|name| name.to_str()).unwrap_or("Unknown")
+}
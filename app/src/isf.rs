@@ -0,0 +1,275 @@
+use crate::effects::{EffectParam, MAX_PARAMS};
+use serde::Deserialize;
+
+/// Declarations standing in for what ISF hosts provide, bound like effects/prelude.wgsl
+const PRELUDE: &str = include_str!("effects/isf_prelude.glsl");
+
+/// Runs the shader's own `main`, renamed `isf_main`, and premultiplies what it wrote
+const MAIN: &str = "
+#undef main
+#undef gl_FragColor
+void main() {
+    isf_main();
+    isf_out = vec4(isf_color.rgb * isf_color.a, isf_color.a);
+}
+";
+
+/// The JSON comment an ISF shader starts with. Fields voop has no use for are ignored.
+#[derive(Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct Header {
+    #[serde(default)]
+    inputs: Vec<Input>,
+    #[serde(default)]
+    passes: Vec<Pass>,
+    imported: Option<serde::de::IgnoredAny>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct Pass {
+    target: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct Input {
+    name: String,
+    #[serde(rename = "TYPE")]
+    kind: String,
+    default: Option<Value>,
+    min: Option<Value>,
+    max: Option<Value>,
+    /// the choices of a `long` input
+    values: Option<Vec<f64>>,
+}
+
+/// A default or limit, a single number or one per component
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Bool(bool),
+    Number(f64),
+    List(Vec<f64>),
+}
+
+impl Value {
+    fn component(&self, index: usize) -> Option<f64> {
+        match self {
+            Value::Bool(value) => Some(*value as u8 as f64),
+            Value::Number(value) => Some(*value),
+            Value::List(values) => values.get(index).copied(),
+        }
+    }
+}
+
+/// An ISF shader translated to WGSL for the effect chain
+pub struct Translated {
+    /// fragment shader with its entry point `fs_main`, bound like effects/prelude.wgsl
+    pub wgsl: String,
+    /// the shader's inputs, scaled to 0.0 to 1.0
+    pub params: Vec<EffectParam>,
+}
+
+/// Translate an ISF fragment shader through naga's GLSL frontend. Each input becomes one
+/// effect parameter, or one per component for colours (`NAME_r` to `NAME_a`) and points
+/// (`NAME_x`, `NAME_y`). Image inputs other than `inputImage` are a parameter choosing the
+/// picture so far (below 0.5) or the chain's output on the previous frame.
+pub fn translate(source: &str) -> Result<Translated, String> {
+    let header = header(source)?;
+    if header.imported.is_some() {
+        return Err("imported images aren't supported".to_string());
+    }
+    if header.passes.len() > 1 || header.passes.iter().any(|pass| pass.target.is_some()) {
+        return Err("only single pass shaders without targets are supported".to_string());
+    }
+
+    let mut defines = String::new();
+    let mut params = Vec::new();
+    for input in &header.inputs {
+        declare(input, &mut defines, &mut params)?;
+    }
+    if params.len() > MAX_PARAMS {
+        return Err(format!("its inputs need {} parameters, effects can have {}", params.len(), MAX_PARAMS));
+    }
+
+    // ISF shaders are written without a version, the prelude brings its own
+    let body: String = source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("#version"))
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    let glsl = format!("{}{}#define main isf_main\n{}{}", PRELUDE, defines, body, MAIN);
+
+    let options = naga::front::glsl::Options::from(naga::ShaderStage::Fragment);
+    let mut module = naga::front::glsl::Frontend::default()
+        .parse(&options, &glsl)
+        .map_err(|e| e.emit_to_string(&glsl))?;
+    for entry_point in &mut module.entry_points {
+        entry_point.name = "fs_main".to_string();
+    }
+    let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+        .validate(&module)
+        .map_err(|e| e.emit_to_string(&glsl))?;
+    let wgsl = naga::back::wgsl::write_string(&module, &info, naga::back::wgsl::WriterFlags::empty())
+        .map_err(|e| e.to_string())?;
+    Ok(Translated { wgsl, params })
+}
+
+/// The JSON between the `/*` and `*/` the shader starts with
+fn header(source: &str) -> Result<Header, String> {
+    let json = source
+        .trim_start()
+        .strip_prefix("/*")
+        .and_then(|rest| rest.split_once("*/"))
+        .map(|(json, _)| json)
+        .ok_or("no JSON header comment at the top")?;
+    serde_json::from_str(json).map_err(|e| format!("bad JSON header: {}", e))
+}
+
+/// Add the parameters standing for `input`, and a #define reading them in its own units
+fn declare(input: &Input, defines: &mut String, params: &mut Vec<EffectParam>) -> Result<(), String> {
+    let name = &input.name;
+    let first = params.len();
+    let default = |index: usize, fallback: f64| input.default.as_ref().and_then(|value| value.component(index)).unwrap_or(fallback);
+    let limits = |index: usize, min: f64, max: f64| {
+        let min = input.min.as_ref().and_then(|value| value.component(index)).unwrap_or(min);
+        let max = input.max.as_ref().and_then(|value| value.component(index)).unwrap_or(max);
+        (min as f32, max as f32)
+    };
+    let mut param = |name: String, value: f32| params.push(EffectParam { name, value: value.clamp(0.0, 1.0) });
+
+    let value = match input.kind.as_str() {
+        "float" => {
+            let (min, max) = limits(0, 0.0, 1.0);
+            param(name.clone(), scaled(default(0, min as f64) as f32, min, max));
+            format!("({:?} + isf_param({}) * {:?})", min, first, max - min)
+        }
+        "long" => {
+            let (min, max) = match &input.values {
+                Some(values) if !values.is_empty() => (
+                    values.iter().copied().fold(f64::INFINITY, f64::min) as f32,
+                    values.iter().copied().fold(f64::NEG_INFINITY, f64::max) as f32,
+                ),
+                _ => limits(0, 0.0, 1.0),
+            };
+            param(name.clone(), scaled(default(0, min as f64) as f32, min, max));
+            format!("int(round({:?} + isf_param({}) * {:?}))", min, first, max - min)
+        }
+        "bool" | "event" => {
+            param(name.clone(), default(0, 0.0) as f32);
+            format!("(isf_param({}) > 0.5)", first)
+        }
+        "color" => {
+            for (index, component) in ["r", "g", "b", "a"].iter().enumerate() {
+                param(format!("{}_{}", name, component), default(index, if index == 3 { 1.0 } else { 0.0 }) as f32);
+            }
+            format!("vec4(isf_param({}), isf_param({}), isf_param({}), isf_param({}))", first, first + 1, first + 2, first + 3)
+        }
+        // Without limits a point moves across the whole picture, starting in the middle
+        "point2D" if input.min.is_none() || input.max.is_none() => {
+            param(format!("{}_x", name), 0.5);
+            param(format!("{}_y", name), 0.5);
+            format!("(vec2(isf_param({}), isf_param({})) * RENDERSIZE)", first, first + 1)
+        }
+        "point2D" => {
+            let ((min_x, max_x), (min_y, max_y)) = (limits(0, 0.0, 1.0), limits(1, 0.0, 1.0));
+            param(format!("{}_x", name), scaled(default(0, min_x as f64) as f32, min_x, max_x));
+            param(format!("{}_y", name), scaled(default(1, min_y as f64) as f32, min_y, max_y));
+            format!(
+                "vec2({:?} + isf_param({}) * {:?}, {:?} + isf_param({}) * {:?})",
+                min_x, first, max_x - min_x, min_y, first + 1, max_y - min_y
+            )
+        }
+        // Defined by the prelude as the picture so far
+        "image" if name == "inputImage" => return Ok(()),
+        "image" => {
+            param(name.clone(), 0.0);
+            format!("(isf_param({}) > 0.5 ? 1 : 0)", first)
+        }
+        kind => return Err(format!("input {} is of type {}, which isn't supported", name, kind)),
+    };
+    defines.push_str(&format!("#define {} {}\n", name, value));
+    Ok(())
+}
+
+/// Where `value` sits between `min` and `max`, 0.0 to 1.0
+fn scaled(value: f32, min: f32, max: f32) -> f32 {
+    if max > min {
+        (value - min) / (max - min)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An input of every type, see tests/fixtures/inputs.fs
+    const INPUTS: &str = include_str!("../tests/fixtures/inputs.fs");
+
+    /// A shader with `header` as its JSON and a body that just copies the picture
+    fn shader(header: &str) -> String {
+        format!("/*{}*/\nvoid main() {{ gl_FragColor = IMG_THIS_PIXEL(inputImage); }}\n", header)
+    }
+
+    #[test]
+    fn inputs_become_scaled_parameters() {
+        let translated = translate(INPUTS).unwrap();
+        let params: Vec<(&str, f32)> = translated.params.iter().map(|param| (param.name.as_str(), param.value)).collect();
+        assert_eq!(
+            params,
+            [
+                ("amount", 0.5),
+                ("steps", 0.5),
+                ("flip", 1.0),
+                ("tint_r", 1.0),
+                ("tint_g", 0.5),
+                ("tint_b", 0.25),
+                ("tint_a", 1.0),
+                ("center_x", 0.25),
+                ("center_y", 0.25),
+                ("offset_x", 0.5),
+                ("offset_y", 0.5),
+                ("previous", 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn translation_is_valid_wgsl() {
+        let translated = translate(INPUTS).unwrap();
+        let module = naga::front::wgsl::parse_str(&translated.wgsl).unwrap();
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+            .validate(&module)
+            .unwrap();
+        assert!(module.entry_points.iter().any(|entry_point| entry_point.name == "fs_main"));
+    }
+
+    #[test]
+    fn multiple_passes_are_rejected() {
+        let passes = shader(r#"{ "PASSES": [{ "TARGET": "blurred" }, {}] }"#);
+        assert!(translate(&passes).err().unwrap().contains("single pass"));
+        let target = shader(r#"{ "PASSES": [{ "TARGET": "blurred", "PERSISTENT": true }] }"#);
+        assert!(translate(&target).err().unwrap().contains("single pass"));
+        assert!(translate(&shader(r#"{ "PASSES": [{}] }"#)).is_ok());
+    }
+
+    #[test]
+    fn imported_images_are_rejected() {
+        let imported = shader(r#"{ "IMPORTED": { "noise": { "PATH": "noise.png" } } }"#);
+        assert!(translate(&imported).err().unwrap().contains("imported"));
+    }
+
+    #[test]
+    fn unknown_input_types_are_rejected() {
+        let audio = shader(r#"{ "INPUTS": [{ "NAME": "spectrum", "TYPE": "audioFFT" }] }"#);
+        assert_eq!(translate(&audio).err().unwrap(), "input spectrum is of type audioFFT, which isn't supported");
+    }
+
+    #[test]
+    fn shaders_without_a_header_are_rejected() {
+        assert!(translate("void main() { gl_FragColor = vec4(1.0); }").err().unwrap().contains("no JSON header"));
+    }
+}
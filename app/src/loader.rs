@@ -0,0 +1,82 @@
+use crate::clip::Clip;
+use crate::frame_cache::{self, SharedFrameCache};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use ffmpeg_next as ffmpeg;
+use std::path::PathBuf;
+use std::thread;
+
+/// Largest share of the frame cache one clip may fill with `--cache-all`, so caching it
+/// doesn't evict the frames of the clip on screen
+const CACHE_ALL_SHARE: f64 = 0.5;
+
+struct LoadRequest {
+    index: usize,
+    path: PathBuf,
+}
+
+/// A clip opened by the loader thread, ready to be swapped in
+pub struct Loaded {
+    pub path: PathBuf,
+    pub clip: Result<Clip, ffmpeg::Error>,
+}
+
+/// Opens and pre-decodes clips on a worker thread so the render loop never stalls
+pub struct Loader {
+    requests: Sender<LoadRequest>,
+    results: Receiver<Loaded>,
+}
+
+impl Loader {
+    pub fn new(cache: SharedFrameCache, cache_all: bool) -> Loader {
+        let (requests, request_rx) = unbounded::<LoadRequest>();
+        let (result_tx, results) = unbounded();
+
+        thread::Builder::new()
+            .name("clip-loader".into())
+            .spawn(move || {
+                for request in request_rx {
+                    log::info!("Preloading file {}: {}", request.index + 1, request.path.display());
+                    let clip = load(&request.path, cache.clone(), cache_all);
+                    let loaded = Loaded { path: request.path, clip };
+                    if result_tx.send(loaded).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to spawn clip loader thread");
+
+        Loader { requests, results }
+    }
+
+    /// Queue a file to be opened in the background
+    pub fn request(&self, index: usize, path: PathBuf) {
+        if let Err(e) = self.requests.send(LoadRequest { index, path }) {
+            log::error!("The clip loader has stopped, {} won't be opened", e.into_inner().path.display());
+        }
+    }
+
+    /// A finished clip, if any
+    pub fn try_recv(&self) -> Option<Loaded> {
+        self.results.try_recv().ok()
+    }
+}
+
+fn load(path: &std::path::Path, cache: SharedFrameCache, cache_all: bool) -> Result<Clip, ffmpeg::Error> {
+    let budget = cache.lock().unwrap().budget_bytes();
+    let mut clip = Clip::new(path, cache)?;
+    // Fill the read-ahead so the first frames are ready when the clip is swapped in
+    let first = clip.play_video_at_position(0.0);
+    if cache_all {
+        let estimate = frame_cache::frame_bytes(&first) * clip.frame_count();
+        if estimate as f64 <= budget as f64 * CACHE_ALL_SHARE {
+            clip.cache_all_frames()?;
+        } else {
+            log::warn!(
+                "Not caching all of {}, about {} MB is more than the frame cache can spare",
+                path.display(),
+                estimate / (1024 * 1024)
+            );
+        }
+    }
+    Ok(clip)
+}
@@ -1,54 +1,174 @@
 mod app;
+mod cli;
 mod clip;
+mod compositor;
+mod config;
+mod control;
+mod effects;
+mod encode;
+mod frame_cache;
+mod grid;
+mod isf;
+mod loader;
+mod media;
+mod midi_map;
+mod osc;
+mod probe;
+mod render;
+mod show;
 mod state;
+mod text;
+mod transition;
 mod vertex;
+mod watch;
+mod yuv;
 
-use transport::link::Link;
+use transport::{
+    clock::InternalClock,
+    link::Link,
+    midi_clock::{MidiClock, MidiConnection},
+    timecode::{AudioInput, TimecodeClock},
+    Transport,
+};
 
+use cli::{Command, Options, TranscodeOptions, TransportKind, WhenStopped};
 use ffmpeg_next as ffmpeg;
-use once_cell::sync::Lazy;
-use std::path::PathBuf;
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
 use winit::{
     event::Event,
     event_loop::EventLoop,
     window::{Window, WindowBuilder},
 };
 
-static LINK: Lazy<Mutex<Link>> = Lazy::new(|| Mutex::new(Link::new()));
+/// Window size when `--size` is not given
+const DEFAULT_WINDOW_SIZE: (u32, u32) = (1280, 720);
 
+/// Colour of the slate shown when `--when-stopped slate` is given without `--slate`
+const DEFAULT_SLATE_RGBA: [u8; 4] = [24, 24, 24, 255];
 
-#[pollster::main]
-async fn main() {
-    env_logger::init();
-    ffmpeg::init().unwrap();
-    let path_arg = std::env::args()
-        .nth(1)
-        .expect("Please provide a video file or folder path");
+/// Virtual MIDI port opened for clock when `--midi-port` is not given
+const MIDI_CLOCK_PORT: &str = "voop clock";
+
+/// Virtual MIDI port opened for MTC when `--midi-port` is not given
+const MTC_PORT: &str = "voop timecode";
 
-    let (files, current_index) = load_files(&path_arg);
+/// Inputs feeding the transport, open for as long as this is kept
+#[derive(Default)]
+struct TransportInputs {
+    _midi: Option<MidiConnection>,
+    _audio: Option<AudioInput>,
+}
+
+fn main() {
+    let cli = cli::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, cli::USAGE);
+        std::process::exit(2);
+    });
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = cli.log_level {
+        logger.filter_level(level);
+    }
+    logger.init();
+    ffmpeg::init().unwrap();
 
-    // Load first file
-    let first_file = &files[current_index];
-    println!("Opening file: {}\n", first_file.display());
+    let result = match cli.command {
+        Command::Play(options) => pollster::block_on(play(options)),
+        Command::Render(options, render) => {
+            load_grid(&options).and_then(|(show, _)| pollster::block_on(render::run(&options, &render, show)))
+        }
+        Command::Probe(files) => return probe_files(&files),
+        Command::Transcode(options) => return transcode_files(&options),
+        Command::ListOutputs => return list_outputs(),
+        Command::Help => return print!("{}", cli::USAGE),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
 
-    let mut clip = clip::Clip::new(first_file.to_str().unwrap()).unwrap();
-    let _ = clip.cache_all_frames();
+/// Open the window and play until it is closed, or say why it can't be
+async fn play(options: Options) -> Result<(), String> {
+    let (show, first_scene) = load_grid(&options)?;
+    let grid = show.grid;
+    let cache = frame_cache::FrameCache::shared(options.cache_mb * 1024 * 1024);
 
     // Main loop
 
-    let event_loop = EventLoop::new().unwrap();
-    let window = WindowBuilder::new()
+    let event_loop = EventLoop::new().map_err(|e| format!("Failed to open a window: {}", e))?;
+    let monitor = options
+        .monitor
+        .map(|index| {
+            let monitor = event_loop.available_monitors().nth(index);
+            monitor.ok_or_else(|| format!("There is no monitor {}, see list-outputs", index + 1))
+        })
+        .transpose()?;
+    let (width, height) = options.size.unwrap_or(DEFAULT_WINDOW_SIZE);
+    let mut window = WindowBuilder::new()
         .with_title("Voop Video Player")
-        .with_inner_size(winit::dpi::LogicalSize::new(1280, 720))
-        .with_min_inner_size(winit::dpi::LogicalSize::new(640, 360))
-        .build(&event_loop)
-        .unwrap();
+        .with_inner_size(winit::dpi::LogicalSize::new(width, height))
+        .with_min_inner_size(winit::dpi::LogicalSize::new(640, 360));
+    if let Some(monitor) = &monitor {
+        window = window.with_position(monitor.position());
+    }
+    let window = window.build(&event_loop).map_err(|e| format!("Failed to open a window: {}", e))?;
 
     // Create a static reference to the window (required for State lifetime)
     let window: &'static Window = Box::leak(Box::new(window));
 
-    let mut app = app::App::new(window, clip, files, current_index).await;
+    let (transport, _inputs) = open_transport(&options)?;
+
+    let loader = loader::Loader::new(cache, options.cache_all);
+
+    let stopped_behavior = match options.when_stopped {
+        WhenStopped::Freeze => app::StoppedBehavior::Freeze,
+        WhenStopped::Slate => app::StoppedBehavior::Slate(load_slate(options.slate.as_deref())?),
+        WhenStopped::Black => app::StoppedBehavior::Black,
+    };
+
+    let mut app = app::App::new(window, loader, transport, grid, first_scene).await;
+    app.set_stopped_behavior(stopped_behavior);
+    app.state.set_monitor(monitor.clone());
+    if let Some(fps) = options.fps {
+        app.set_target_fps(fps);
+    }
+    if let Some(timeout) = options.cursor_timeout {
+        app.set_cursor_timeout(timeout);
+    }
+    if let Some(path) = show.luma_matte.as_ref().or(options.luma_matte.as_ref()) {
+        let matte = image::open(path)
+            .map_err(|e| format!("Failed to open luma matte {}: {}", path.display(), e))?
+            .into_rgba8();
+        app.state.set_luma_matte(&matte);
+    }
+    if let Some(folder) = show.shader_dir.as_ref().or(options.shader_dir.as_ref()) {
+        app.load_shader_folder(folder)
+            .map_err(|e| format!("Failed to read shader folder {}: {}", folder.display(), e))?;
+    }
+    app.state.effects_mut().configure(&options.effect_params, &options.effects)?;
+    app.apply_scene_settings();
+    if show.fullscreen.unwrap_or(options.fullscreen || monitor.is_some()) {
+        app.state.set_fullscreen(true);
+    }
+    if let Some(opacity) = show.opacity {
+        app.state.set_opacity(opacity);
+    }
+    let osc_server = options.osc_port.map(|port| {
+        osc::OscServer::bind(port).map_err(|e| format!("Failed to listen for OSC on port {}: {}", port, e))
+    });
+    app.set_osc_server(osc_server.transpose()?);
+    let midi_control = options.midi_control.as_deref().map(|port| {
+        midi_map::MidiControlSurface::open(port, options.midi_map.clone())
+            .map_err(|e| format!("Failed to open MIDI controller: {}", e))
+    });
+    app.set_midi_control(midi_control.transpose()?);
+
+    // A show file lists its own clips, a folder is followed as files come and go
+    let folder = options.path.as_deref().map(PathBuf::from).filter(|path| options.show.is_none() && path.is_dir());
+    if let Some(folder) = folder {
+        let filter = options.media_filter.clone();
+        app.watch_media_folder(&folder, filter, Box::new(move |file, path| default_slot(&options, file, path)));
+    }
 
     let _ = event_loop.run(move |event, control_flow| match event {
         Event::WindowEvent {
@@ -59,49 +179,183 @@ async fn main() {
         }
         _ => {}
     });
+    Ok(())
 }
 
-/// just roads all video files from provided path to a vec
-fn load_files(path_arg: &str) -> (Vec<PathBuf>, usize) {
-    let path = PathBuf::from(&path_arg);
-    // Determine if it's a file or directory
-    let (files, current_index) = if path.is_dir() {
-        // Load all video files from directory
-        let mut video_files: Vec<PathBuf> = std::fs::read_dir(&path)
-            .expect("Failed to read directory")
-            .filter_map(|entry| {
-                let entry = entry.ok()?;
-                let path = entry.path();
-                if path.is_file() {
-                    let ext = path.extension()?.to_str()?;
-                    if matches!(
-                        ext.to_lowercase().as_str(),
-                        "mp4" | "avi" | "mov" | "mkv" | "webm"
-                    ) {
-                        Some(path)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            })
-            .collect();
+/// Print what voop makes of each file, exiting with an error if any can't be read
+fn probe_files(files: &[PathBuf]) {
+    let mut failed = false;
+    for path in files {
+        match probe::probe(path) {
+            Ok(probe) => println!("{}: {}", path.display(), probe),
+            Err(e) => {
+                println!("{}: {}", path.display(), e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
 
-        video_files.sort();
+/// Re-encode each file into the output folder as a `.mov` of the same name
+fn transcode_files(options: &TranscodeOptions) {
+    if let Err(e) = std::fs::create_dir_all(&options.output_dir) {
+        eprintln!("Failed to create {}: {}", options.output_dir.display(), e);
+        std::process::exit(1);
+    }
+    let mut failed = false;
+    for input in &options.files {
+        let name = input.file_stem().unwrap_or(input.as_os_str()).to_string_lossy();
+        let output = options.output_dir.join(format!("{}.mov", name));
+        if output == *input {
+            eprintln!("Not transcoding {} over itself, pick another --output-dir", input.display());
+            failed = true;
+            continue;
+        }
+        println!("{} -> {}", input.display(), output.display());
+        if let Err(e) = encode::transcode(input, &output, &options.codec) {
+            eprintln!("Failed to transcode {}: {}", input.display(), e);
+            failed = true;
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Print the monitors `--monitor` picks from, numbered from 1
+fn list_outputs() {
+    let event_loop = EventLoop::new().unwrap();
+    for (index, monitor) in event_loop.available_monitors().enumerate() {
+        let size = monitor.size();
+        let position = monitor.position();
+        let refresh = monitor
+            .refresh_rate_millihertz()
+            .map_or(String::new(), |millihertz| format!(", {:.2} Hz", millihertz as f64 / 1000.0));
+        println!(
+            "{}: {} {}x{} at {},{}{}",
+            index + 1,
+            monitor.name().unwrap_or_else(|| "unnamed".to_string()),
+            size.width,
+            size.height,
+            position.x,
+            position.y,
+            refresh
+        );
+    }
+}
 
-        if video_files.is_empty() {
-            panic!("No video files found in directory: {}", path_arg);
+/// Start the transport chosen on the command line, along with the inputs feeding it
+fn open_transport(options: &Options) -> Result<(Box<dyn Transport>, TransportInputs), String> {
+    let mut inputs = TransportInputs::default();
+    let transport: Box<dyn Transport> = match options.transport {
+        TransportKind::Link => Box::new(Link::new(options.bpm, options.quantum)),
+        TransportKind::Internal => Box::new(InternalClock::new(options.bpm, options.quantum)),
+        TransportKind::Midi => {
+            let clock = MidiClock::new(options.quantum);
+            let connection = match &options.midi_port {
+                Some(port) => clock.connect(port),
+                None => clock.create_virtual(MIDI_CLOCK_PORT),
+            };
+            inputs._midi = Some(connection.map_err(|e| format!("Failed to open MIDI clock input: {}", e))?);
+            Box::new(clock)
+        }
+        TransportKind::Mtc => {
+            let clock = TimecodeClock::new(options.bpm, options.quantum);
+            let connection = match &options.midi_port {
+                Some(port) => clock.connect_mtc(port),
+                None => clock.create_virtual_mtc(MTC_PORT),
+            };
+            inputs._midi = Some(connection.map_err(|e| format!("Failed to open MTC input: {}", e))?);
+            Box::new(clock)
+        }
+        TransportKind::Ltc => {
+            let clock = TimecodeClock::new(options.bpm, options.quantum);
+            match &options.ltc_wav {
+                Some(path) => clock
+                    .play_ltc_wav(path)
+                    .map_err(|e| format!("Failed to read LTC from {}: {}", path.display(), e))?,
+                None => {
+                    let input = clock
+                        .connect_ltc(options.ltc_input.as_deref())
+                        .map_err(|e| format!("Failed to open LTC input: {}", e))?;
+                    inputs._audio = Some(input);
+                }
+            }
+            Box::new(clock)
         }
+    };
+    Ok((transport, inputs))
+}
 
-        println!("Found {} video files in directory", video_files.len());
-        (video_files, 0)
-    } else if path.is_file() {
-        // Single file
-        println!("Loading single file: {}", path_arg);
-        (vec![path], 0)
+/// The show file from `--show`, or a single track playing the files at the path given,
+/// with the scene to start on
+fn load_grid(options: &Options) -> Result<(show::Show, usize), String> {
+    let slot_for = |file: usize, path: &Path| default_slot(options, file, path);
+    if let Some(path) = &options.show {
+        let shader_dir = options.shader_dir.as_deref();
+        let show = show::load(path, options.timecode_rate, options.launch_quantize, shader_dir, slot_for)
+            .map_err(|e| format!("Failed to read show file {}: {}", path.display(), e))?;
+        println!("Loaded {} scenes on {} tracks from {}", show.grid.scenes.len(), show.grid.tracks.len(), path.display());
+        return Ok((show, 0));
+    }
+
+    let path = options.path.as_deref().ok_or("Please provide a video file or folder path, or --show FILE")?;
+    let (files, current_index) = load_files(path, &options.media_filter)?;
+    let show = show::Show {
+        grid: grid::Grid::from_files(files, slot_for, options.launch_quantize),
+        fullscreen: None,
+        opacity: None,
+        luma_matte: None,
+        shader_dir: None,
+    };
+    Ok((show, current_index))
+}
+
+/// How a file plays unless the show file says otherwise, from the command line and config file
+fn default_slot(options: &Options, file: usize, path: &Path) -> grid::Slot {
+    let name = grid::file_name(path);
+    let loop_mode = if options.transport.is_timecode() {
+        let offset_secs = options.timecode_offsets.get(name).copied().unwrap_or(0.0);
+        clip::LoopMode::Timecode { offset_secs }
     } else {
-        panic!("Path does not exist: {}", path_arg);
+        options.loop_mode
     };
-    (files, current_index)
+    let launch_quantize = options.clip_launch_quantize.get(name).copied().unwrap_or(options.launch_quantize);
+    grid::Slot { file, loop_mode, launch_quantize, transition: options.transition }
+}
+
+/// The holding slate: the given image, or a plain dark frame
+fn load_slate(path: Option<&std::path::Path>) -> Result<image::RgbaImage, String> {
+    match path {
+        Some(path) => image::open(path)
+            .map(|image| image.into_rgba8())
+            .map_err(|e| format!("Failed to open slate {}: {}", path.display(), e)),
+        None => Ok(image::RgbaImage::from_pixel(16, 9, image::Rgba(DEFAULT_SLATE_RGBA))),
+    }
+}
+
+/// The file or image sequence at `path`, or every playable one in the folder it names and
+/// the folders inside it, printing the files passed over
+fn load_files(path_arg: &str, filter: &media::Filter) -> Result<(Vec<PathBuf>, usize), String> {
+    let path = PathBuf::from(&path_arg);
+    if path.is_dir() {
+        let scan = media::scan(&path, filter);
+        for skipped in &scan.skipped {
+            println!("Skipped {}: {}", skipped.path.display(), skipped.reason);
+        }
+        if scan.files.is_empty() {
+            return Err(format!("No video files found in {}, skipped {}", path_arg, scan.skipped.len()));
+        }
+        println!("Found {} video files in directory, skipped {}", scan.files.len(), scan.skipped.len());
+        Ok((scan.files, 0))
+    } else if media::exists(&path) {
+        // Single file or image sequence
+        println!("Loading single file: {}", path_arg);
+        Ok((vec![path], 0))
+    } else {
+        Err(format!("Path does not exist: {}", path_arg))
+    }
 }
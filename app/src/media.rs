@@ -0,0 +1,369 @@
+use crate::probe;
+use ffmpeg_next as ffmpeg;
+use glob::{MatchOptions, Pattern};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Globs ignore case, so `*.mov` also takes `CLIP.MOV`, and `*` matches across folders
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/// Which files of a media folder are looked at, by globs matched against their path inside it
+#[derive(Clone)]
+pub struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Filter {
+    /// Files matching one of `include`, or any file if it is empty, and none of `exclude`
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Filter, String> {
+        let compile = |globs: &[String]| {
+            globs
+                .iter()
+                .map(|glob| Pattern::new(glob).map_err(|e| format!("Invalid glob `{}`: {}", glob, e)))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Filter { include: compile(include)?, exclude: compile(exclude)? })
+    }
+
+    /// Why the file at `relative` inside the folder is left out, if it is
+    fn rejects(&self, relative: &Path) -> Option<&'static str> {
+        let matches = |pattern: &Pattern| pattern.matches_path_with(relative, MATCH_OPTIONS);
+        if !self.include.is_empty() && !self.include.iter().any(matches) {
+            Some("not matched by an include glob")
+        } else if self.exclude.iter().any(matches) {
+            Some("matched by an exclude glob")
+        } else {
+            None
+        }
+    }
+}
+
+/// A file found in a media folder that won't be played
+pub struct Skipped {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// What [`scan`] found
+pub struct Scan {
+    /// playable files and image sequences, in path order
+    pub files: Vec<PathBuf>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// Fewest frames numbered one after the other that make an image sequence, so a few
+/// stills like `IMG_0001.jpg`, `IMG_0002.jpg` are played one by one
+const MIN_SEQUENCE_FRAMES: usize = 24;
+
+/// Files to probe: single files, and numbered files that may make an image sequence
+enum Candidate {
+    File(PathBuf),
+    Numbered { pattern: PathBuf, frames: Vec<PathBuf> },
+}
+
+/// Look through `folder` and every folder inside it for files ffmpeg can play a video
+/// stream from, whatever their extension. Numbered images like `shot_0001.png`,
+/// `shot_0002.png`... numbered without gaps make one image sequence, played as a clip
+/// with a path holding an ffmpeg pattern, `shot_%04d.png`. Hidden files and folders are
+/// passed over.
+pub fn scan(folder: &Path, filter: &Filter) -> Scan {
+    let mut scan = Scan { files: Vec::new(), skipped: Vec::new() };
+    let files = walk(folder, filter, &mut scan.skipped);
+    for candidate in candidates(files) {
+        match candidate {
+            Candidate::File(path) => match playable(&path) {
+                Ok(()) => scan.files.push(path),
+                Err(reason) => scan.skipped.push(Skipped { path, reason }),
+            },
+            Candidate::Numbered { pattern, frames } => {
+                if playable(&pattern).is_ok() {
+                    scan.files.push(pattern);
+                    continue;
+                }
+                // Numbered videos, `take1.mov`, `take2.mov`..., are clips of their own
+                for path in frames {
+                    match playable(&path) {
+                        Ok(()) => scan.files.push(path),
+                        Err(reason) => scan.skipped.push(Skipped { path, reason }),
+                    }
+                }
+            }
+        }
+    }
+    scan
+}
+
+/// Files under `folder` the filter lets through, without looking inside them
+pub fn list(folder: &Path, filter: &Filter) -> Vec<PathBuf> {
+    walk(folder, filter, &mut Vec::new())
+}
+
+/// Whether `file` is the file or one of the frames of the image sequence at `path`
+pub fn covers(path: &Path, file: &Path) -> bool {
+    if path == file {
+        return true;
+    }
+    let (Some((prefix, width, suffix)), Some(name)) =
+        (path.file_name().and_then(|name| parse_pattern(name.to_str()?)), file.file_name().and_then(|name| name.to_str()))
+    else {
+        return false;
+    };
+    let digits = name.strip_prefix(&prefix).and_then(|rest| rest.strip_suffix(&suffix));
+    path.parent() == file.parent()
+        && digits.is_some_and(|digits| digits.len() >= width && digits.bytes().all(|byte| byte.is_ascii_digit()))
+}
+
+/// Whether the file or image sequence at `path` is there to be played
+pub fn exists(path: &Path) -> bool {
+    path.is_file() || sequence_start(path).is_some()
+}
+
+/// Open a video file, or an image sequence given by a pattern like `shot_%04d.png`
+pub fn open(path: &Path) -> Result<ffmpeg::format::context::Input, ffmpeg::Error> {
+    match sequence_start(path) {
+        // ffmpeg only looks for the first frame among the first few numbers by itself
+        Some(start) => {
+            let mut options = ffmpeg::Dictionary::new();
+            options.set("start_number", &start.to_string());
+            ffmpeg::format::input_with_dictionary(&path, options)
+        }
+        None => ffmpeg::format::input(&path),
+    }
+}
+
+/// Files under `folder` in path order, hidden ones passed over and the ones `filter`
+/// rejects added to `skipped`
+fn walk(folder: &Path, filter: &Filter, skipped: &mut Vec<Skipped>) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let walk = WalkDir::new(folder)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.'));
+    for entry in walk {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let path = e.path().unwrap_or(folder).to_path_buf();
+                skipped.push(Skipped { path, reason: e.to_string() });
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(folder).unwrap_or(entry.path());
+        match filter.rejects(relative) {
+            Some(reason) => skipped.push(Skipped { path: entry.into_path(), reason: reason.to_string() }),
+            None => files.push(entry.into_path()),
+        }
+    }
+    files
+}
+
+/// Ok if ffmpeg can play a video stream from `path`, else why not
+fn playable(path: &Path) -> Result<(), String> {
+    match probe::probe(path) {
+        // ffmpeg reads text files as ANSI art
+        Ok(probe) if probe.codec == "ansi" => Err("text, not video".to_string()),
+        Ok(_) => Ok(()),
+        Err(ffmpeg::Error::StreamNotFound) => Err("no video stream".to_string()),
+        Err(ffmpeg::Error::InvalidData) => Err("not a format ffmpeg reads".to_string()),
+        Err(ffmpeg::Error::DecoderNotFound) => Err("no decoder for its codec".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// `files` in the same order, with those differing only by a number at the end of their
+/// name gathered where the first of them is, if they are numbered without gaps and there
+/// are at least [`MIN_SEQUENCE_FRAMES`] of them
+fn candidates(files: Vec<PathBuf>) -> Vec<Candidate> {
+    let mut runs: HashMap<(PathBuf, String, String), Vec<(u64, usize)>> = HashMap::new();
+    for path in &files {
+        if let Some((key, digits)) = numbered(path) {
+            runs.entry(key).or_default().push((digits.parse().unwrap_or(u64::MAX), digits.len()));
+        }
+    }
+    // ffmpeg stops at the first gap, and a pattern can't leave files out
+    runs.retain(|_, run| {
+        run.sort();
+        run.len() >= MIN_SEQUENCE_FRAMES && run.windows(2).all(|pair| pair[1].0 == pair[0].0 + 1)
+    });
+
+    let mut sequences: HashMap<_, Vec<(u64, PathBuf)>> = HashMap::new();
+    let mut order = Vec::new();
+    for path in files {
+        let Some((key, digits)) = numbered(&path).filter(|(key, _)| runs.contains_key(key)) else {
+            order.push(Err(path));
+            continue;
+        };
+        let frames = sequences.entry(key.clone()).or_default();
+        if frames.is_empty() {
+            order.push(Ok(key));
+        }
+        frames.push((digits.parse().unwrap_or(u64::MAX), path));
+    }
+
+    order
+        .into_iter()
+        .map(|item| match item {
+            Err(path) => Candidate::File(path),
+            Ok(key) => {
+                // `%0Nd` with the shortest number's width also matches the longer ones,
+                // so `1`..`10` and `0998`..`1000` both work
+                let width = runs[&key].iter().map(|&(_, width)| width).min().unwrap_or(1);
+                let mut frames = sequences.remove(&key).unwrap_or_default();
+                frames.sort();
+                let (folder, prefix, suffix) = key;
+                let name = format!("{}%0{}d{}", prefix.replace('%', "%%"), width, suffix.replace('%', "%%"));
+                Candidate::Numbered {
+                    pattern: folder.join(name),
+                    frames: frames.into_iter().map(|(_, path)| path).collect(),
+                }
+            }
+        })
+        .collect()
+}
+
+/// The folder, name before the number and extension of a file whose name ends in a number,
+/// with the number
+fn numbered(path: &Path) -> Option<((PathBuf, String, String), String)> {
+    let stem = path.file_stem()?.to_str()?;
+    let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    let digits = &stem[prefix.len()..];
+    digits.parse::<u64>().ok()?;
+    let suffix = match path.extension() {
+        Some(extension) => format!(".{}", extension.to_str()?),
+        None => String::new(),
+    };
+    Some(((path.parent()?.to_path_buf(), prefix.to_string(), suffix), digits.to_string()))
+}
+
+/// First frame number of the image sequence `path` is a pattern of, if it is one
+fn sequence_start(path: &Path) -> Option<u64> {
+    if path.exists() {
+        return None;
+    }
+    let (prefix, width, suffix) = parse_pattern(path.file_name()?.to_str()?)?;
+    let folder = path.parent().filter(|folder| !folder.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    std::fs::read_dir(folder)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let digits = name.strip_prefix(&prefix)?.strip_suffix(&suffix)?;
+            if digits.len() < width || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+            digits.parse().ok()
+        })
+        .min()
+}
+
+/// The text before and after the `%0Nd` of a file name pattern, with N
+fn parse_pattern(name: &str) -> Option<(String, usize, String)> {
+    let mut prefix = String::new();
+    let mut rest = name;
+    while let Some(at) = rest.find('%') {
+        prefix.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+        if let Some(after) = rest.strip_prefix('%') {
+            prefix.push('%');
+            rest = after;
+            continue;
+        }
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let suffix = rest[digits..].strip_prefix('d')?;
+        let width = if digits == 0 { 0 } else { rest[..digits].parse().ok()? };
+        return Some((prefix, width, suffix.replace("%%", "%")));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `candidates` makes of `names`, a sequence written as its pattern and first and last frames
+    fn describe(names: &[String]) -> Vec<String> {
+        candidates(names.iter().map(|name| Path::new("clips").join(name)).collect())
+            .into_iter()
+            .map(|candidate| match candidate {
+                Candidate::File(path) => path.file_name().unwrap().to_string_lossy().into_owned(),
+                Candidate::Numbered { pattern, frames } => {
+                    let name = |path: Option<&PathBuf>| path.unwrap().file_name().unwrap().to_string_lossy().into_owned();
+                    let pattern = pattern.file_name().unwrap().to_string_lossy().into_owned();
+                    format!("{} {}..{}", pattern, name(frames.first()), name(frames.last()))
+                }
+            })
+            .collect()
+    }
+
+    fn frames(prefix: &str, numbers: impl IntoIterator<Item = u32>, width: usize, suffix: &str) -> Vec<String> {
+        numbers.into_iter().map(|n| format!("{prefix}{n:0width$}{suffix}")).collect()
+    }
+
+    #[test]
+    fn a_long_gapless_run_is_a_sequence() {
+        let mut names = vec!["intro.mov".to_string()];
+        names.extend(frames("shot_", 1..=30, 4, ".png"));
+        names.push("outro.mov".to_string());
+        assert_eq!(describe(&names), ["intro.mov", "shot_%04d.png shot_0001.png..shot_0030.png", "outro.mov"]);
+    }
+
+    #[test]
+    fn camera_stills_stay_apart() {
+        let names = frames("IMG_", 1..=3, 4, ".jpg");
+        assert_eq!(describe(&names), names);
+        // Enough of them, but with gaps where shots were deleted
+        let names = frames("IMG_", (1..=60).filter(|n| n % 7 != 0), 4, ".jpg");
+        assert_eq!(describe(&names), names);
+        let names = frames("take", 1..=2, 0, ".mov");
+        assert_eq!(describe(&names), names);
+    }
+
+    #[test]
+    fn numbers_growing_past_their_width_share_a_pattern() {
+        assert_eq!(describe(&frames("f", 1..=30, 0, ".exr")), ["f%01d.exr f1.exr..f30.exr"]);
+        assert_eq!(describe(&frames("f", 980..=1010, 4, ".exr")), ["f%04d.exr f0980.exr..f1010.exr"]);
+        // Walked in name order, so `f1000.exr` comes before `f980.exr`
+        let mut names = frames("f", 980..=1010, 0, ".exr");
+        names.sort();
+        assert_eq!(describe(&names), ["f%03d.exr f980.exr..f1010.exr"]);
+    }
+
+    #[test]
+    fn percent_signs_are_escaped_and_read_back() {
+        assert_eq!(describe(&frames("100%_", 1..=24, 3, ".png")), ["100%%_%03d.png 100%_001.png..100%_024.png"]);
+        assert_eq!(parse_pattern("100%%_%03d.png"), Some(("100%_".to_string(), 3, ".png".to_string())));
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(parse_pattern("shot_%04d.png"), Some(("shot_".to_string(), 4, ".png".to_string())));
+        assert_eq!(parse_pattern("%d"), Some((String::new(), 0, String::new())));
+        assert_eq!(parse_pattern("shot_0001.png"), None);
+        assert_eq!(parse_pattern("shot_%04x.png"), None);
+        assert_eq!(parse_pattern("50%%.png"), None);
+    }
+
+    #[test]
+    fn a_sequence_covers_its_frames() {
+        let sequence = Path::new("clips/shot_%04d.png");
+        assert!(covers(sequence, sequence));
+        assert!(covers(sequence, Path::new("clips/shot_0001.png")));
+        assert!(covers(sequence, Path::new("clips/shot_12345.png")));
+        assert!(!covers(sequence, Path::new("clips/shot_001.png")));
+        assert!(!covers(sequence, Path::new("clips/shot_000a.png")));
+        assert!(!covers(sequence, Path::new("clips/shot_0001.jpg")));
+        assert!(!covers(sequence, Path::new("other/shot_0001.png")));
+        assert!(covers(Path::new("clips/a.mov"), Path::new("clips/a.mov")));
+        assert!(!covers(Path::new("clips/a.mov"), Path::new("clips/b.mov")));
+    }
+}
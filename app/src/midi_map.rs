@@ -0,0 +1,399 @@
+use crate::compositor::BlendMode;
+use crate::control::Action;
+use crossbeam_channel::{unbounded, Receiver};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use transport::midi_clock::{self, MidiConnection, MidiError};
+
+/// Mapping file used when `--midi-map` is not given
+pub const DEFAULT_MIDI_MAP_PATH: &str = "voop-midi.toml";
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PROGRAM_CHANGE: u8 = 0xC0;
+const CHANNEL_PRESSURE: u8 = 0xD0;
+/// Real-time messages are this or above, a single byte that may arrive anywhere
+const REAL_TIME: u8 = 0xF8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlKind {
+    Note,
+    Cc,
+}
+
+/// A pad, key, knob or fader on a controller
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MidiControl {
+    pub kind: ControlKind,
+    /// 1 to 16
+    pub channel: u8,
+    /// note or controller number
+    pub number: u8,
+}
+
+impl fmt::Display for MidiControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            ControlKind::Note => "note",
+            ControlKind::Cc => "CC",
+        };
+        write!(f, "{} {} on channel {}", kind, self.number, self.channel)
+    }
+}
+
+/// A control moving, with its velocity or value. Note off arrives as value 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MidiMessage {
+    pub control: MidiControl,
+    pub value: u8,
+}
+
+impl MidiMessage {
+    pub fn parse(bytes: &[u8]) -> Option<MidiMessage> {
+        let [status, number, value, ..] = *bytes else {
+            return None;
+        };
+        let (kind, value) = match status & 0xF0 {
+            NOTE_ON => (ControlKind::Note, value),
+            NOTE_OFF => (ControlKind::Note, 0),
+            CONTROL_CHANGE => (ControlKind::Cc, value),
+            _ => return None,
+        };
+        let control = MidiControl { kind, channel: (status & 0x0F) + 1, number };
+        Some(MidiMessage { control, value })
+    }
+
+    fn pressed(&self) -> bool {
+        self.value > 0
+    }
+
+    /// The value as a fader position from 0.0 to 1.0
+    fn amount(&self) -> f32 {
+        self.value as f32 / 127.0
+    }
+}
+
+/// Splits raw MIDI into messages, keeping the last status byte for senders that leave it
+/// out while it repeats (running status)
+#[derive(Default)]
+pub struct MidiParser {
+    running: Option<u8>,
+    data: Vec<u8>,
+}
+
+impl MidiParser {
+    /// Note and control change messages completed by `bytes`
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<MidiMessage> {
+        let mut messages = Vec::new();
+        for &byte in bytes {
+            if byte >= REAL_TIME {
+                continue;
+            }
+            if byte & 0x80 != 0 {
+                // System messages end running status
+                self.running = (byte < 0xF0).then_some(byte);
+                self.data.clear();
+                continue;
+            }
+            let Some(status) = self.running else {
+                continue;
+            };
+            self.data.push(byte);
+            let len = match status & 0xF0 {
+                PROGRAM_CHANGE | CHANNEL_PRESSURE => 1,
+                _ => 2,
+            };
+            if self.data.len() < len {
+                continue;
+            }
+            let value = self.data.get(1).copied().unwrap_or(0);
+            messages.extend(MidiMessage::parse(&[status, self.data[0], value]));
+            self.data.clear();
+        }
+        messages
+    }
+}
+
+/// What a mapped control does
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum MidiTarget {
+    // Older mappings were written when every file was a scene of its own
+    #[serde(alias = "next_clip")]
+    NextScene,
+    #[serde(alias = "previous_clip")]
+    PreviousScene,
+    /// launch the scene at this index, counted from 0
+    #[serde(alias = "select_clip")]
+    LaunchScene {
+        #[serde(alias = "clip")]
+        scene: usize,
+    },
+    /// launch one slot of the grid, both counted from 0
+    LaunchClip { track: usize, scene: usize },
+    StopTrack { track: usize },
+    ToggleFullscreen,
+    /// follows a fader or knob
+    Opacity,
+    /// follows a fader or knob
+    TrackOpacity { track: usize },
+    /// follows a fader or knob
+    Crossfader,
+    BlendMode { track: usize, mode: BlendMode },
+    ToggleEffect { effect: String },
+    /// follows a fader or knob
+    EffectParam { effect: String, param: String },
+}
+
+impl MidiTarget {
+    /// The action a message on a control mapped here asks for. Buttons act when pressed, not released.
+    fn action(&self, message: &MidiMessage) -> Option<Action> {
+        match *self {
+            MidiTarget::NextScene if message.pressed() => Some(Action::NextScene),
+            MidiTarget::PreviousScene if message.pressed() => Some(Action::PreviousScene),
+            MidiTarget::LaunchScene { scene } if message.pressed() => Some(Action::LaunchScene(scene)),
+            MidiTarget::LaunchClip { track, scene } if message.pressed() => Some(Action::LaunchClip { track, scene }),
+            MidiTarget::StopTrack { track } if message.pressed() => Some(Action::StopTrack(track)),
+            MidiTarget::ToggleFullscreen if message.pressed() => Some(Action::ToggleFullscreen),
+            MidiTarget::Opacity => Some(Action::SetOpacity(message.amount())),
+            MidiTarget::TrackOpacity { track } => Some(Action::SetTrackOpacity { track, opacity: message.amount() }),
+            MidiTarget::Crossfader => Some(Action::SetCrossfader(message.amount())),
+            MidiTarget::BlendMode { track, mode } if message.pressed() => Some(Action::SetBlendMode { track, mode }),
+            MidiTarget::ToggleEffect { ref effect } if message.pressed() => Some(Action::ToggleEffect(effect.clone())),
+            MidiTarget::EffectParam { ref effect, ref param } => Some(Action::SetEffectParam {
+                effect: effect.clone(),
+                param: param.clone(),
+                value: message.amount(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Binding {
+    #[serde(flatten)]
+    control: MidiControl,
+    #[serde(flatten)]
+    target: MidiTarget,
+}
+
+/// Which controls do what, as stored in the mapping file
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MidiMap {
+    #[serde(default, rename = "binding")]
+    bindings: Vec<Binding>,
+}
+
+#[derive(Debug)]
+pub enum MidiMapError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+}
+
+impl fmt::Display for MidiMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiMapError::Io(e) => write!(f, "{}", e),
+            MidiMapError::Parse(e) => write!(f, "{}", e),
+            MidiMapError::Serialize(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl MidiMap {
+    /// The mapping in `path`, or an empty one if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<MidiMap, MidiMapError> {
+        if !path.exists() {
+            return Ok(MidiMap::default());
+        }
+        let text = std::fs::read_to_string(path).map_err(MidiMapError::Io)?;
+        toml::from_str(&text).map_err(MidiMapError::Parse)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), MidiMapError> {
+        let text = toml::to_string_pretty(self).map_err(MidiMapError::Serialize)?;
+        std::fs::write(path, text).map_err(MidiMapError::Io)
+    }
+
+    pub fn action(&self, message: &MidiMessage) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.control == message.control)
+            .and_then(|binding| binding.target.action(message))
+    }
+
+    /// Make `control` do `target`, replacing what it did before
+    pub fn bind(&mut self, control: MidiControl, target: MidiTarget) {
+        self.bindings.retain(|binding| binding.control != control);
+        self.bindings.push(Binding { control, target });
+    }
+}
+
+/// A MIDI controller turned into [`Action`]s through a [`MidiMap`], with MIDI-learn
+/// for filling the map in from the controller itself
+pub struct MidiControlSurface {
+    _connection: MidiConnection,
+    messages: Receiver<MidiMessage>,
+    map: MidiMap,
+    map_path: PathBuf,
+    learning: bool,
+    /// control last moved while learning, waiting for a target
+    learned: Option<MidiControl>,
+}
+
+impl MidiControlSurface {
+    /// Listen to the first input port whose name contains `port_name`,
+    /// mapped by the file at `map_path`
+    pub fn open(port_name: &str, map_path: PathBuf) -> Result<MidiControlSurface, MidiError> {
+        let map = MidiMap::load(&map_path).unwrap_or_else(|e| {
+            log::error!("Failed to read MIDI mapping {}: {}", map_path.display(), e);
+            MidiMap::default()
+        });
+        let (message_tx, messages) = unbounded();
+        let mut parser = MidiParser::default();
+        let connection = midi_clock::connect_input(port_name, move |bytes| {
+            for message in parser.feed(bytes) {
+                let _ = message_tx.send(message);
+            }
+        })?;
+        Ok(MidiControlSurface {
+            _connection: connection,
+            messages,
+            map,
+            map_path,
+            learning: false,
+            learned: None,
+        })
+    }
+
+    /// An action asked for since the last call, if any. While learning, controls only get remembered.
+    pub fn try_recv(&mut self) -> Option<Action> {
+        while let Ok(message) = self.messages.try_recv() {
+            if self.learning {
+                if self.learned != Some(message.control) {
+                    log::info!("MIDI learn: {} touched, now pick what it should do", message.control);
+                }
+                self.learned = Some(message.control);
+                continue;
+            }
+            if let Some(action) = self.map.action(&message) {
+                return Some(action);
+            }
+        }
+        None
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.learning
+    }
+
+    pub fn toggle_learning(&mut self) {
+        self.learning = !self.learning;
+        self.learned = None;
+        log::info!("MIDI learn {}", if self.learning { "on" } else { "off" });
+    }
+
+    /// Map the control last touched while learning to `target` and save the mapping
+    pub fn learn(&mut self, target: MidiTarget) {
+        let Some(control) = self.learned.take() else {
+            log::warn!("MIDI learn: touch a control first");
+            return;
+        };
+        log::info!("MIDI learn: {} mapped to {:?}", control, target);
+        self.map.bind(control, target);
+        match self.map.save(&self.map_path) {
+            Ok(()) => log::info!("Saved MIDI mapping to {}", self.map_path.display()),
+            Err(e) => log::error!("Failed to save MIDI mapping {}: {}", self.map_path.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control(kind: ControlKind, channel: u8, number: u8) -> MidiControl {
+        MidiControl { kind, channel, number }
+    }
+
+    #[test]
+    fn note_on_without_velocity_is_a_release() {
+        let pad = control(ControlKind::Note, 1, 36);
+        assert_eq!(MidiMessage::parse(&[0x90, 36, 100]), Some(MidiMessage { control: pad, value: 100 }));
+        assert_eq!(MidiMessage::parse(&[0x90, 36, 0]), Some(MidiMessage { control: pad, value: 0 }));
+        assert_eq!(MidiMessage::parse(&[0x80, 36, 64]), Some(MidiMessage { control: pad, value: 0 }));
+
+        let mut map = MidiMap::default();
+        map.bind(pad, MidiTarget::LaunchScene { scene: 2 });
+        assert_eq!(map.action(&MidiMessage::parse(&[0x90, 36, 100]).unwrap()), Some(Action::LaunchScene(2)));
+        assert_eq!(map.action(&MidiMessage::parse(&[0x90, 36, 0]).unwrap()), None);
+        assert_eq!(map.action(&MidiMessage::parse(&[0x80, 36, 64]).unwrap()), None);
+    }
+
+    #[test]
+    fn control_changes_move_faders_over_their_range() {
+        let fader = control(ControlKind::Cc, 16, 7);
+        let mut map = MidiMap::default();
+        map.bind(fader, MidiTarget::TrackOpacity { track: 1 });
+        let opacity = |value| match map.action(&MidiMessage::parse(&[0xBF, 7, value]).unwrap()) {
+            Some(Action::SetTrackOpacity { track: 1, opacity }) => opacity,
+            action => panic!("unexpected {:?}", action),
+        };
+        assert_eq!(opacity(0), 0.0);
+        assert_eq!(opacity(127), 1.0);
+        assert!((opacity(64) - 64.0 / 127.0).abs() < 1e-6);
+        // Other channels and controls are left alone
+        assert_eq!(map.action(&MidiMessage::parse(&[0xB0, 7, 64]).unwrap()), None);
+        assert_eq!(MidiMessage::parse(&[0xE0, 0, 64]), None);
+        assert_eq!(MidiMessage::parse(&[0xB0, 7]), None);
+    }
+
+    #[test]
+    fn running_status_repeats_the_last_status() {
+        let mut parser = MidiParser::default();
+        let pad = control(ControlKind::Note, 1, 60);
+        assert_eq!(
+            parser.feed(&[0x90, 60, 100, 60, 0]),
+            vec![MidiMessage { control: pad, value: 100 }, MidiMessage { control: pad, value: 0 }]
+        );
+        // Across calls and around a clock message
+        let knob = control(ControlKind::Cc, 3, 21);
+        assert_eq!(parser.feed(&[0xB2, 21]), vec![]);
+        assert_eq!(parser.feed(&[0xF8, 10, 21]), vec![MidiMessage { control: knob, value: 10 }]);
+        assert_eq!(parser.feed(&[30]), vec![MidiMessage { control: knob, value: 30 }]);
+        // Data with no status before it, or after a system message, means nothing
+        assert_eq!(MidiParser::default().feed(&[60, 100]), vec![]);
+        assert_eq!(parser.feed(&[0xF2, 0, 0, 21, 40]), vec![]);
+    }
+
+    #[test]
+    fn old_mappings_load_through_aliases() {
+        let text = r#"
+            [[binding]]
+            kind = "note"
+            channel = 10
+            number = 40
+            action = "select_clip"
+            clip = 3
+
+            [[binding]]
+            kind = "cc"
+            channel = 1
+            number = 1
+            action = "next_clip"
+        "#;
+        let map: MidiMap = toml::from_str(text).unwrap();
+        let press = |kind, channel, number| MidiMessage { control: control(kind, channel, number), value: 127 };
+        assert_eq!(map.action(&press(ControlKind::Note, 10, 40)), Some(Action::LaunchScene(3)));
+        assert_eq!(map.action(&press(ControlKind::Cc, 1, 1)), Some(Action::NextScene));
+
+        // Saved again under the new names
+        let saved = toml::to_string_pretty(&map).unwrap();
+        assert!(saved.contains("action = \"launch_scene\"") && saved.contains("scene = 3"), "{}", saved);
+    }
+}
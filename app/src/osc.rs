@@ -0,0 +1,395 @@
+use crate::compositor::BlendMode;
+use crate::control::Action;
+use crossbeam_channel::{unbounded, Receiver};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Largest datagram accepted
+const MAX_PACKET: usize = 65_507;
+/// Clients remembered for state reports, oldest forgotten first
+const MAX_CLIENTS: usize = 16;
+
+/// An argument of an OSC message
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Bool(bool),
+}
+
+impl OscArg {
+    /// Numeric value of the argument, as sent by faders and buttons
+    fn as_f32(&self) -> Option<f32> {
+        match *self {
+            OscArg::Int(value) => Some(value as f32),
+            OscArg::Float(value) => Some(value),
+            OscArg::Bool(value) => Some(if value { 1.0 } else { 0.0 }),
+            OscArg::String(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: &str, args: Vec<OscArg>) -> OscMessage {
+        OscMessage { address: address.to_string(), args }
+    }
+
+    /// The action an address under `/voop` asks for, if any.
+    /// Buttons send 0 on release, which is ignored.
+    pub fn action(&self) -> Option<Action> {
+        let first = self.args.first().and_then(OscArg::as_f32);
+        let pressed = first.is_none_or(|value| value != 0.0);
+        match self.address.as_str() {
+            // `/voop/clip/...` predates the grid, when every file was a scene of its own
+            "/voop/scene/next" | "/voop/clip/next" if pressed => Some(Action::NextScene),
+            "/voop/scene/previous" | "/voop/clip/previous" if pressed => Some(Action::PreviousScene),
+            "/voop/scene/launch" | "/voop/clip/select" => self.index(0).map(Action::LaunchScene),
+            "/voop/clip/launch" => Some(Action::LaunchClip { track: self.index(0)?, scene: self.index(1)? }),
+            "/voop/track/stop" => self.index(0).map(Action::StopTrack),
+            "/voop/track/opacity" => Some(Action::SetTrackOpacity {
+                track: self.index(0)?,
+                opacity: self.args.get(1)?.as_f32()?,
+            }),
+            "/voop/track/blend" => match self.args.get(1)? {
+                OscArg::String(name) => Some(Action::SetBlendMode { track: self.index(0)?, mode: BlendMode::parse(name)? }),
+                _ => None,
+            },
+            "/voop/crossfader" => first.map(Action::SetCrossfader),
+            "/voop/fullscreen" => Some(match first {
+                Some(value) => Action::SetFullscreen(value != 0.0),
+                None => Action::ToggleFullscreen,
+            }),
+            "/voop/opacity" => first.map(Action::SetOpacity),
+            "/voop/state" => Some(Action::ReportState),
+            address => self.effect_action(address.strip_prefix("/voop/effect/")?, first, pressed),
+        }
+    }
+
+    /// `/voop/effect/NAME` turns an effect on or off, or toggles it without an argument,
+    /// `/voop/effect/NAME/PARAM` sets one of its parameters
+    fn effect_action(&self, path: &str, first: Option<f32>, pressed: bool) -> Option<Action> {
+        match path.split_once('/') {
+            None => match first {
+                Some(value) => Some(Action::SetEffect { effect: path.to_string(), enabled: value != 0.0 }),
+                None if pressed => Some(Action::ToggleEffect(path.to_string())),
+                None => None,
+            },
+            Some((effect, param)) => Some(Action::SetEffectParam {
+                effect: effect.to_string(),
+                param: param.to_string(),
+                value: first?,
+            }),
+        }
+    }
+
+    /// Argument `n` as an index counted from 0
+    fn index(&self, n: usize) -> Option<usize> {
+        let value = self.args.get(n)?.as_f32()?;
+        (value >= 0.0).then_some(value as usize)
+    }
+}
+
+/// Receives OSC over UDP on a background thread and turns it into [`Action`]s.
+/// State reports go back to every address that has sent us something.
+pub struct OscServer {
+    socket: UdpSocket,
+    actions: Receiver<Action>,
+    clients: Arc<Mutex<Vec<SocketAddr>>>,
+}
+
+impl OscServer {
+    /// Listen on `port` on all interfaces
+    pub fn bind(port: u16) -> io::Result<OscServer> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        let receiver = socket.try_clone()?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let (action_tx, actions) = unbounded();
+
+        let known_clients = clients.clone();
+        thread::Builder::new()
+            .name("osc-server".into())
+            .spawn(move || {
+                let mut buffer = vec![0u8; MAX_PACKET];
+                loop {
+                    let (len, from) = match receiver.recv_from(&mut buffer) {
+                        Ok(received) => received,
+                        Err(e) => {
+                            log::warn!("OSC receive failed: {}", e);
+                            continue;
+                        }
+                    };
+                    remember(&known_clients, from);
+
+                    for message in decode_packet(&buffer[..len]) {
+                        log::debug!("OSC from {}: {:?}", from, message);
+                        match message.action() {
+                            Some(action) => {
+                                if action_tx.send(action).is_err() {
+                                    return;
+                                }
+                            }
+                            None => log::debug!("Ignoring OSC address {}", message.address),
+                        }
+                    }
+                }
+            })?;
+
+        log::info!("Listening for OSC on UDP port {}", port);
+        Ok(OscServer { socket, actions, clients })
+    }
+
+    /// An action received since the last call, if any
+    pub fn try_recv(&self) -> Option<Action> {
+        self.actions.try_recv().ok()
+    }
+
+    /// Send messages to every client we have heard from
+    pub fn report(&self, messages: &[OscMessage]) {
+        let clients = self.clients.lock().unwrap().clone();
+        for message in messages {
+            let packet = encode_message(message);
+            for client in &clients {
+                if let Err(e) = self.socket.send_to(&packet, client) {
+                    log::warn!("OSC send to {} failed: {}", client, e);
+                }
+            }
+        }
+    }
+}
+
+fn remember(clients: &Mutex<Vec<SocketAddr>>, client: SocketAddr) {
+    let mut clients = clients.lock().unwrap();
+    if clients.contains(&client) {
+        return;
+    }
+    if clients.len() == MAX_CLIENTS {
+        clients.remove(0);
+    }
+    clients.push(client);
+}
+
+/// Messages in a packet, unpacking bundles. Malformed parts are dropped.
+pub fn decode_packet(packet: &[u8]) -> Vec<OscMessage> {
+    let mut messages = Vec::new();
+    decode_into(packet, &mut messages);
+    messages
+}
+
+fn decode_into(packet: &[u8], messages: &mut Vec<OscMessage>) {
+    let mut reader = Reader { bytes: packet, pos: 0 };
+    if packet.starts_with(b"#bundle\0") {
+        // Timetags are ignored, everything happens as soon as it arrives
+        reader.pos = 16;
+        while let Some(size) = reader.int() {
+            match reader.take(size.max(0) as usize) {
+                Some(element) => decode_into(element, messages),
+                None => return,
+            }
+        }
+        return;
+    }
+
+    if let Some(message) = decode_message(&mut reader) {
+        messages.push(message);
+    }
+}
+
+fn decode_message(reader: &mut Reader) -> Option<OscMessage> {
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        return None;
+    }
+    // Very old senders omit the type tags, treat that as no arguments
+    let tags = reader.string().unwrap_or_else(|| ",".to_string());
+
+    let mut args = Vec::new();
+    for tag in tags.strip_prefix(',')?.chars() {
+        args.push(match tag {
+            'i' => OscArg::Int(reader.int()?),
+            'f' => OscArg::Float(f32::from_bits(reader.int()? as u32)),
+            's' | 'S' => OscArg::String(reader.string()?),
+            'T' => OscArg::Bool(true),
+            'F' => OscArg::Bool(false),
+            'b' => {
+                // Skip blobs, nothing we accept takes one
+                let size = reader.int()?.max(0) as usize;
+                reader.take(padded(size))?;
+                continue;
+            }
+            'h' | 'd' | 't' => {
+                reader.take(8)?;
+                continue;
+            }
+            'N' | 'I' => continue,
+            _ => return None,
+        });
+    }
+    Some(OscMessage { address, args })
+}
+
+pub fn encode_message(message: &OscMessage) -> Vec<u8> {
+    let mut packet = Vec::new();
+    push_string(&mut packet, &message.address);
+
+    let mut tags = String::from(",");
+    for arg in &message.args {
+        tags.push(match arg {
+            OscArg::Int(_) => 'i',
+            OscArg::Float(_) => 'f',
+            OscArg::String(_) => 's',
+            OscArg::Bool(true) => 'T',
+            OscArg::Bool(false) => 'F',
+        });
+    }
+    push_string(&mut packet, &tags);
+
+    for arg in &message.args {
+        match arg {
+            OscArg::Int(value) => packet.extend_from_slice(&value.to_be_bytes()),
+            OscArg::Float(value) => packet.extend_from_slice(&value.to_be_bytes()),
+            OscArg::String(value) => push_string(&mut packet, value),
+            OscArg::Bool(_) => {}
+        }
+    }
+    packet
+}
+
+/// OSC strings are null terminated and padded to a multiple of four bytes
+fn push_string(packet: &mut Vec<u8>, value: &str) {
+    packet.extend_from_slice(value.as_bytes());
+    packet.resize(packet.len() + padded(value.len() + 1) - value.len(), 0);
+}
+
+fn padded(len: usize) -> usize {
+    len.div_ceil(4) * 4
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn int(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let rest = self.bytes.get(self.pos..)?;
+        let len = rest.iter().position(|&byte| byte == 0)?;
+        let value = std::str::from_utf8(&rest[..len]).ok()?.to_string();
+        self.take(padded(len + 1))?;
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(elements: &[Vec<u8>]) -> Vec<u8> {
+        let mut packet = b"#bundle\0".to_vec();
+        packet.extend_from_slice(&1u64.to_be_bytes());
+        for element in elements {
+            packet.extend_from_slice(&(element.len() as i32).to_be_bytes());
+            packet.extend_from_slice(element);
+        }
+        packet
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let message = OscMessage::new(
+            "/voop/track/blend",
+            vec![
+                OscArg::Int(-3),
+                OscArg::Float(0.5),
+                OscArg::String("add".to_string()),
+                OscArg::String(String::new()),
+                OscArg::Bool(true),
+                OscArg::Bool(false),
+            ],
+        );
+        let packet = encode_message(&message);
+        assert_eq!(packet.len() % 4, 0);
+        assert_eq!(decode_packet(&packet), vec![message]);
+    }
+
+    #[test]
+    fn bundles_unpack_in_order() {
+        let first = OscMessage::new("/voop/scene/next", vec![]);
+        let second = OscMessage::new("/voop/opacity", vec![OscArg::Float(0.25)]);
+        let inner = bundle(&[encode_message(&second)]);
+        assert_eq!(decode_packet(&bundle(&[encode_message(&first), inner])), vec![first, second]);
+    }
+
+    #[test]
+    fn truncated_strings_are_dropped() {
+        assert_eq!(decode_packet(b"/voop/state"), vec![]);
+        let mut packet = encode_message(&OscMessage::new("/voop/track/blend", vec![OscArg::String("screen".into())]));
+        packet.truncate(packet.len() - 4);
+        assert_eq!(decode_packet(&packet), vec![]);
+    }
+
+    #[test]
+    fn short_bundles_hold_nothing() {
+        assert_eq!(decode_packet(b"#bundle\0"), vec![]);
+        assert_eq!(decode_packet(b"#bundle\0\0\0\0\0\0\0"), vec![]);
+        // An element claiming more bytes than are left ends the bundle
+        let mut packet = bundle(&[encode_message(&OscMessage::new("/voop/state", vec![]))]);
+        packet.extend_from_slice(&64i32.to_be_bytes());
+        packet.extend_from_slice(b"/voo");
+        assert_eq!(decode_packet(&packet), vec![OscMessage::new("/voop/state", vec![])]);
+    }
+
+    #[test]
+    fn negative_sizes_count_as_empty() {
+        let mut packet = Vec::new();
+        push_string(&mut packet, "/voop/opacity");
+        push_string(&mut packet, ",bi");
+        packet.extend_from_slice(&(-8i32).to_be_bytes());
+        packet.extend_from_slice(&7i32.to_be_bytes());
+        assert_eq!(decode_packet(&packet), vec![OscMessage::new("/voop/opacity", vec![OscArg::Int(7)])]);
+
+        let mut packet = b"#bundle\0".to_vec();
+        packet.extend_from_slice(&1u64.to_be_bytes());
+        packet.extend_from_slice(&(-4i32).to_be_bytes());
+        packet.extend_from_slice(&(encode_message(&OscMessage::new("/voop/state", vec![])).len() as i32).to_be_bytes());
+        packet.extend_from_slice(&encode_message(&OscMessage::new("/voop/state", vec![])));
+        assert_eq!(decode_packet(&packet), vec![OscMessage::new("/voop/state", vec![])]);
+    }
+
+    #[test]
+    fn unknown_tags_drop_the_message() {
+        let mut packet = Vec::new();
+        push_string(&mut packet, "/voop/opacity");
+        push_string(&mut packet, ",fz");
+        packet.extend_from_slice(&0.5f32.to_be_bytes());
+        assert_eq!(decode_packet(&packet), vec![]);
+    }
+
+    #[test]
+    fn skipped_tags_keep_the_arguments_after_them() {
+        let mut packet = Vec::new();
+        push_string(&mut packet, "/voop/crossfader");
+        push_string(&mut packet, ",hNf");
+        packet.extend_from_slice(&42i64.to_be_bytes());
+        packet.extend_from_slice(&0.75f32.to_be_bytes());
+        assert_eq!(decode_packet(&packet), vec![OscMessage::new("/voop/crossfader", vec![OscArg::Float(0.75)])]);
+    }
+}
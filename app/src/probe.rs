@@ -0,0 +1,78 @@
+use crate::media;
+use crate::yuv::{Colors, Layout};
+use ffmpeg::format::Pixel;
+use ffmpeg_next as ffmpeg;
+use std::fmt;
+use std::path::Path;
+
+/// What voop makes of a video file, read from its headers without decoding frames
+pub struct Probe {
+    /// demuxer name, e.g. `mov,mp4,m4a,3gp,3g2,mj2`
+    pub container: String,
+    pub codec: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub format: Pixel,
+    /// frames per second, 0.0 when the file doesn't say
+    pub frame_rate: f64,
+    /// length in seconds, 0.0 when the file doesn't say
+    pub duration_secs: f64,
+    pub colors: Colors,
+}
+
+impl Probe {
+    /// How frames go to the GPU, converted to RGBA while decoding if `None`
+    pub fn layout(&self) -> Option<Layout> {
+        Layout::of(self.format)
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}x{} {:?} in {}, {:.3} fps, {:.2} s, ",
+            self.codec, self.width, self.height, self.format, self.container, self.frame_rate, self.duration_secs
+        )?;
+        match self.layout() {
+            Some(Layout::Rgba) => write!(f, "shown as decoded"),
+            Some(layout) => write!(
+                f,
+                "{:?} converted to RGB on the GPU ({:?}, {} range)",
+                layout,
+                self.colors.matrix,
+                if self.colors.full_range { "full" } else { "limited" }
+            ),
+            None => write!(f, "converted to RGBA while decoding"),
+        }
+    }
+}
+
+/// Read what voop needs to know about the video stream of the file at `path`
+pub fn probe(path: &Path) -> Result<Probe, ffmpeg::Error> {
+    let ctx = media::open(path)?;
+    let stream = ctx.streams().best(ffmpeg::media::Type::Video).ok_or(ffmpeg::Error::StreamNotFound)?;
+    let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?.decoder().video()?;
+
+    let frame_rate = [stream.avg_frame_rate(), stream.rate()]
+        .into_iter()
+        .map(f64::from)
+        .find(|rate| rate.is_finite() && *rate > 0.0)
+        .unwrap_or(0.0);
+    // Same fallback as clips: the stream's own duration, else the container's microseconds
+    let duration_secs = match stream.duration() {
+        d if d > 0 => d as f64 * f64::from(stream.time_base()),
+        _ => ctx.duration().max(0) as f64 / 1_000_000.0,
+    };
+
+    Ok(Probe {
+        container: ctx.format().name().to_string(),
+        codec: decoder.id().name(),
+        width: decoder.width(),
+        height: decoder.height(),
+        format: decoder.format(),
+        frame_rate,
+        duration_secs,
+        colors: Colors::tagged(decoder.format(), decoder.color_space(), decoder.color_range(), decoder.height()),
+    })
+}
@@ -0,0 +1,95 @@
+use crate::app;
+use crate::cli::{Options, RenderOptions};
+use crate::clip::Clip;
+use crate::encode::VideoWriter;
+use crate::frame_cache::FrameCache;
+use crate::show::Show;
+use crate::state::State;
+use ffmpeg::Rational;
+use ffmpeg_next as ffmpeg;
+use transport::{manual::ManualClock, Transport};
+
+/// Picture size when `--size` is not given
+const DEFAULT_SIZE: (u32, u32) = (1920, 1080);
+
+/// Frames per second when `--fps` is not given
+const DEFAULT_FPS: u32 = 30;
+
+/// Render bars of one scene of `show` into a video file. Time comes from a clock of its own
+/// stepped a frame at a time, so the file is the same however long frames take to draw.
+pub async fn run(options: &Options, render: &RenderOptions, show: Show) -> Result<(), String> {
+    if options.transport.is_timecode() {
+        return Err("render follows beats, it can't chase timecode".to_string());
+    }
+    let grid = show.grid;
+    let scene = grid
+        .scenes
+        .get(render.scene)
+        .ok_or_else(|| format!("There is no scene {}, the show has {}", render.scene + 1, grid.scenes.len()))?;
+    let (width, height) = options.size.unwrap_or(DEFAULT_SIZE);
+    let fps = options.fps.unwrap_or(DEFAULT_FPS);
+    let mut state = State::headless(width, height, grid.tracks.len()).await;
+
+    if let Some(path) = show.luma_matte.as_ref().or(options.luma_matte.as_ref()) {
+        let matte = image::open(path)
+            .map_err(|e| format!("Failed to open luma matte {}: {}", path.display(), e))?
+            .into_rgba8();
+        state.set_luma_matte(&matte);
+    }
+    if let Some(folder) = show.shader_dir.as_ref().or(options.shader_dir.as_ref()) {
+        let files = app::shader_files(folder)
+            .map_err(|e| format!("Failed to read shader folder {}: {}", folder.display(), e))?;
+        for path in files {
+            state.load_shader(&path);
+        }
+    }
+    state.effects_mut().configure(&options.effect_params, &options.effects)?;
+    // The show file was checked for unknown effects when it was read
+    if let Some(effects) = &scene.effects {
+        let names: Vec<String> = state.effects().names().map(str::to_string).collect();
+        for name in names {
+            state.effects_mut().set_enabled(&name, effects.contains(&name));
+        }
+    }
+    if let Some(opacity) = show.opacity {
+        state.set_opacity(opacity);
+    }
+
+    let cache = FrameCache::shared(options.cache_mb * 1024 * 1024);
+    let mut layers = Vec::new();
+    for (track, settings) in grid.tracks.iter().enumerate() {
+        state.set_layer_blend(track, settings.blend);
+        state.set_layer_opacity(track, settings.opacity);
+        let Some(slot) = scene.slots[track] else {
+            continue;
+        };
+        let path = &grid.files[slot.file];
+        let clip = Clip::new(path, cache.clone())
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        state.set_layer_visible(track, true);
+        layers.push((track, slot.loop_mode, clip));
+    }
+
+    let quantum = scene.quantum.unwrap_or(options.quantum);
+    let mut clock = ManualClock::new(options.bpm, quantum);
+    let frames = (render.bars as f64 * quantum * 60.0 / options.bpm * fps as f64).round() as u64;
+    let mut writer = VideoWriter::create(&render.output, width, height, Rational(fps as i32, 1), render.codec.as_deref())
+        .map_err(|e| format!("Failed to write {}: {}", render.output.display(), e))?;
+    let write_error = |e: ffmpeg::Error| format!("Failed to write {}: {}", render.output.display(), e);
+
+    log::info!("Rendering {} bars of scene `{}`, {} frames", render.bars, scene.name, frames);
+    for frame in 0..frames {
+        let transport = clock.update_phase_and_beat();
+        state.effects_mut().set_transport_at(&transport, frame as f32 / fps as f32);
+        for (track, loop_mode, clip) in &mut layers {
+            let position = loop_mode.position(&transport, clip.duration_secs());
+            let picture = clip.play_video_at_position(position);
+            state.update_layer_frame(*track, &picture);
+        }
+        writer.write_rgba(&state.capture()).map_err(write_error)?;
+        clock.advance_secs(1.0 / fps as f64);
+    }
+    writer.finish().map_err(write_error)?;
+    println!("Rendered {} frames to {}", frames, render.output.display());
+    Ok(())
+}
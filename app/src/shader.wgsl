@@ -1,6 +1,21 @@
+// Puts the composited layers on screen
 @group(0) @binding(0) var samp : sampler;
 @group(0) @binding(1) var tex  : texture_2d<f32>;
 
+// Matches `Params` in state.rs
+struct Params {
+    opacity : f32,
+    // 1.0 while a clip is armed to launch
+    armed   : f32,
+    _pad1   : f32,
+    _pad2   : f32,
+};
+
+// Width of the armed border as a fraction of the picture
+const ARMED_BORDER : f32 = 0.015;
+const ARMED_COLOR  : vec3<f32> = vec3<f32>(1.0, 0.6, 0.0);
+@group(1) @binding(0) var<uniform> params : Params;
+
 struct VSOut {
     @builtin(position) pos : vec4<f32>,
     @location(0)        uv  : vec2<f32>,
@@ -17,6 +32,11 @@ fn vs_main(@location(0) in_pos : vec2<f32>,
 
 @fragment
 fn fs_main(in : VSOut) -> @location(0) vec4<f32> {
-    return textureSample(tex, samp, in.uv);
+    let color = textureSample(tex, samp, in.uv);
+    let edge = min(min(in.uv.x, 1.0 - in.uv.x), min(in.uv.y, 1.0 - in.uv.y));
+    if (params.armed > 0.0 && edge < ARMED_BORDER) {
+        return vec4<f32>(ARMED_COLOR, 1.0);
+    }
+    // Layers were composited over opaque black, so fading is scaling the colour
+    return vec4<f32>(color.rgb * params.opacity, 1.0);
 }
-
@@ -0,0 +1,448 @@
+use crate::app;
+use crate::clip::{LaunchQuantize, LoopMode};
+use crate::compositor::BlendMode;
+use crate::effects;
+use crate::grid::{CrossfadeSide, Grid, Scene, Slot, Track};
+use crate::media;
+use crate::transition::{TransitionKind, TransitionLength};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use transport::timecode::{FrameRate, Timecode};
+
+/// Version of the show file format this build reads. Files without a `version` are taken
+/// to be version 1, which they were written for.
+pub const SHOW_VERSION: u32 = 1;
+
+/// Keys of the top table of a show file. Each is read on its own, as are every track and
+/// scene, so a value of the wrong type is one more problem rather than the only one reported.
+///
+/// - `version`, a number
+/// - `tracks`, bottom to top
+/// - `launch_quantize`, when tracks stop for an empty slot, and the default for clips
+/// - `output`, a [`ShowOutput`]
+/// - `scene`, or `song`, the songs of a setlist in the order they are played
+const SHOW_KEYS: &[&str] = &["version", "tracks", "launch_quantize", "output", "scene", "song"];
+
+/// How the show is put on screen, paths being relative to the show file's folder
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShowOutput {
+    fullscreen: Option<bool>,
+    /// master opacity, 0.0 to 1.0
+    opacity: Option<f32>,
+    luma_matte: Option<PathBuf>,
+    shader_dir: Option<PathBuf>,
+}
+
+/// A track, given by name alone or as a table with how it is mixed
+#[derive(Debug, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum ShowTrack {
+    Name(String),
+    Mixed {
+        name: String,
+        #[serde(default)]
+        blend: BlendMode,
+        opacity: Option<f32>,
+        crossfade: Option<CrossfadeSide>,
+    },
+}
+
+impl ShowTrack {
+    fn into_track(self) -> Track {
+        match self {
+            ShowTrack::Name(name) => Track::new(&name),
+            ShowTrack::Mixed { name, blend, opacity, crossfade } => Track {
+                blend,
+                opacity: opacity.unwrap_or(1.0).clamp(0.0, 1.0),
+                crossfade,
+                ..Track::new(&name)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShowScene {
+    name: Option<String>,
+    /// beats per bar, set when the scene is launched
+    quantum: Option<f64>,
+    /// loop length of the scene's clips that don't set their own
+    bars: Option<u32>,
+    /// launch quantization of the scene's clips that don't set their own
+    launch_quantize: Option<String>,
+    /// effects turned on when the scene is launched, turning the others off
+    effects: Option<Vec<String>>,
+    /// by track name, tracks left out have an empty slot
+    #[serde(default)]
+    clips: HashMap<String, ShowClip>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShowClip {
+    /// relative to the show file's folder
+    file: PathBuf,
+    bars: Option<u32>,
+    native_speed: Option<bool>,
+    launch_quantize: Option<String>,
+    /// how the clip takes over its track: `cut`, `crossfade`, `dip_to_black`, `wipe` or `luma_wipe`
+    transition: Option<String>,
+    /// `N beats` or `N bars`
+    transition_length: Option<String>,
+    /// SMPTE start time when chasing timecode, as `HH:MM:SS:FF` or seconds
+    timecode: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ShowError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    /// everything wrong with the show, one problem per entry
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ShowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShowError::Io(e) => write!(f, "{}", e),
+            ShowError::Parse(e) => write!(f, "{}", e),
+            ShowError::Invalid(problems) => {
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A show file's grid and the output settings it makes
+#[derive(Debug)]
+pub struct Show {
+    pub grid: Grid,
+    pub fullscreen: Option<bool>,
+    pub opacity: Option<f32>,
+    pub luma_matte: Option<PathBuf>,
+    pub shader_dir: Option<PathBuf>,
+}
+
+/// Read the show file at `path`. Clips start from `slot_for`'s settings for their file,
+/// overridden by what the show file sets. Scenes may turn on the built-in effects and those
+/// of the show's shader folder, or `default_shader_dir` if it names none. Missing files and bad
+/// values don't stop the file being read, so the error lists all of them.
+pub fn load(
+    path: &Path,
+    timecode_rate: FrameRate,
+    launch_quantize: LaunchQuantize,
+    default_shader_dir: Option<&Path>,
+    slot_for: impl Fn(usize, &Path) -> Slot,
+) -> Result<Show, ShowError> {
+    let text = std::fs::read_to_string(path).map_err(ShowError::Io)?;
+    let mut table: toml::Table = toml::from_str(&text).map_err(ShowError::Parse)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut problems = Vec::new();
+
+    let version: u32 = take(&mut table, "version", &mut problems).unwrap_or(1);
+    if version == 0 || version > SHOW_VERSION {
+        problems.push(format!("version {} isn't supported, this build reads up to version {}", version, SHOW_VERSION));
+    }
+    let tracks: Vec<toml::Value> = take(&mut table, "tracks", &mut problems).unwrap_or_default();
+    let tracks: Vec<Track> = tracks
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, track)| read::<ShowTrack>(track, &format!("track {}", index + 1), &mut problems))
+        .map(ShowTrack::into_track)
+        .collect();
+    if tracks.is_empty() {
+        problems.push("no tracks".to_string());
+    }
+    let mut names = HashSet::new();
+    let mut repeated = HashSet::new();
+    for track in &tracks {
+        if !names.insert(&track.name) && repeated.insert(&track.name) {
+            problems.push(format!("track `{}` is listed more than once", track.name));
+        }
+    }
+    let show_launch_quantize: Option<String> = take(&mut table, "launch_quantize", &mut problems);
+    let output: ShowOutput = take(&mut table, "output", &mut problems).unwrap_or_default();
+    let scenes: Vec<toml::Value> = match table.contains_key("scene") {
+        true => take(&mut table, "scene", &mut problems),
+        false => take(&mut table, "song", &mut problems),
+    }
+    .unwrap_or_default();
+    for key in table.keys().filter(|key| !SHOW_KEYS.contains(&key.as_str())) {
+        problems.push(format!("unknown field `{}`", key));
+    }
+
+    let show_quantize = show_launch_quantize.as_deref().and_then(|text| parse_quantize(text, &mut problems));
+    let mut grid = Grid {
+        files: Vec::new(),
+        tracks,
+        scenes: Vec::new(),
+        launch_quantize: show_quantize.unwrap_or(launch_quantize),
+    };
+
+    for (index, scene) in scenes.into_iter().enumerate() {
+        let what = match scene.get("name").and_then(toml::Value::as_str) {
+            Some(name) => format!("scene `{}`", name),
+            None => format!("scene {}", index + 1),
+        };
+        let Some(scene) = read::<ShowScene>(scene, &what, &mut problems) else {
+            continue;
+        };
+        let name = scene.name.unwrap_or_else(|| format!("Scene {}", index + 1));
+        for track in scene.clips.keys().filter(|name| !grid.tracks.iter().any(|track| track.name == **name)) {
+            problems.push(format!("scene `{}` has a clip on unknown track `{}`", name, track));
+        }
+        if scene.quantum.is_some_and(|quantum| quantum < 1.0 || quantum.is_nan()) {
+            problems.push(format!("scene `{}` has a quantum below 1", name));
+        }
+        let scene_quantize = scene.launch_quantize.as_deref().and_then(|text| parse_quantize(text, &mut problems));
+
+        let mut slots = Vec::with_capacity(grid.tracks.len());
+        for track in &grid.tracks {
+            let Some(clip) = scene.clips.get(&track.name) else {
+                slots.push(None);
+                continue;
+            };
+            let path = dir.join(&clip.file);
+            let file = match grid.files.iter().position(|known| *known == path) {
+                Some(file) => file,
+                None => {
+                    if !media::exists(&path) {
+                        problems.push(format!("scene `{}` plays {}, which doesn't exist", name, path.display()));
+                    }
+                    grid.files.push(path.clone());
+                    grid.files.len() - 1
+                }
+            };
+            let mut slot = slot_for(file, &path);
+            if let Some(quantize) = scene_quantize.or(show_quantize) {
+                slot.launch_quantize = quantize;
+            }
+            if let Some(bars) = scene.bars {
+                slot.loop_mode = slot.loop_mode.with_bars(bars);
+            }
+            slots.push(Some(clip.apply(slot, timecode_rate, &mut problems)));
+        }
+        grid.scenes.push(Scene { name, slots, quantum: scene.quantum, effects: scene.effects });
+    }
+    if grid.scenes.is_empty() {
+        problems.push("no scenes".to_string());
+    }
+
+    if output.opacity.is_some_and(|opacity| !(0.0..=1.0).contains(&opacity)) {
+        problems.push("output opacity isn't between 0.0 and 1.0".to_string());
+    }
+    let luma_matte = output.luma_matte.map(|matte| dir.join(matte));
+    if let Some(matte) = luma_matte.as_ref().filter(|matte| !matte.is_file()) {
+        problems.push(format!("luma matte {} doesn't exist", matte.display()));
+    }
+    let shader_dir = output.shader_dir.map(|folder| dir.join(folder));
+    if let Some(folder) = shader_dir.as_ref().filter(|folder| !folder.is_dir()) {
+        problems.push(format!("shader folder {} doesn't exist", folder.display()));
+    }
+    let mut effects: Vec<String> = effects::built_in_names().map(str::to_string).collect();
+    if let Some(folder) = shader_dir.as_deref().or(default_shader_dir).filter(|folder| folder.is_dir()) {
+        match app::shader_files(folder) {
+            Ok(files) => effects.extend(files.iter().filter_map(|file| Some(file.file_stem()?.to_str()?.to_string()))),
+            Err(e) => problems.push(format!("shader folder {} can't be read: {}", folder.display(), e)),
+        }
+    }
+    for scene in &grid.scenes {
+        for effect in scene.effects.iter().flatten().filter(|effect| !effects.contains(effect)) {
+            problems.push(format!("scene `{}` turns on unknown effect `{}`", scene.name, effect));
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(ShowError::Invalid(problems));
+    }
+    Ok(Show { grid, fullscreen: output.fullscreen, opacity: output.opacity, luma_matte, shader_dir })
+}
+
+impl ShowClip {
+    /// `slot` with what the show file sets for the clip, noting values that make no sense
+    fn apply(&self, mut slot: Slot, timecode_rate: FrameRate, problems: &mut Vec<String>) -> Slot {
+        if let Some(native_speed) = self.native_speed {
+            let bars = slot.loop_mode.bars();
+            slot.loop_mode = match slot.loop_mode {
+                LoopMode::Timecode { .. } => slot.loop_mode,
+                _ if native_speed => LoopMode::Native { resync_bars: bars },
+                _ => LoopMode::Bars(bars),
+            };
+        }
+        if let Some(bars) = self.bars {
+            slot.loop_mode = slot.loop_mode.with_bars(bars);
+        }
+        if let Some(quantize) = self.launch_quantize.as_deref().and_then(|text| parse_quantize(text, problems)) {
+            slot.launch_quantize = quantize;
+        }
+        if let Some(text) = &self.transition {
+            match TransitionKind::parse(text) {
+                Some(kind) => slot.transition.kind = kind,
+                None => problems.push(format!("invalid transition `{}` for {}", text, self.file.display())),
+            }
+        }
+        if let Some(text) = &self.transition_length {
+            match TransitionLength::parse(text) {
+                Some(length) => slot.transition.length = length,
+                None => problems.push(format!("invalid transition length `{}` for {}", text, self.file.display())),
+            }
+        }
+        if let (Some(text), LoopMode::Timecode { .. }) = (&self.timecode, slot.loop_mode) {
+            match Timecode::parse_secs(text, timecode_rate) {
+                Some(offset_secs) => slot.loop_mode = LoopMode::Timecode { offset_secs },
+                None => problems.push(format!("invalid timecode `{}` for {}", text, self.file.display())),
+            }
+        }
+        slot
+    }
+}
+
+/// Take `key` out of `table` as a `T`, noting why not if it isn't one
+fn take<T: DeserializeOwned>(table: &mut toml::Table, key: &str, problems: &mut Vec<String>) -> Option<T> {
+    read(table.remove(key)?, key, problems)
+}
+
+/// `value` as a `T`, noting why not for `what` if it isn't one
+fn read<T: DeserializeOwned>(value: toml::Value, what: &str, problems: &mut Vec<String>) -> Option<T> {
+    value.try_into().map_err(|e: toml::de::Error| problems.push(format!("{}: {}", what, e.message()))).ok()
+}
+
+fn parse_quantize(text: &str, problems: &mut Vec<String>) -> Option<LaunchQuantize> {
+    let quantize = LaunchQuantize::parse(text);
+    if quantize.is_none() {
+        problems.push(format!("invalid launch quantization `{}`", text));
+    }
+    quantize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transition::Transition;
+    use std::path::PathBuf;
+
+    /// A folder of its own for each test, holding `files` and a show file written from `show`
+    fn folder(test: &str, show: &str, files: &[&str]) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("voop-show-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        for file in files {
+            let path = folder.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        std::fs::write(folder.join("show.toml"), show).unwrap();
+        folder
+    }
+
+    fn load_from(folder: &Path, shader_dir: Option<&Path>) -> Result<Show, ShowError> {
+        let slot_for = |file, _: &Path| Slot {
+            file,
+            loop_mode: LoopMode::Bars(1),
+            launch_quantize: LaunchQuantize::Bar,
+            transition: Transition::CUT,
+        };
+        load(&folder.join("show.toml"), FrameRate::Fps25, LaunchQuantize::Bar, shader_dir, slot_for)
+    }
+
+    fn problems(folder: &Path) -> Vec<String> {
+        match load_from(folder, None) {
+            Err(ShowError::Invalid(problems)) => problems,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("the show loaded"),
+        }
+    }
+
+    #[test]
+    fn loads_a_setlist() {
+        let show = r#"
+            tracks = ["base", { name = "top", blend = "add", opacity = 0.5 }]
+            launch_quantize = "beat"
+
+            [output]
+            shader_dir = "shaders"
+
+            [[song]]
+            name = "Intro"
+            effects = ["mirror", "glow"]
+            clips.base = { file = "loops/a.mov", bars = 4 }
+
+            [[song]]
+            clips.base = { file = "loops/a.mov" }
+            clips.top = { file = "b.mov", launch_quantize = "none" }
+        "#;
+        let folder = folder("setlist", show, &["loops/a.mov", "b.mov", "shaders/glow.wgsl"]);
+        let show = load_from(&folder, None).unwrap_or_else(|e| panic!("{}", e));
+        let grid = show.grid;
+        assert_eq!(grid.files, [folder.join("loops/a.mov"), folder.join("b.mov")]);
+        assert_eq!(grid.tracks[1].blend, BlendMode::Add);
+        assert_eq!(grid.scenes[0].name, "Intro");
+        assert_eq!(grid.scenes[1].name, "Scene 2");
+        assert_eq!(grid.scenes[0].slots[0].map(|slot| slot.loop_mode), Some(LoopMode::Bars(4)));
+        assert_eq!(grid.scenes[0].slots[1], None);
+        assert_eq!(grid.scenes[1].slots[0].map(|slot| slot.launch_quantize), Some(LaunchQuantize::Beat));
+        assert_eq!(grid.scenes[1].slots[1].map(|slot| slot.launch_quantize), Some(LaunchQuantize::None));
+        assert_eq!(show.shader_dir, Some(folder.join("shaders")));
+    }
+
+    #[test]
+    fn every_problem_is_reported_together() {
+        let show = r#"
+            version = "two"
+            tracks = ["base", 5, "base", "top", "top", "top"]
+            colour = "red"
+
+            [[scene]]
+            name = "Broken"
+            quantum = "fast"
+
+            [[scene]]
+            name = "Chorus"
+            effects = ["mirror", "sparkle"]
+            clips.base = { file = "missing.mov" }
+            clips.side = { file = "a.mov" }
+        "#;
+        let problems = problems(&folder("problems", show, &["a.mov"]));
+        let expected = [
+            "version: invalid type: string \"two\", expected u32",
+            "track 2: ",
+            "track `base` is listed more than once",
+            "track `top` is listed more than once",
+            "unknown field `colour`",
+            "scene `Broken`: invalid type: string \"fast\", expected f64",
+            "scene `Chorus` has a clip on unknown track `side`",
+            "scene `Chorus` plays ",
+            "scene `Chorus` turns on unknown effect `sparkle`",
+        ];
+        for expected in expected {
+            assert!(problems.iter().any(|problem| problem.starts_with(expected)), "no `{}` in {:#?}", expected, problems);
+        }
+        assert_eq!(problems.len(), expected.len(), "{:#?}", problems);
+    }
+
+    #[test]
+    fn scene_effects_come_from_the_default_shader_folder() {
+        let show = r#"
+            tracks = ["base"]
+            [[scene]]
+            effects = ["glow"]
+            clips.base = { file = "a.mov" }
+        "#;
+        let folder = folder("effects", show, &["a.mov", "fx/glow.fs"]);
+        assert!(load_from(&folder, Some(&folder.join("fx"))).is_ok());
+        assert!(matches!(load_from(&folder, None), Err(ShowError::Invalid(_))));
+    }
+
+    #[test]
+    fn syntax_errors_stop_reading() {
+        let folder = folder("syntax", "tracks = [", &[]);
+        assert!(matches!(load_from(&folder, None), Err(ShowError::Parse(_))));
+    }
+}
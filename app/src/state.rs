@@ -1,42 +1,63 @@
-use crate::vertex::{Vertex, INDICES, VERTICES};
+use crate::compositor::{BlendMode, Compositor, Pixels};
+use crate::effects::EffectChain;
+use crate::text;
+use crate::transition::TransitionKind;
+use crate::yuv::{Colors, Layout};
+use crate::vertex::{Quad, Vertex};
 use ffmpeg_next as ffmpeg;
+use std::path::Path;
 use wgpu::util::DeviceExt;
-use winit::{event::{WindowEvent, KeyEvent}, window::{Window, Fullscreen}, keyboard::{KeyCode, PhysicalKey}};
+use winit::{event::{WindowEvent, KeyEvent}, monitor::MonitorHandle, window::{Window, Fullscreen}, keyboard::{KeyCode, PhysicalKey}};
+
+/// Width of on-screen messages in characters, which sets the size of their text
+const MESSAGE_COLUMNS: u32 = 100;
+
+/// Values the fragment shader reads besides the frame, laid out like `Params` in shader.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    opacity: f32,
+    armed: f32,
+    _padding: [f32; 2],
+}
+
+/// Format of the picture when drawing without a window
+const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
 
 /// state of rendering engine
 pub struct State<'a> {
-    surface: wgpu::Surface<'a>,
+    /// `None` when drawing without a window, see [`State::headless`]
+    surface: Option<wgpu::Surface<'a>>,
     pub surface_configured: bool,
     device: wgpu::Device,
     queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
-    window: &'a Window,
-    texture_bind_group: wgpu::BindGroup,
+    window: Option<&'a Window>,
+    /// monitor to go fullscreen on, the window's own if not set
+    monitor: Option<MonitorHandle>,
+    /// texture [`State::capture`] draws into and the buffer it is read back through
+    capture: Option<(wgpu::Texture, wgpu::Buffer)>,
+    /// one layer per track, stacked into the picture shown
+    compositor: Compositor,
+    /// post-processing run over the composited layers
+    effects: EffectChain,
     render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
-    diffuse_texture: wgpu::Texture,
-    texture_width: u32,
-    texture_height: u32,
+    quad: Quad,
     is_fullscreen: bool,
-    video_aspect_ratio: f32,
+    /// clear to black instead of drawing the layers
+    blackout: bool,
+    params: Params,
+    params_buffer: wgpu::Buffer,
+    params_bind_group: wgpu::BindGroup,
 }
 
 impl<'a> State<'a> {
     // Creating some of the wgpu types requires async code
-    pub async fn new(window: &'a Window) -> State<'a> {
+    pub async fn new(window: &'a Window, layer_count: usize) -> State<'a> {
         let size = window.inner_size();
 
-        // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            #[cfg(not(target_arch = "wasm32"))]
-            backends: wgpu::Backends::PRIMARY,
-            ..Default::default()
-        });
-
+        let instance = instance();
         let surface = instance.create_surface(window).unwrap();
 
         let adapter = instance
@@ -48,22 +69,7 @@ impl<'a> State<'a> {
             .await
             .unwrap();
         dbg!(&adapter);
-        let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::empty(),
-                // WebGL doesn't support all of wgpu's features, so if
-                // we're building for the web, we'll have to disable some.
-                required_limits: if cfg!(target_arch = "wasm32") {
-                    wgpu::Limits::downlevel_webgl2_defaults()
-                } else {
-                    wgpu::Limits::default()
-                },
-                label: None,
-                memory_hints: Default::default(),
-                trace: wgpu::Trace::Off,
-            })
-            .await
-            .unwrap();
+        let (device, queue) = request_device(&adapter).await;
         let surface_caps = surface.get_capabilities(&adapter);
         // Shader code in this tutorial assumes an sRGB surface texture. Using a different
         // one will result in all the colors coming out darker. If you want to support non
@@ -85,88 +91,75 @@ impl<'a> State<'a> {
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
+        surface.configure(&device, &config);
 
-        // Create a placeholder texture that will be updated with video frames
-        // We'll start with a 1x1 texture and resize it when we get the first frame
-        let texture_size = wgpu::Extent3d {
-            width: 1,
-            height: 1,
-            depth_or_array_layers: 1,
-        };
-        let diffuse_texture = device.create_texture(&wgpu::TextureDescriptor {
-            size: texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: Some("diffuse_texture"),
-            view_formats: &[],
-        });
+        State::build(device, queue, config, Some(surface), Some(window), layer_count)
+    }
 
-        // Initialize with a single black pixel
-        let black_pixel = [0u8, 0u8, 0u8, 255u8];
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &diffuse_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &black_pixel,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4),
-                rows_per_image: Some(1),
-            },
-            texture_size,
-        );
+    /// A state drawing a `width` by `height` picture without a window, read back with
+    /// [`State::capture`]
+    pub async fn headless(width: u32, height: u32, layer_count: usize) -> State<'a> {
+        let adapter = instance()
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            })
+            .await
+            .unwrap();
+        let (device, queue) = request_device(&adapter).await;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: HEADLESS_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        State::build(device, queue, config, None, None, layer_count)
+    }
 
-        // view + sampler
-        let diffuse_view = diffuse_texture.create_view(&Default::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
+    fn build(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        surface: Option<wgpu::Surface<'a>>,
+        window: Option<&'a Window>,
+        layer_count: usize,
+    ) -> State<'a> {
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+        let compositor = Compositor::new(&device, &queue, size.width, size.height, layer_count);
+        let effects = EffectChain::new(&device, compositor.output_layout(), size.width, size.height);
+
+        // shader parameters
+        let params = Params { opacity: 1.0, armed: 0.0, _padding: [0.0; 2] };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params_buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
-
-        // bind‑group layout + instance
-        let tex_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
                 },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-            ],
-            label: Some("texture_bind_group_layout"),
+                count: None,
+            }],
+            label: Some("params_bind_group_layout"),
         });
-        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &tex_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_view),
-                },
-            ],
-            label: Some("texture_bind_group"),
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &params_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            }],
+            label: Some("params_bind_group"),
         });
 
         // shader & pipeline
@@ -176,7 +169,7 @@ impl<'a> State<'a> {
         });
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("pipeline_layout"),
-            bind_group_layouts: &[&tex_layout],
+            bind_group_layouts: &[compositor.output_layout(), &params_layout],
             push_constant_ranges: &[],
         });
         let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
@@ -193,7 +186,7 @@ impl<'a> State<'a> {
                 entry_point: Some("fs_main"), // ← now Option<&str>
                 targets: &[Some(wgpu::ColorTargetState {
                     format: config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    blend: None,
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
                 compilation_options: Default::default(), // ← new field
@@ -205,20 +198,7 @@ impl<'a> State<'a> {
             cache: None, // ← new field
         });
 
-        // vertex / index buffers
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("vertex_buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("index_buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-
-        // (‼️) configure the surface once up‑front
-        surface.configure(&device, &config);
+        let quad = Quad::new(&device);
 
         // final return
         Self {
@@ -228,97 +208,28 @@ impl<'a> State<'a> {
             config,
             size,
             window,
-            texture_bind_group,
+            monitor: None,
+            capture: None,
+            compositor,
+            effects,
             render_pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_indices: INDICES.len() as u32,
-            diffuse_texture,
-            texture_width: 1,
-            texture_height: 1,
+            quad,
             is_fullscreen: false,
-            video_aspect_ratio: 1.0,
             surface_configured: false,
+            blackout: false,
+            params,
+            params_buffer,
+            params_bind_group,
         }
     }
 
     pub fn window(&self) -> &Window {
-        &self.window
+        self.window.expect("State drawing without a window has none")
     }
 
-    pub fn recreate_texture(&mut self, width: u32, height: u32) {
-        let texture_size = wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        };
-
-        self.diffuse_texture = self.device.create_texture(&wgpu::TextureDescriptor {
-            size: texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: Some("diffuse_texture"),
-            view_formats: &[],
-        });
-
-        // Recreate the texture view and bind group
-        let diffuse_view = self.diffuse_texture.create_view(&Default::default());
-        let sampler = self.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
-        });
-
-        let tex_layout = self
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
-        self.texture_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &tex_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_view),
-                },
-            ],
-            label: Some("texture_bind_group"),
-        });
-
-        self.texture_width = width;
-        self.texture_height = height;
-        self.video_aspect_ratio = width as f32 / height as f32;
-        
-        // Update vertex buffer with new aspect ratio
-        self.update_vertex_buffer_for_aspect_ratio();
+    /// Go fullscreen on `monitor` rather than wherever the window is
+    pub fn set_monitor(&mut self, monitor: Option<MonitorHandle>) {
+        self.monitor = monitor;
     }
 
     // impl State
@@ -328,10 +239,11 @@ impl<'a> State<'a> {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-            
-            // Update vertex buffer for new window aspect ratio
-            self.update_vertex_buffer_for_aspect_ratio();
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
+            self.compositor.resize(&self.device, new_size.width, new_size.height);
+            self.effects.resize(&self.device, new_size.width, new_size.height);
         }
     }
 
@@ -380,147 +292,264 @@ impl<'a> State<'a> {
     }
 
     pub fn toggle_fullscreen(&mut self) {
+        let Some(window) = self.window else {
+            return;
+        };
         self.is_fullscreen = !self.is_fullscreen;
         
         if self.is_fullscreen {
             // Enter fullscreen mode
-            self.window.set_fullscreen(Some(Fullscreen::Borderless(None)));
+            window.set_fullscreen(Some(Fullscreen::Borderless(self.monitor.clone())));
         } else {
             // Exit fullscreen mode
-            self.window.set_fullscreen(None);
+            window.set_fullscreen(None);
         }
         
         log::info!("Fullscreen toggled: {}", self.is_fullscreen);
     }
 
+    pub fn is_fullscreen(&self) -> bool {
+        self.is_fullscreen
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        if fullscreen != self.is_fullscreen {
+            self.toggle_fullscreen();
+        }
+    }
+
     pub fn exit_fullscreen(&mut self) {
-        if self.is_fullscreen {
+        if let (true, Some(window)) = (self.is_fullscreen, self.window) {
             self.is_fullscreen = false;
-            self.window.set_fullscreen(None);
+            window.set_fullscreen(None);
             log::info!("Exited fullscreen mode");
         }
     }
 
-    /// Update vertex buffer to maintain video aspect ratio
-    fn update_vertex_buffer_for_aspect_ratio(&mut self) {
-        if self.video_aspect_ratio <= 0.0 {
-            return; // Skip if we don't have valid video dimensions yet
+    pub fn opacity(&self) -> f32 {
+        self.params.opacity
+    }
+
+    /// Fade the picture towards black, 0.0 to 1.0
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.params.opacity = opacity.clamp(0.0, 1.0);
+        self.queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+
+    /// Frame the picture to show a clip is waiting to launch
+    pub fn set_armed(&mut self, armed: bool) {
+        self.params.armed = if armed { 1.0 } else { 0.0 };
+        self.queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+
+    /// Show only black until turned off again
+    pub fn set_blackout(&mut self, blackout: bool) {
+        self.blackout = blackout;
+    }
+
+    pub fn effects(&self) -> &EffectChain {
+        &self.effects
+    }
+
+    pub fn effects_mut(&mut self) -> &mut EffectChain {
+        &mut self.effects
+    }
+
+    /// Add or rebuild the effect in a `.wgsl` or ISF `.fs` file, named after the file
+    pub fn load_shader(&mut self, path: &Path) {
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            return;
+        };
+        match std::fs::read_to_string(path) {
+            Ok(source) => {
+                log::info!("Loading effect {} from {}", name, path.display());
+                if path.extension().is_some_and(|extension| extension == "fs") {
+                    self.load_isf_effect(name, &source);
+                } else {
+                    self.load_effect(name, &source);
+                }
+            }
+            Err(e) => log::error!("Failed to read effect {}: {}", path.display(), e),
         }
+    }
 
-        let window_aspect_ratio = self.size.width as f32 / self.size.height as f32;
-        
-        let (scale_x, scale_y) = if self.video_aspect_ratio > window_aspect_ratio {
-            // Video is wider than window - fit to width, letterbox top/bottom
-            (1.0, window_aspect_ratio / self.video_aspect_ratio)
-        } else {
-            // Video is taller than window - fit to height, pillarbox left/right
-            (self.video_aspect_ratio / window_aspect_ratio, 1.0)
+    /// Drop the effect a shader file that is gone was loaded as, see [`EffectChain::remove`]
+    pub fn unload_shader(&mut self, path: &Path) {
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            return;
         };
+        if self.effects.remove(&self.device, name) {
+            log::info!("Effect {} removed with {}", name, path.display());
+        }
+    }
 
-        // Create new vertices with aspect ratio correction
-        let corrected_vertices = [
-            Vertex { pos: [-scale_x, -scale_y], uv: [0.0, 1.0] },
-            Vertex { pos: [ scale_x, -scale_y], uv: [1.0, 1.0] },
-            Vertex { pos: [ scale_x,  scale_y], uv: [1.0, 0.0] },
-            Vertex { pos: [-scale_x,  scale_y], uv: [0.0, 0.0] },
-        ];
-
-        // Update the vertex buffer
-        self.vertex_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("aspect_ratio_vertex_buffer"),
-            contents: bytemuck::cast_slice(&corrected_vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+    /// Add or rebuild an effect from WGSL source, see [`EffectChain::load`]
+    pub fn load_effect(&mut self, name: &str, source: &str) {
+        self.effects.load(&self.device, name, source);
+    }
 
-        log::debug!("Updated vertex buffer for aspect ratio: video={:.3}, window={:.3}, scale=({:.3}, {:.3})", 
-                   self.video_aspect_ratio, window_aspect_ratio, scale_x, scale_y);
+    /// Add or rebuild an effect from an ISF shader, see [`EffectChain::load_isf`]
+    pub fn load_isf_effect(&mut self, name: &str, source: &str) {
+        self.effects.load_isf(&self.device, name, source);
+    }
+
+    /// Show `lines` of text over the picture until [`State::hide_message`]
+    pub fn show_message(&mut self, lines: &[String]) {
+        let panel = text::panel(lines, MESSAGE_COLUMNS);
+        let (width, height) = panel.dimensions();
+        let image = Pixels::rgba(width, height, panel.as_raw());
+        self.compositor.show_overlay(&self.device, &self.queue, image);
+    }
+
+    pub fn hide_message(&mut self) {
+        self.compositor.hide_overlay();
     }
 
     pub fn update(&mut self) {
         // todo!()
     }
 
-    pub fn update_texture_with_frame(&mut self, frame: &ffmpeg::util::frame::Video) {
-        let width = frame.width() as u32;
-        let height = frame.height() as u32;
-        let data = frame.data(0);
-        let stride = frame.stride(0) as u32;
+    /// Show `frame` on the layer of `track`
+    pub fn update_layer_frame(&mut self, track: usize, frame: &ffmpeg::util::frame::Video) {
+        if let Some(image) = frame_pixels(frame) {
+            self.compositor.upload(&self.device, &self.queue, track, image);
+        }
+    }
 
-        // Check if we need to recreate the texture with new dimensions
-        if self.texture_width != width || self.texture_height != height {
-            self.recreate_texture(width, height);
+    /// Show `frame` as the clip `track` is transitioning away from
+    pub fn update_layer_outgoing_frame(&mut self, track: usize, frame: &ffmpeg::util::frame::Video) {
+        if let Some(image) = frame_pixels(frame) {
+            self.compositor.upload_outgoing(&self.device, &self.queue, track, image);
         }
+    }
 
-        // Calculate the actual row size (width * 4 bytes per pixel for RGBA)
-        let row_size = width * 4;
+    /// Keep what the layer of `track` shows now as the outgoing picture of a transition
+    pub fn begin_layer_transition(&mut self, track: usize, kind: TransitionKind) {
+        self.compositor.begin_transition(&self.device, track, kind);
+    }
 
-        // If stride equals row size, we can copy directly
-        if stride == row_size {
-            self.queue.write_texture(
-                wgpu::TexelCopyTextureInfo {
-                    texture: &self.diffuse_texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                    aspect: wgpu::TextureAspect::All,
-                },
-                data,
-                wgpu::TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(row_size),
-                    rows_per_image: Some(height),
-                },
-                wgpu::Extent3d {
-                    width,
-                    height,
-                    depth_or_array_layers: 1,
-                },
-            );
-        } else {
-            // If stride is different, we need to copy row by row
-            let mut packed_data = Vec::with_capacity((width * height * 4) as usize);
-            for y in 0..height {
-                let row_start = (y * stride) as usize;
-                let row_end = row_start + row_size as usize;
-                packed_data.extend_from_slice(&data[row_start..row_end]);
-            }
+    /// Move the transition of `track` along, 0.0 to 1.0, or end it with `None`
+    pub fn set_layer_transition(&mut self, track: usize, transition: Option<(TransitionKind, f32)>) {
+        self.compositor.set_transition(track, transition);
+    }
 
-            self.queue.write_texture(
-                wgpu::TexelCopyTextureInfo {
-                    texture: &self.diffuse_texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                    aspect: wgpu::TextureAspect::All,
-                },
-                &packed_data,
-                wgpu::TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(row_size),
-                    rows_per_image: Some(height),
-                },
-                wgpu::Extent3d {
-                    width,
-                    height,
-                    depth_or_array_layers: 1,
-                },
-            );
-        }
+    /// Use a still image as the matte of luma wipes
+    pub fn set_luma_matte(&mut self, matte: &image::RgbaImage) {
+        let (width, height) = matte.dimensions();
+        let image = Pixels::rgba(width, height, matte.as_raw());
+        self.compositor.set_matte(&self.device, &self.queue, image);
+    }
+
+    /// Whether the layer of `track` is drawn, it is hidden while the track is stopped
+    pub fn set_layer_visible(&mut self, track: usize, visible: bool) {
+        self.compositor.set_visible(track, visible);
+    }
+
+    pub fn set_layer_opacity(&mut self, track: usize, opacity: f32) {
+        self.compositor.set_opacity(track, opacity);
+    }
+
+    pub fn set_layer_blend(&mut self, track: usize, blend: BlendMode) {
+        self.compositor.set_blend(track, blend);
+    }
+
+    /// Show a still image instead of the layers until [`State::hide_slate`]
+    pub fn show_slate(&mut self, slate: &image::RgbaImage) {
+        let (width, height) = slate.dimensions();
+        let image = Pixels::rgba(width, height, slate.as_raw());
+        self.compositor.show_slate(&self.device, &self.queue, image);
+    }
+
+    pub fn hide_slate(&mut self) {
+        self.compositor.hide_slate();
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let frame = self.surface.get_current_texture()?;
+        let Some(surface) = &self.surface else {
+            return Ok(());
+        };
+        let frame = surface.get_current_texture()?;
         let view = frame.texture.create_view(&Default::default());
+        self.draw(&view);
+        frame.present();
+        Ok(())
+    }
 
+    /// Draw the picture and read it back, as tightly packed rows of sRGB RGBA pixels
+    pub fn capture(&mut self) -> Vec<u8> {
+        let (width, height) = (self.config.width, self.config.height);
+        let row_size = width * 4;
+        let padded_row_size = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let (texture, buffer) = self.capture.get_or_insert_with(|| {
+            let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                label: Some("capture_texture"),
+                view_formats: &[],
+            });
+            let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("capture_buffer"),
+                size: (padded_row_size * height) as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+            (texture, buffer)
+        });
+        let (texture, buffer) = (texture.clone(), buffer.clone());
+
+        self.draw(&texture.create_view(&Default::default()));
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("capture") });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            if let Err(e) = result {
+                log::error!("Failed to read back the picture: {}", e);
+            }
+        });
+        self.device.poll(wgpu::PollType::Wait).expect("Failed to wait for the GPU");
+        let pixels = slice
+            .get_mapped_range()
+            .chunks(padded_row_size as usize)
+            .flat_map(|row| &row[..row_size as usize])
+            .copied()
+            .collect();
+        buffer.unmap();
+        pixels
+    }
+
+    /// Composite the layers, run the effects and draw the result into `view`
+    fn draw(&mut self, view: &wgpu::TextureView) {
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("encoder"),
             });
+        let composite = self.compositor.render(&self.queue, &mut encoder, &self.quad);
+        let composite = self.effects.render(&self.queue, &mut encoder, &self.quad, composite);
+        let composite = self.compositor.render_overlay(&self.queue, &mut encoder, &self.quad, composite);
 
         {
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("render_pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -532,15 +561,59 @@ impl<'a> State<'a> {
                 timestamp_writes: None,
             });
 
-            rpass.set_pipeline(&self.render_pipeline);
-            rpass.set_bind_group(0, &self.texture_bind_group, &[]);
-            rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            rpass.draw_indexed(0..self.num_indices, 0, 0..1);
+            if !self.blackout {
+                rpass.set_pipeline(&self.render_pipeline);
+                rpass.set_bind_group(0, composite, &[]);
+                rpass.set_bind_group(1, &self.params_bind_group, &[]);
+                self.quad.draw(&mut rpass);
+            }
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
-        frame.present();
-        Ok(())
     }
 }
+
+/// The instance is a handle to our GPU
+fn instance() -> wgpu::Instance {
+    // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
+    wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        #[cfg(not(target_arch = "wasm32"))]
+        backends: wgpu::Backends::PRIMARY,
+        ..Default::default()
+    })
+}
+
+async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    adapter
+        .request_device(&wgpu::DeviceDescriptor {
+            // 10-bit video keeps its depth where 16-bit textures are available
+            required_features: adapter.features() & wgpu::Features::TEXTURE_FORMAT_16BIT_NORM,
+            // WebGL doesn't support all of wgpu's features, so if
+            // we're building for the web, we'll have to disable some.
+            required_limits: if cfg!(target_arch = "wasm32") {
+                wgpu::Limits::downlevel_webgl2_defaults()
+            } else {
+                wgpu::Limits::default()
+            },
+            label: None,
+            memory_hints: Default::default(),
+            trace: wgpu::Trace::Off,
+        })
+        .await
+        .unwrap()
+}
+
+/// The planes of a decoded frame as they are, `None` for formats clips convert away from
+fn frame_pixels(frame: &ffmpeg::util::frame::Video) -> Option<Pixels<'_>> {
+    let Some(layout) = Layout::of(frame.format()) else {
+        log::warn!("Can't show frames in {:?}", frame.format());
+        return None;
+    };
+    Some(Pixels {
+        width: frame.width(),
+        height: frame.height(),
+        layout,
+        colors: Colors::of(frame),
+        planes: (0..layout.plane_count()).map(|plane| (frame.data(plane), frame.stride(plane) as u32)).collect(),
+    })
+}
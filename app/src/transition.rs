@@ -0,0 +1,80 @@
+/// How a track changes over from one clip to the next
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionKind {
+    /// the new clip replaces the old one straight away
+    Cut,
+    Crossfade,
+    /// the old clip fades to black, then the new one fades in
+    DipToBlack,
+    /// the new clip sweeps in from the left
+    Wipe,
+    /// the new clip shows through the dark parts of a matte image first
+    LumaWipe,
+}
+
+impl TransitionKind {
+    /// Reads `cut`, `crossfade`, `dip_to_black`, `wipe` or `luma_wipe`
+    pub fn parse(text: &str) -> Option<TransitionKind> {
+        match text {
+            "cut" => Some(TransitionKind::Cut),
+            "crossfade" => Some(TransitionKind::Crossfade),
+            "dip_to_black" => Some(TransitionKind::DipToBlack),
+            "wipe" => Some(TransitionKind::Wipe),
+            "luma_wipe" => Some(TransitionKind::LumaWipe),
+            _ => None,
+        }
+    }
+
+    /// Number composite.wgsl switches on
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+}
+
+/// How long a transition takes, in musical time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransitionLength {
+    Beats(f64),
+    Bars(f64),
+}
+
+impl TransitionLength {
+    /// Reads `beat`, `bar`, `N beats`, `N bars` or a number of bars
+    pub fn parse(text: &str) -> Option<TransitionLength> {
+        let length = match text.split_whitespace().collect::<Vec<_>>()[..] {
+            ["beat"] => TransitionLength::Beats(1.0),
+            ["bar"] => TransitionLength::Bars(1.0),
+            [count] => TransitionLength::Bars(count.parse().ok()?),
+            [count, "beat" | "beats"] => TransitionLength::Beats(count.parse().ok()?),
+            [count, "bar" | "bars"] => TransitionLength::Bars(count.parse().ok()?),
+            _ => return None,
+        };
+        let (TransitionLength::Beats(count) | TransitionLength::Bars(count)) = length;
+        (count > 0.0 && count.is_finite()).then_some(length)
+    }
+
+    pub fn beats(&self, quantum: f64) -> f64 {
+        match *self {
+            TransitionLength::Beats(beats) => beats,
+            TransitionLength::Bars(bars) => bars * quantum,
+        }
+    }
+}
+
+/// How a clip takes over its track when launched
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub length: TransitionLength,
+}
+
+impl Transition {
+    pub const CUT: Transition = Transition {
+        kind: TransitionKind::Cut,
+        length: TransitionLength::Bars(1.0),
+    };
+
+    pub fn is_cut(&self) -> bool {
+        self.kind == TransitionKind::Cut
+    }
+}
@@ -0,0 +1,161 @@
+use crate::media;
+use crossbeam_channel::{unbounded, Receiver};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often watched folders are looked at again
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Something that happened to a file in a watched folder
+pub enum FileEvent {
+    /// the file appeared or was written to
+    Changed(PathBuf),
+    Removed(PathBuf),
+}
+
+/// Looks for files appearing, changing and going away in a set of folders on a worker thread.
+/// Folders are polled rather than subscribed to, which is plenty for a few files edited by hand.
+pub struct FolderWatcher {
+    events: Receiver<FileEvent>,
+}
+
+impl FolderWatcher {
+    /// Watch the files directly inside `folders` that `wanted` accepts. Files there already
+    /// aren't reported until they change.
+    pub fn spawn(folders: Vec<PathBuf>, wanted: fn(&Path) -> bool) -> FolderWatcher {
+        let (events_tx, events) = unbounded();
+
+        thread::Builder::new()
+            .name("folder-watcher".into())
+            .spawn(move || {
+                let mut seen = scan(&folders, wanted);
+                loop {
+                    thread::sleep(POLL_INTERVAL);
+                    let now = scan(&folders, wanted);
+                    let changed = now
+                        .iter()
+                        .filter(|(path, modified)| seen.get(*path) != Some(modified))
+                        .map(|(path, _)| FileEvent::Changed(path.clone()));
+                    let removed = seen
+                        .keys()
+                        .filter(|path| !now.contains_key(*path))
+                        .map(|path| FileEvent::Removed(path.clone()));
+                    for event in changed.chain(removed) {
+                        if events_tx.send(event).is_err() {
+                            return;
+                        }
+                    }
+                    seen = now;
+                }
+            })
+            .expect("Failed to spawn folder watcher thread");
+
+        FolderWatcher { events }
+    }
+
+    /// A change seen since the last call, if any
+    pub fn try_recv(&self) -> Option<FileEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+/// When each wanted file in `folders` was last written
+fn scan(folders: &[PathBuf], wanted: fn(&Path) -> bool) -> HashMap<PathBuf, SystemTime> {
+    folders
+        .iter()
+        .filter_map(|folder| std::fs::read_dir(folder).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && wanted(path))
+        .filter_map(|path| {
+            let modified = std::fs::metadata(&path).and_then(|metadata| metadata.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect()
+}
+
+/// A media folder as it was last scanned
+pub struct Playlist {
+    /// playable files and image sequences, in path order
+    pub files: Vec<PathBuf>,
+    /// files and image sequences written over since the previous playlist
+    pub replaced: Vec<PathBuf>,
+}
+
+/// Scans a media folder again on a worker thread whenever files anywhere in it are added,
+/// removed or written, once they have stopped changing
+pub struct PlaylistWatcher {
+    playlists: Receiver<Playlist>,
+}
+
+impl PlaylistWatcher {
+    /// Watch `folder` and every folder inside it, looking at the files `filter` lets through.
+    /// The files there already aren't reported until something changes.
+    pub fn spawn(folder: PathBuf, filter: media::Filter) -> PlaylistWatcher {
+        let (playlists_tx, playlists) = unbounded();
+
+        thread::Builder::new()
+            .name("playlist-watcher".into())
+            .spawn(move || {
+                let list = || -> HashMap<PathBuf, SystemTime> {
+                    media::list(&folder, &filter)
+                        .into_iter()
+                        .filter_map(|path| {
+                            let modified = std::fs::metadata(&path).and_then(|metadata| metadata.modified()).ok()?;
+                            Some((path, modified))
+                        })
+                        .collect()
+                };
+                // Files as of the last playlist, and as of the last look
+                let mut scanned = list();
+                let mut seen = scanned.clone();
+                let mut settling = false;
+                loop {
+                    thread::sleep(POLL_INTERVAL);
+                    let now = list();
+                    if now != seen {
+                        // Files still being copied or rendered are looked at once they stay put
+                        seen = now;
+                        settling = true;
+                        continue;
+                    }
+                    if !settling {
+                        continue;
+                    }
+                    settling = false;
+
+                    let scan = media::scan(&folder, &filter);
+                    log::info!("{} changed, {} playable files", folder.display(), scan.files.len());
+                    for skipped in &scan.skipped {
+                        log::debug!("Skipped {}: {}", skipped.path.display(), skipped.reason);
+                    }
+                    let written: Vec<&PathBuf> = seen
+                        .iter()
+                        .filter(|(path, modified)| scanned.get(*path).is_some_and(|before| before != *modified))
+                        .map(|(path, _)| path)
+                        .collect();
+                    let replaced = scan
+                        .files
+                        .iter()
+                        .filter(|path| written.iter().any(|file| media::covers(path, file)))
+                        .cloned()
+                        .collect();
+                    scanned = seen.clone();
+                    if playlists_tx.send(Playlist { files: scan.files, replaced }).is_err() {
+                        return;
+                    }
+                }
+            })
+            .expect("Failed to spawn playlist watcher thread");
+
+        PlaylistWatcher { playlists }
+    }
+
+    /// A playlist scanned since the last call, if any
+    pub fn try_recv(&self) -> Option<Playlist> {
+        self.playlists.try_recv().ok()
+    }
+}
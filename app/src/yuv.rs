@@ -0,0 +1,214 @@
+use ffmpeg::format::Pixel;
+use ffmpeg::util::color;
+use ffmpeg_next as ffmpeg;
+
+/// How the planes of a picture hold its pixels. Frames in any other format are converted
+/// to RGBA when decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// one plane of sRGB pixels with alpha, for stills and frames converted on the CPU
+    Rgba,
+    /// a luma plane, then U and V interleaved at half width and height
+    Nv12,
+    /// luma, U and V planes, chroma at half width and height
+    Yuv420,
+    /// luma, U and V planes, chroma at half width
+    Yuv422,
+    /// like `Yuv422` with 10-bit samples in 16-bit little endian words
+    Yuv422P10,
+}
+
+impl Layout {
+    /// Layout of frames decoded as `format`, if they can be shown as they are
+    pub fn of(format: Pixel) -> Option<Layout> {
+        match format {
+            Pixel::RGBA => Some(Layout::Rgba),
+            Pixel::NV12 => Some(Layout::Nv12),
+            Pixel::YUV420P | Pixel::YUVJ420P => Some(Layout::Yuv420),
+            Pixel::YUV422P | Pixel::YUVJ422P => Some(Layout::Yuv422),
+            Pixel::YUV422P10LE => Some(Layout::Yuv422P10),
+            _ => None,
+        }
+    }
+
+    pub fn plane_count(self) -> usize {
+        match self {
+            Layout::Rgba => 1,
+            Layout::Nv12 => 2,
+            Layout::Yuv420 | Layout::Yuv422 | Layout::Yuv422P10 => 3,
+        }
+    }
+
+    /// Size in pixels of plane `index` of a picture `width` by `height`
+    pub fn plane_size(self, index: usize, width: u32, height: u32) -> (u32, u32) {
+        match (self, index) {
+            (_, 0) | (Layout::Rgba, _) => (width, height),
+            (Layout::Nv12 | Layout::Yuv420, _) => (width.div_ceil(2), height.div_ceil(2)),
+            (Layout::Yuv422 | Layout::Yuv422P10, _) => (width.div_ceil(2), height),
+        }
+    }
+
+    /// Bytes of each pixel of plane `index`
+    pub fn bytes_per_pixel(self, index: usize) -> u32 {
+        match (self, index) {
+            (Layout::Rgba, _) => 4,
+            (Layout::Nv12, 1) => 2,
+            (Layout::Yuv422P10, _) => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            Layout::Yuv422P10 => 10,
+            _ => 8,
+        }
+    }
+
+    /// Number composite.wgsl switches on
+    pub fn code(self) -> u32 {
+        match self {
+            Layout::Rgba => 0,
+            Layout::Nv12 => 1,
+            Layout::Yuv420 | Layout::Yuv422 | Layout::Yuv422P10 => 2,
+        }
+    }
+}
+
+/// Which standard's weights turn YUV into RGB
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Matrix {
+    Bt601,
+    Bt709,
+    Bt2020,
+}
+
+impl Matrix {
+    /// The matrix a frame is tagged with. Untagged frames are assumed to follow
+    /// BT.709 from 720 lines up and BT.601 below, as players generally do.
+    pub fn of(space: color::Space, height: u32) -> Matrix {
+        match space {
+            color::Space::BT709 => Matrix::Bt709,
+            color::Space::BT470BG | color::Space::SMPTE170M | color::Space::FCC => Matrix::Bt601,
+            color::Space::BT2020NCL | color::Space::BT2020CL => Matrix::Bt2020,
+            _ if height >= 720 => Matrix::Bt709,
+            _ => Matrix::Bt601,
+        }
+    }
+
+    /// Luma weights of red and blue
+    pub fn kr_kb(self) -> (f32, f32) {
+        match self {
+            Matrix::Bt601 => (0.299, 0.114),
+            Matrix::Bt709 => (0.2126, 0.0722),
+            Matrix::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+}
+
+/// How the YUV of a picture is to be read
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Colors {
+    pub matrix: Matrix,
+    /// samples use the whole range rather than 16-235 (scaled up for deeper samples)
+    pub full_range: bool,
+}
+
+impl Colors {
+    /// What stills and RGBA frames use, the values are never read
+    pub const RGB: Colors = Colors { matrix: Matrix::Bt709, full_range: true };
+
+    /// How to read `frame`
+    pub fn of(frame: &ffmpeg::util::frame::Video) -> Colors {
+        Colors::tagged(frame.format(), frame.color_space(), frame.color_range(), frame.height())
+    }
+
+    /// How to read pictures tagged with `space` and `range`, JPEG formats being full range
+    /// whatever they are tagged with
+    pub fn tagged(format: Pixel, space: color::Space, range: color::Range, height: u32) -> Colors {
+        let full_range = matches!(format, Pixel::YUVJ420P | Pixel::YUVJ422P) || range == color::Range::JPEG;
+        Colors { matrix: Matrix::of(space, height), full_range }
+    }
+
+    /// Offset and scale taking normalized luma samples of `bits` stored in `container_bits`
+    /// to 0.0 to 1.0, and chroma to -0.5 to 0.5
+    pub fn ranges(self, bits: u32, container_bits: u32) -> [f32; 4] {
+        let container = ((1u32 << container_bits) - 1) as f32;
+        let step = (1u32 << (bits - 8)) as f32;
+        let max = ((1u32 << bits) - 1) as f32;
+        let middle = 128.0 * step;
+        if self.full_range {
+            [0.0, container / max, middle / container, container / max]
+        } else {
+            [16.0 * step / container, container / (219.0 * step), middle / container, container / (224.0 * step)]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Luma and chroma from samples stored in `container_bits`, as composite.wgsl reads them
+    fn read(ranges: [f32; 4], container_bits: u32, luma: u32, chroma: u32) -> (f32, f32) {
+        let container = ((1u32 << container_bits) - 1) as f32;
+        ((luma as f32 / container - ranges[0]) * ranges[1], (chroma as f32 / container - ranges[2]) * ranges[3])
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{actual} is not {expected}");
+    }
+
+    fn limited() -> Colors {
+        Colors { matrix: Matrix::Bt709, full_range: false }
+    }
+
+    #[test]
+    fn limited_8_bit_ranges() {
+        let ranges = limited().ranges(8, 8);
+        let expected = [16.0 / 255.0, 255.0 / 219.0, 128.0 / 255.0, 255.0 / 224.0];
+        for (actual, expected) in ranges.into_iter().zip(expected) {
+            assert_near(actual, expected);
+        }
+        let (black, middle) = read(ranges, 8, 16, 128);
+        assert_near(black, 0.0);
+        assert_near(middle, 0.0);
+        let (white, top) = read(ranges, 8, 235, 240);
+        assert_near(white, 1.0);
+        assert_near(top, 0.5);
+    }
+
+    #[test]
+    fn limited_10_bit_in_16_bit_containers() {
+        let ranges = limited().ranges(10, 16);
+        let (black, bottom) = read(ranges, 16, 64, 64);
+        assert_near(black, 0.0);
+        assert_near(bottom, -0.5);
+        let (white, middle) = read(ranges, 16, 940, 512);
+        assert_near(white, 1.0);
+        assert_near(middle, 0.0);
+    }
+
+    #[test]
+    fn full_range_covers_every_sample() {
+        let full = Colors { matrix: Matrix::Bt601, full_range: true };
+        let (black, middle) = read(full.ranges(8, 8), 8, 0, 128);
+        assert_near(black, 0.0);
+        assert_near(middle, 0.0);
+        let (white, _) = read(full.ranges(8, 8), 8, 255, 128);
+        assert_near(white, 1.0);
+        let (white, middle) = read(full.ranges(10, 16), 16, 1023, 512);
+        assert_near(white, 1.0);
+        assert_near(middle, 0.0);
+    }
+
+    #[test]
+    fn jpeg_formats_are_full_range_whatever_their_tag() {
+        let colors = Colors::tagged(Pixel::YUVJ420P, color::Space::Unspecified, color::Range::MPEG, 1080);
+        assert!(colors.full_range);
+        assert_eq!(colors.matrix, Matrix::Bt709);
+        let colors = Colors::tagged(Pixel::YUV420P, color::Space::Unspecified, color::Range::Unspecified, 576);
+        assert!(!colors.full_range);
+        assert_eq!(colors.matrix, Matrix::Bt601);
+    }
+}
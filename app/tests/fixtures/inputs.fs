@@ -0,0 +1,26 @@
+/*{
+    "DESCRIPTION": "One input of every type voop reads",
+    "CATEGORIES": ["Test"],
+    "ISFVSN": "2",
+    "INPUTS": [
+        { "NAME": "inputImage", "TYPE": "image" },
+        { "NAME": "amount", "TYPE": "float", "DEFAULT": 1.0, "MIN": -1.0, "MAX": 3.0 },
+        { "NAME": "steps", "TYPE": "long", "VALUES": [0, 1, 2, 4], "LABELS": ["0", "1", "2", "4"], "DEFAULT": 2 },
+        { "NAME": "flip", "TYPE": "bool", "DEFAULT": true },
+        { "NAME": "tint", "TYPE": "color", "DEFAULT": [1.0, 0.5, 0.25, 1.0] },
+        { "NAME": "center", "TYPE": "point2D", "DEFAULT": [25.0, 50.0], "MIN": [0.0, 0.0], "MAX": [100.0, 200.0] },
+        { "NAME": "offset", "TYPE": "point2D", "DEFAULT": [0.0, 0.0] },
+        { "NAME": "previous", "TYPE": "image" }
+    ]
+}*/
+
+void main() {
+    vec2 uv = isf_FragNormCoord;
+    if (flip) {
+        uv.x = 1.0 - uv.x;
+    }
+    uv += (center + offset) / RENDERSIZE * 0.1;
+    vec4 color = IMG_NORM_PIXEL(inputImage, uv) * tint * amount;
+    vec4 before = IMG_THIS_PIXEL(previous);
+    gl_FragColor = mix(color, before, float(steps) / 4.0);
+}
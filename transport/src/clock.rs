@@ -0,0 +1,56 @@
+use crate::{Transport, TransportSnapshot};
+use std::time::Instant;
+
+/// Free-running clock at a fixed tempo, for rehearsing without a DAW
+pub struct InternalClock {
+    started: Instant,
+    /// microseconds since `started` when the tempo or alignment last changed
+    origin_micros: i64,
+    /// beat at `origin_micros`
+    origin_beat: f64,
+    tempo: f64,
+    quantum: f64,
+}
+
+impl InternalClock {
+    pub fn new(tempo: f64, quantum: f64) -> InternalClock {
+        InternalClock {
+            started: Instant::now(),
+            origin_micros: 0,
+            origin_beat: 0.0,
+            tempo,
+            quantum,
+        }
+    }
+
+    fn now_micros(&self) -> i64 {
+        self.started.elapsed().as_micros() as i64
+    }
+
+    fn beat_at(&self, micros: i64) -> f64 {
+        self.origin_beat + (micros - self.origin_micros) as f64 / 1_000_000.0 * self.tempo / 60.0
+    }
+}
+
+impl Transport for InternalClock {
+    fn update_phase_and_beat(&mut self) -> TransportSnapshot {
+        let now = self.now_micros();
+        TransportSnapshot::at_beat(self.beat_at(now), self.tempo, self.quantum, now)
+    }
+
+    fn set_quantum(&mut self, quantum: f64) {
+        self.quantum = quantum;
+    }
+
+    fn propose_tempo(&mut self, bpm: f64) {
+        let now = self.now_micros();
+        self.origin_beat = self.beat_at(now);
+        self.origin_micros = now;
+        self.tempo = bpm;
+    }
+
+    fn request_beat_at_time(&mut self, beat: f64, timestamp: i64) {
+        self.origin_beat = beat;
+        self.origin_micros = timestamp;
+    }
+}
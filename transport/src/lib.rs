@@ -1 +1,25 @@
+pub mod clock;
 pub mod link;
+pub mod ltc;
+pub mod manual;
+pub mod midi_clock;
+pub mod snapshot;
+pub mod timecode;
+
+pub use snapshot::TransportSnapshot;
+
+/// A source of musical time for playback to follow
+pub trait Transport: Send {
+    /// Sample the transport's current position
+    fn update_phase_and_beat(&mut self) -> TransportSnapshot;
+
+    /// Set the number of beats in a bar
+    fn set_quantum(&mut self, quantum: f64);
+
+    /// Change the tempo, keeping the current beat where it is
+    fn propose_tempo(&mut self, bpm: f64);
+
+    /// Shift the timeline so `beat` falls at `timestamp`, in the clock of
+    /// [`TransportSnapshot::timestamp`]
+    fn request_beat_at_time(&mut self, beat: f64, timestamp: i64);
+}
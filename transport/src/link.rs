@@ -1,34 +1,70 @@
-use log::debug;
+use crate::{Transport, TransportSnapshot};
+use log::{debug, info};
 use rusty_link::{AblLink, SessionState};
 
 pub struct Link {
     link: AblLink,
     quantum: f64,
-    beat: f64,
-    pub phase: f64,
     state: SessionState,
 }
 impl Link {
-    pub fn new() -> Link {
+    /// Join the Link session, proposing `tempo` if we are the first peer
+    pub fn new(tempo: f64, quantum: f64) -> Link {
         // Link
-        let link = AblLink::new(120.0);
+        let link = AblLink::new(tempo);
         link.enable(true);
+        // follow the session's start/stop, not just its beat
+        link.enable_start_stop_sync(true);
         let state = SessionState::new();
-        let quantum = 4.0;
         Link {
             link,
             quantum,
-            beat: 0.0,
-            phase: 0.0,
             state,
         }
     }
-    pub fn update_phase_and_beat(&mut self) {
+}
+
+impl Transport for Link {
+    fn update_phase_and_beat(&mut self) -> TransportSnapshot {
         self.link.capture_app_session_state(&mut self.state);
         let now = self.link.clock_micros();
 
-        self.beat = self.state.beat_at_time(now, self.quantum);
-        self.phase = self.state.phase_at_time(now, self.quantum) / self.quantum;
-        debug!("Beat: {:.2}, Phase: {:.2}", self.beat, self.phase);
+        let beat = self.state.beat_at_time(now, self.quantum);
+        let phase = self.state.phase_at_time(now, self.quantum) / self.quantum;
+        debug!("Beat: {:.2}, Phase: {:.2}", beat, phase);
+
+        TransportSnapshot {
+            beat,
+            bar: (beat / self.quantum).floor() as i64,
+            phase,
+            tempo: self.state.tempo(),
+            quantum: self.quantum,
+            num_peers: self.link.num_peers(),
+            // with nobody in the session there is nothing to press play, so keep running
+            is_playing: self.state.is_playing() || self.link.num_peers() == 0,
+            timestamp: now,
+            timecode: None,
+        }
+    }
+
+    fn set_quantum(&mut self, quantum: f64) {
+        // Quantum is local to each peer, Link only uses it to align phases
+        self.quantum = quantum;
+        info!("Quantum: {}", quantum);
+    }
+
+    fn propose_tempo(&mut self, bpm: f64) {
+        self.link.capture_app_session_state(&mut self.state);
+        self.state.set_tempo(bpm, self.link.clock_micros());
+        self.link.commit_app_session_state(&self.state);
+        info!("Proposed tempo: {:.2} BPM", bpm);
+    }
+
+    fn request_beat_at_time(&mut self, beat: f64, timestamp: i64) {
+        self.link.capture_app_session_state(&mut self.state);
+        // With other peers connected Link moves us to the nearest matching phase instead
+        self.state.request_beat_at_time(beat, timestamp, self.quantum);
+        self.link.commit_app_session_state(&self.state);
+        info!("Requested beat {:.2} at {}", beat, timestamp);
     }
 }
@@ -0,0 +1,189 @@
+use crate::timecode::{FrameRate, Timecode};
+use std::path::Path;
+
+/// Bits in one LTC frame
+const FRAME_BITS: u32 = 80;
+/// Sync word in bits 64 to 79, read with bit 64 as the least significant
+const SYNC_WORD: u128 = 0xBFFC;
+/// Bit rate assumed before any bits have been measured, between 24 and 30 fps
+const INITIAL_BITS_PER_SEC: f64 = 2200.0;
+/// Signal level a sample must cross to count as a transition
+const HYSTERESIS: f32 = 0.02;
+/// Weight of each new bit length in the running estimate
+const PERIOD_SMOOTHING: f64 = 0.05;
+
+/// A timecode frame decoded from audio
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LtcFrame {
+    pub timecode: Timecode,
+    /// samples fed up to and including the edge that ended the frame's last bit
+    pub end_sample: u64,
+}
+
+/// Decodes SMPTE linear timecode from mono audio samples. The biphase mark signal has a
+/// transition at every bit boundary and an extra one mid-bit for ones.
+pub struct LtcDecoder {
+    sample_rate: f64,
+    /// estimated samples per bit
+    period: f64,
+    /// signal is currently above zero
+    high: bool,
+    /// samples since the last transition
+    since_transition: u64,
+    /// a short interval was seen and is waiting for its second half
+    half_bit: bool,
+    /// the last 80 bits, bit 0 of the frame lowest once a frame is complete
+    bits: u128,
+    samples: u64,
+}
+
+impl LtcDecoder {
+    pub fn new(sample_rate: u32) -> LtcDecoder {
+        LtcDecoder {
+            sample_rate: sample_rate as f64,
+            period: sample_rate as f64 / INITIAL_BITS_PER_SEC,
+            high: false,
+            since_transition: 0,
+            half_bit: false,
+            bits: 0,
+            samples: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// Samples fed so far
+    pub fn samples_decoded(&self) -> u64 {
+        self.samples
+    }
+
+    /// Feed samples, returning the frames that finished within them
+    pub fn decode(&mut self, samples: &[f32]) -> Vec<LtcFrame> {
+        let mut frames = Vec::new();
+        for &sample in samples {
+            self.samples += 1;
+            self.since_transition += 1;
+
+            let flipped = if self.high { sample < -HYSTERESIS } else { sample > HYSTERESIS };
+            if !flipped {
+                continue;
+            }
+            self.high = !self.high;
+            let interval = self.since_transition as f64;
+            self.since_transition = 0;
+
+            if let Some(frame) = self.on_transition(interval) {
+                frames.push(frame);
+            }
+        }
+        frames
+    }
+
+    fn on_transition(&mut self, interval: f64) -> Option<LtcFrame> {
+        // Anything far off the bit length is noise or silence, start over
+        if interval > self.period * 2.0 || interval < self.period * 0.25 {
+            self.half_bit = false;
+            return None;
+        }
+
+        if interval > self.period * 0.75 {
+            self.period += PERIOD_SMOOTHING * (interval - self.period);
+            self.half_bit = false;
+            return self.push_bit(false);
+        }
+
+        self.period += PERIOD_SMOOTHING * (interval * 2.0 - self.period);
+        if self.half_bit {
+            self.half_bit = false;
+            self.push_bit(true)
+        } else {
+            self.half_bit = true;
+            None
+        }
+    }
+
+    fn push_bit(&mut self, bit: bool) -> Option<LtcFrame> {
+        self.bits = (self.bits >> 1) | ((bit as u128) << (FRAME_BITS - 1));
+        if self.bits >> 64 != SYNC_WORD {
+            return None;
+        }
+
+        let timecode = self.frame_timecode();
+        Some(LtcFrame { timecode, end_sample: self.samples })
+    }
+
+    fn frame_timecode(&self) -> Timecode {
+        let field = |start: u32, len: u32| ((self.bits >> start) & ((1 << len) - 1)) as u8;
+        let drop_frame = field(10, 1) == 1;
+        let fps = self.sample_rate / (self.period * FRAME_BITS as f64);
+        Timecode {
+            hours: field(56, 2) * 10 + field(48, 4),
+            minutes: field(40, 3) * 10 + field(32, 4),
+            seconds: field(24, 3) * 10 + field(16, 4),
+            frames: field(8, 2) * 10 + field(0, 4),
+            rate: FrameRate::nearest(fps, drop_frame),
+        }
+    }
+}
+
+/// Decode every LTC frame in a WAV file, reading its first channel
+pub fn decode_wav(path: &Path) -> Result<Vec<LtcFrame>, hound::Error> {
+    let (sample_rate, samples) = read_wav(path)?;
+    Ok(LtcDecoder::new(sample_rate).decode(&samples))
+}
+
+/// First channel of a WAV file as samples between -1.0 and 1.0, with its sample rate
+pub(crate) fn read_wav(path: &Path) -> Result<(u32, Vec<f32>), hound::Error> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().step_by(channels).collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .step_by(channels)
+                .map(|sample| sample.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    Ok((spec.sample_rate, samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// 30 frames of 25 fps LTC from 01:00:00:00, 48 kHz 16-bit mono after 10 ms of silence
+    fn fixture() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/ltc_25fps_01h.wav")
+    }
+
+    fn expected(frame: u8) -> Timecode {
+        Timecode { hours: 1, minutes: 0, seconds: frame / 25, frames: frame % 25, rate: FrameRate::Fps25 }
+    }
+
+    #[test]
+    fn decodes_every_frame_of_a_wav() {
+        let frames = decode_wav(&fixture()).unwrap();
+        let timecodes: Vec<Timecode> = frames.iter().map(|frame| frame.timecode).collect();
+        assert_eq!(timecodes, (0..30).map(expected).collect::<Vec<_>>());
+        // 1920 samples a frame at 48 kHz after 480 of silence, each frame's end seen on the
+        // first sample of the next one
+        let ends: Vec<u64> = frames.iter().map(|frame| frame.end_sample).collect();
+        assert_eq!(ends, (1..=30).map(|n| 481 + n * 1920).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn finds_the_sync_word_in_inverted_signal() {
+        let (sample_rate, samples) = read_wav(&fixture()).unwrap();
+        let inverted: Vec<f32> = samples.iter().map(|sample| -sample).collect();
+        let timecodes: Vec<Timecode> =
+            LtcDecoder::new(sample_rate).decode(&inverted).iter().map(|frame| frame.timecode).collect();
+        assert_eq!(timecodes, (0..30).map(expected).collect::<Vec<_>>());
+    }
+}
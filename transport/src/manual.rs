@@ -0,0 +1,67 @@
+use crate::{Transport, TransportSnapshot};
+
+/// Clock that only moves when told to, for deterministic tests of playback logic
+pub struct ManualClock {
+    beat: f64,
+    tempo: f64,
+    quantum: f64,
+    is_playing: bool,
+    micros: i64,
+}
+
+impl ManualClock {
+    pub fn new(tempo: f64, quantum: f64) -> ManualClock {
+        ManualClock {
+            beat: 0.0,
+            tempo,
+            quantum,
+            is_playing: true,
+            micros: 0,
+        }
+    }
+
+    pub fn set_beat(&mut self, beat: f64) {
+        self.beat = beat;
+    }
+
+    /// Move time forward, advancing the beat at the current tempo while playing
+    pub fn advance_secs(&mut self, secs: f64) {
+        if self.is_playing {
+            self.beat += secs * self.tempo / 60.0;
+        }
+        self.micros += (secs * 1_000_000.0) as i64;
+    }
+
+    pub fn set_tempo(&mut self, tempo: f64) {
+        self.tempo = tempo;
+    }
+
+    pub fn set_playing(&mut self, is_playing: bool) {
+        self.is_playing = is_playing;
+    }
+}
+
+impl Transport for ManualClock {
+    fn update_phase_and_beat(&mut self) -> TransportSnapshot {
+        TransportSnapshot {
+            is_playing: self.is_playing,
+            ..TransportSnapshot::at_beat(self.beat, self.tempo, self.quantum, self.micros)
+        }
+    }
+
+    fn set_quantum(&mut self, quantum: f64) {
+        self.quantum = quantum;
+    }
+
+    fn propose_tempo(&mut self, bpm: f64) {
+        self.tempo = bpm;
+    }
+
+    fn request_beat_at_time(&mut self, beat: f64, timestamp: i64) {
+        self.beat = beat;
+        if self.is_playing {
+            let since = (self.micros - timestamp) as f64 / 1_000_000.0;
+            self.beat += since * self.tempo / 60.0;
+        }
+    }
+}
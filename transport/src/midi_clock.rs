@@ -0,0 +1,394 @@
+use crate::{Transport, TransportSnapshot};
+use log::{info, warn};
+use midir::{MidiInput, MidiInputConnection};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Clock messages per quarter note
+const PPQN: f64 = 24.0;
+/// Clock ticks in one Song Position Pointer step, a sixteenth note
+const TICKS_PER_SPP_STEP: i64 = 6;
+
+const CLOCK: u8 = 0xF8;
+const START: u8 = 0xFA;
+const CONTINUE: u8 = 0xFB;
+const STOP: u8 = 0xFC;
+const SONG_POSITION: u8 = 0xF2;
+
+/// Weight of the newest interval in the smoothed tick length
+const TEMPO_SMOOTHING: f64 = 0.05;
+/// How far a tick's smoothed time moves towards its actual arrival
+const PHASE_SMOOTHING: f64 = 0.2;
+/// An interval this many times the smoothed one means the clock paused, so measuring starts over
+const RESYNC_FACTOR: f64 = 4.0;
+/// Tempo reported before two clock messages have arrived
+const UNKNOWN_TEMPO: f64 = 120.0;
+
+/// Tempo and position followed from MIDI clock, start/stop/continue and Song Position Pointer
+/// messages sent by a hardware sequencer or DAW
+pub struct MidiClock {
+    started: Instant,
+    quantum: f64,
+    state: Arc<Mutex<ClockState>>,
+}
+
+/// Keeps a MIDI input feeding a [`MidiClock`] open until dropped
+pub struct MidiConnection {
+    _connection: MidiInputConnection<()>,
+}
+
+#[derive(Debug)]
+pub enum MidiError {
+    Init(midir::InitError),
+    /// no input port name contains the requested one
+    PortNotFound(String),
+    Connect(String),
+}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiError::Init(e) => write!(f, "{}", e),
+            MidiError::PortNotFound(name) => write!(f, "no MIDI input port matching `{}`", name),
+            MidiError::Connect(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl MidiClock {
+    pub fn new(quantum: f64) -> MidiClock {
+        MidiClock {
+            started: Instant::now(),
+            quantum,
+            state: Arc::new(Mutex::new(ClockState::new())),
+        }
+    }
+
+    /// Microseconds since the clock was created, the time base of `feed` and snapshots
+    pub fn now_micros(&self) -> i64 {
+        self.started.elapsed().as_micros() as i64
+    }
+
+    /// Handle raw MIDI bytes that arrived at `micros`
+    pub fn feed(&self, bytes: &[u8], micros: i64) {
+        self.state.lock().unwrap().handle_bytes(bytes, micros);
+    }
+
+    /// Where the clock was at `micros`, for callers feeding bytes with their own timestamps
+    pub fn snapshot_at(&self, micros: i64) -> TransportSnapshot {
+        let state = self.state.lock().unwrap();
+        TransportSnapshot {
+            num_peers: 0,
+            is_playing: state.is_playing,
+            ..TransportSnapshot::at_beat(state.beat_at(micros), state.tempo(), self.quantum, micros)
+        }
+    }
+
+    /// Listen to the first input port whose name contains `port_name`
+    pub fn connect(&self, port_name: &str) -> Result<MidiConnection, MidiError> {
+        connect_input(port_name, self.feeder())
+    }
+
+    /// Open a virtual input port named `port_name` for other software to send clock to
+    pub fn create_virtual(&self, port_name: &str) -> Result<MidiConnection, MidiError> {
+        create_virtual_input(port_name, self.feeder())
+    }
+
+    /// Callback for MIDI input threads, stamping bytes with our own clock on arrival
+    fn feeder(&self) -> impl FnMut(&[u8]) + Send + 'static {
+        let state = self.state.clone();
+        let started = self.started;
+        move |bytes| {
+            let micros = started.elapsed().as_micros() as i64;
+            state.lock().unwrap().handle_bytes(bytes, micros);
+        }
+    }
+}
+
+/// Open the first input port whose name contains `port_name`, handing every message to `feed`
+pub fn connect_input(
+    port_name: &str,
+    mut feed: impl FnMut(&[u8]) + Send + 'static,
+) -> Result<MidiConnection, MidiError> {
+    let input = MidiInput::new("voop").map_err(MidiError::Init)?;
+    let port = input
+        .ports()
+        .into_iter()
+        .find(|port| input.port_name(port).is_ok_and(|name| name.contains(port_name)))
+        .ok_or_else(|| MidiError::PortNotFound(port_name.to_string()))?;
+    info!("Listening to MIDI input {}", input.port_name(&port).unwrap_or_default());
+
+    input
+        .connect(&port, "voop-in", move |_, bytes, _| feed(bytes), ())
+        .map(|connection| MidiConnection { _connection: connection })
+        .map_err(|e| MidiError::Connect(e.to_string()))
+}
+
+/// Open a virtual input port named `port_name` that other software can send to
+#[cfg(unix)]
+pub fn create_virtual_input(
+    port_name: &str,
+    mut feed: impl FnMut(&[u8]) + Send + 'static,
+) -> Result<MidiConnection, MidiError> {
+    use midir::os::unix::VirtualInput;
+
+    let input = MidiInput::new("voop").map_err(MidiError::Init)?;
+    info!("Listening to virtual MIDI port {}", port_name);
+
+    input
+        .create_virtual(port_name, move |_, bytes, _| feed(bytes), ())
+        .map(|connection| MidiConnection { _connection: connection })
+        .map_err(|e| MidiError::Connect(e.to_string()))
+}
+
+#[cfg(not(unix))]
+pub fn create_virtual_input(
+    _port_name: &str,
+    _feed: impl FnMut(&[u8]) + Send + 'static,
+) -> Result<MidiConnection, MidiError> {
+    Err(MidiError::Connect("virtual MIDI ports are not supported on this platform".to_string()))
+}
+
+impl Transport for MidiClock {
+    fn update_phase_and_beat(&mut self) -> TransportSnapshot {
+        self.snapshot_at(self.now_micros())
+    }
+
+    fn set_quantum(&mut self, quantum: f64) {
+        self.quantum = quantum;
+    }
+
+    fn propose_tempo(&mut self, _bpm: f64) {
+        warn!("Tempo follows the MIDI clock sender and can't be changed here");
+    }
+
+    fn request_beat_at_time(&mut self, beat: f64, timestamp: i64) {
+        let mut state = self.state.lock().unwrap();
+        state.beat_offset += beat - state.beat_at(timestamp);
+    }
+}
+
+/// Where the parser is inside a Song Position Pointer message
+#[derive(Clone, Copy)]
+enum Pending {
+    None,
+    SongPositionLsb,
+    SongPositionMsb(u8),
+}
+
+struct ClockState {
+    /// tick the next clock message stands for, counted from the start of the song
+    next_tick: i64,
+    /// latest tick while playing and its smoothed arrival time
+    last_tick: Option<(i64, f64)>,
+    /// arrival of the previous clock message, playing or not
+    last_clock_micros: Option<i64>,
+    /// smoothed microseconds between clock messages
+    tick_micros: Option<f64>,
+    is_playing: bool,
+    /// added to the beat, set by `request_beat_at_time`
+    beat_offset: f64,
+    pending: Pending,
+}
+
+impl ClockState {
+    fn new() -> ClockState {
+        ClockState {
+            next_tick: 0,
+            last_tick: None,
+            last_clock_micros: None,
+            tick_micros: None,
+            is_playing: false,
+            beat_offset: 0.0,
+            pending: Pending::None,
+        }
+    }
+
+    fn handle_bytes(&mut self, bytes: &[u8], micros: i64) {
+        for &byte in bytes {
+            self.handle_byte(byte, micros);
+        }
+    }
+
+    fn handle_byte(&mut self, byte: u8, micros: i64) {
+        // Real-time messages may arrive in the middle of other messages
+        if byte >= CLOCK {
+            return self.handle_real_time(byte, micros);
+        }
+
+        if byte & 0x80 != 0 {
+            self.pending = if byte == SONG_POSITION { Pending::SongPositionLsb } else { Pending::None };
+            return;
+        }
+
+        self.pending = match self.pending {
+            Pending::None => Pending::None,
+            Pending::SongPositionLsb => Pending::SongPositionMsb(byte),
+            Pending::SongPositionMsb(lsb) => {
+                let steps = (byte as i64) << 7 | lsb as i64;
+                self.locate(steps * TICKS_PER_SPP_STEP);
+                self.beat_offset = 0.0;
+                Pending::None
+            }
+        };
+    }
+
+    fn handle_real_time(&mut self, byte: u8, micros: i64) {
+        match byte {
+            CLOCK => self.on_clock(micros),
+            START => {
+                self.locate(0);
+                self.beat_offset = 0.0;
+                self.is_playing = true;
+            }
+            CONTINUE => {
+                self.last_tick = None;
+                self.is_playing = true;
+            }
+            STOP => self.is_playing = false,
+            // active sensing, reset and undefined
+            _ => {}
+        }
+    }
+
+    fn on_clock(&mut self, micros: i64) {
+        if let Some(previous) = self.last_clock_micros {
+            let interval = (micros - previous) as f64;
+            self.tick_micros = Some(match self.tick_micros {
+                Some(smoothed) if interval < smoothed * RESYNC_FACTOR => {
+                    smoothed + TEMPO_SMOOTHING * (interval - smoothed)
+                }
+                _ => interval,
+            });
+        }
+        self.last_clock_micros = Some(micros);
+
+        if !self.is_playing {
+            return;
+        }
+        // Place the tick where the smoothed tempo predicts, pulled towards when it actually came
+        let at = match (self.last_tick, self.tick_micros) {
+            (Some((tick, at)), Some(tick_micros)) if tick + 1 == self.next_tick => {
+                let predicted = at + tick_micros;
+                predicted + PHASE_SMOOTHING * (micros as f64 - predicted)
+            }
+            _ => micros as f64,
+        };
+        self.last_tick = Some((self.next_tick, at));
+        self.next_tick += 1;
+    }
+
+    /// Jump to `tick`, to be reached by the next clock message
+    fn locate(&mut self, tick: i64) {
+        self.next_tick = tick;
+        self.last_tick = None;
+    }
+
+    fn tempo(&self) -> f64 {
+        match self.tick_micros {
+            Some(tick_micros) if tick_micros > 0.0 => 60_000_000.0 / (tick_micros * PPQN),
+            _ => UNKNOWN_TEMPO,
+        }
+    }
+
+    fn beat_at(&self, micros: i64) -> f64 {
+        let tick = match (self.is_playing, self.last_tick, self.tick_micros) {
+            (true, Some((tick, at)), Some(tick_micros)) => {
+                // Never run past the next tick, in case the clock stopped coming
+                tick as f64 + ((micros as f64 - at) / tick_micros).clamp(0.0, 1.0)
+            }
+            (true, Some((tick, _)), None) => tick as f64,
+            _ => self.next_tick as f64,
+        };
+        tick / PPQN + self.beat_offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Microseconds between clock messages at 120 bpm
+    const TICK_AT_120: i64 = 20_833;
+
+    #[test]
+    fn tempo_follows_the_clock_interval() {
+        let clock = MidiClock::new(4.0);
+        assert_eq!(clock.snapshot_at(0).tempo, UNKNOWN_TEMPO);
+        clock.feed(&[START], 0);
+        for tick in 0..48 {
+            clock.feed(&[CLOCK], tick * TICK_AT_120);
+        }
+        let snapshot = clock.snapshot_at(47 * TICK_AT_120);
+        assert!((snapshot.tempo - 120.0).abs() < 0.01, "tempo {}", snapshot.tempo);
+        assert!((snapshot.beat - 47.0 / PPQN).abs() < 1e-6, "beat {}", snapshot.beat);
+    }
+
+    #[test]
+    fn jitter_is_smoothed_out_of_tempo_and_beat() {
+        let clock = MidiClock::new(4.0);
+        clock.feed(&[START], 0);
+        // Every message 2 ms early or late, almost a tenth of a tick, so intervals swing
+        // almost 20% either way
+        let jitter = |tick: i64| if tick % 2 == 0 { 2_000 } else { -2_000 };
+        for tick in 0..120 {
+            clock.feed(&[CLOCK], tick * TICK_AT_120 + jitter(tick));
+            // Once the tempo has settled, ticks are placed within half the jitter
+            if tick >= 72 {
+                let beat = clock.snapshot_at(tick * TICK_AT_120).beat;
+                let error_ticks = (beat - tick as f64 / PPQN).abs() * PPQN;
+                assert!(error_ticks < 0.045, "tick {} off by {} ticks", tick, error_ticks);
+            }
+        }
+        let tempo = clock.snapshot_at(119 * TICK_AT_120).tempo;
+        assert!((tempo - 120.0).abs() < 1.0, "tempo {}", tempo);
+    }
+
+    #[test]
+    fn start_stop_and_continue_play_and_pause() {
+        let clock = MidiClock::new(4.0);
+        assert!(!clock.snapshot_at(0).is_playing);
+        clock.feed(&[START], 0);
+        assert!(clock.snapshot_at(0).is_playing);
+        for tick in 0..12 {
+            clock.feed(&[CLOCK], tick * TICK_AT_120);
+        }
+
+        clock.feed(&[STOP], 12 * TICK_AT_120);
+        let stopped = clock.snapshot_at(12 * TICK_AT_120);
+        assert!(!stopped.is_playing);
+        assert_eq!(stopped.beat, 0.5);
+        // Clock keeps coming while stopped, without moving the song on
+        for tick in 12..24 {
+            clock.feed(&[CLOCK], tick * TICK_AT_120);
+        }
+        assert_eq!(clock.snapshot_at(24 * TICK_AT_120).beat, 0.5);
+
+        clock.feed(&[CONTINUE], 24 * TICK_AT_120);
+        clock.feed(&[CLOCK], 25 * TICK_AT_120);
+        let continued = clock.snapshot_at(25 * TICK_AT_120);
+        assert!(continued.is_playing);
+        assert_eq!(continued.beat, 0.5);
+
+        clock.feed(&[START], 26 * TICK_AT_120);
+        clock.feed(&[CLOCK], 27 * TICK_AT_120);
+        assert_eq!(clock.snapshot_at(27 * TICK_AT_120).beat, 0.0);
+    }
+
+    #[test]
+    fn song_position_pointer_moves_to_its_sixteenth() {
+        let clock = MidiClock::new(4.0);
+        // 144 sixteenths, sent least significant 7 bits first
+        clock.feed(&[SONG_POSITION, 0x10, 0x01], 0);
+        assert_eq!(clock.snapshot_at(0).beat, 36.0);
+
+        clock.feed(&[CONTINUE], TICK_AT_120);
+        clock.feed(&[CLOCK], 2 * TICK_AT_120);
+        assert_eq!(clock.snapshot_at(2 * TICK_AT_120).beat, 36.0);
+
+        // A clock message in the middle of the pointer doesn't break it
+        clock.feed(&[STOP, SONG_POSITION, 0x04, CLOCK, 0x00], 3 * TICK_AT_120);
+        assert_eq!(clock.snapshot_at(3 * TICK_AT_120).beat, 1.0);
+    }
+}
@@ -0,0 +1,47 @@
+/// Musical position of the transport at one instant, cheap to copy around a frame
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TransportSnapshot {
+    /// beats since the start of the session timeline
+    pub beat: f64,
+    /// whole bars elapsed, `beat / quantum` rounded down
+    pub bar: i64,
+    /// position within the current bar, 0.0 to 1.0
+    pub phase: f64,
+    /// tempo in BPM
+    pub tempo: f64,
+    /// beats per bar
+    pub quantum: f64,
+    pub num_peers: u64,
+    pub is_playing: bool,
+    /// clock time the snapshot was taken at, in microseconds
+    pub timestamp: i64,
+    /// SMPTE time in seconds, when following timecode
+    pub timecode: Option<f64>,
+}
+
+impl TransportSnapshot {
+    /// Snapshot of a playing transport with bar and phase derived from `beat`
+    pub fn at_beat(beat: f64, tempo: f64, quantum: f64, timestamp: i64) -> TransportSnapshot {
+        TransportSnapshot {
+            beat,
+            bar: (beat / quantum).floor() as i64,
+            phase: beat.rem_euclid(quantum) / quantum,
+            tempo,
+            quantum,
+            num_peers: 0,
+            is_playing: true,
+            timestamp,
+            timecode: None,
+        }
+    }
+
+    /// The same instant counted from `origin`, so that beat 0 falls on `origin`
+    pub fn relative_to(&self, origin: f64) -> TransportSnapshot {
+        TransportSnapshot {
+            num_peers: self.num_peers,
+            is_playing: self.is_playing,
+            timecode: self.timecode,
+            ..TransportSnapshot::at_beat(self.beat - origin, self.tempo, self.quantum, self.timestamp)
+        }
+    }
+}
@@ -0,0 +1,664 @@
+use crate::ltc::{self, LtcDecoder};
+use crate::midi_clock::{self, MidiConnection, MidiError};
+use crate::{Transport, TransportSnapshot};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use log::{info, warn};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Without new timecode for this long the source is considered stopped
+const TIMEOUT_MICROS: i64 = 250_000;
+/// Errors larger than this are jumps in the timecode, followed at once instead of smoothed
+const RELOCATE_SECS: f64 = 0.1;
+/// How far the freewheeling time moves towards each received timecode
+const CHASE_SMOOTHING: f64 = 0.2;
+/// How much WAV audio is fed at a time when playing a file as a timecode source
+const WAV_CHUNK: Duration = Duration::from_millis(10);
+
+/// SMPTE frame rates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameRate {
+    Fps24,
+    Fps25,
+    /// 29.97 fps drop frame
+    Fps2997Drop,
+    Fps30,
+}
+
+impl FrameRate {
+    pub fn fps(self) -> f64 {
+        match self {
+            FrameRate::Fps24 => 24.0,
+            FrameRate::Fps25 => 25.0,
+            FrameRate::Fps2997Drop => 30000.0 / 1001.0,
+            FrameRate::Fps30 => 30.0,
+        }
+    }
+
+    /// Frames counted per second in the timecode, 30 for drop frame
+    fn nominal(self) -> u32 {
+        match self {
+            FrameRate::Fps24 => 24,
+            FrameRate::Fps25 => 25,
+            FrameRate::Fps2997Drop | FrameRate::Fps30 => 30,
+        }
+    }
+
+    /// The rate closest to a measured `fps`
+    pub fn nearest(fps: f64, drop_frame: bool) -> FrameRate {
+        if drop_frame {
+            FrameRate::Fps2997Drop
+        } else if fps < 24.5 {
+            FrameRate::Fps24
+        } else if fps < 27.5 {
+            FrameRate::Fps25
+        } else {
+            FrameRate::Fps30
+        }
+    }
+
+    /// Rate named by a frames-per-second number, e.g. `25` or `29.97`
+    pub fn from_fps(fps: f64) -> Option<FrameRate> {
+        [FrameRate::Fps24, FrameRate::Fps25, FrameRate::Fps2997Drop, FrameRate::Fps30]
+            .into_iter()
+            .find(|rate| (rate.fps() - fps).abs() < 0.01)
+    }
+
+    /// Rate from the two rate bits of MTC hour messages
+    fn from_mtc_bits(bits: u8) -> FrameRate {
+        match bits & 0x03 {
+            0 => FrameRate::Fps24,
+            1 => FrameRate::Fps25,
+            2 => FrameRate::Fps2997Drop,
+            _ => FrameRate::Fps30,
+        }
+    }
+}
+
+/// An SMPTE timecode, hours:minutes:seconds:frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub rate: FrameRate,
+}
+
+impl Timecode {
+    /// Time since 00:00:00:00 in seconds, skipping the frame numbers drop frame leaves out
+    pub fn to_secs(&self) -> f64 {
+        let nominal = self.rate.nominal() as i64;
+        let total_minutes = self.hours as i64 * 60 + self.minutes as i64;
+        let mut frames = (total_minutes * 60 + self.seconds as i64) * nominal + self.frames as i64;
+        if self.rate == FrameRate::Fps2997Drop {
+            frames -= 2 * (total_minutes - total_minutes / 10);
+        }
+        frames as f64 / self.rate.fps()
+    }
+
+    /// Parse `HH:MM:SS:FF` at `rate`, or a plain number of seconds
+    pub fn parse_secs(text: &str, rate: FrameRate) -> Option<f64> {
+        if let Ok(secs) = text.parse() {
+            return Some(secs);
+        }
+        let mut fields = text.split([':', ';']).map(|field| field.parse::<u8>().ok());
+        let timecode = Timecode {
+            hours: fields.next()??,
+            minutes: fields.next()??,
+            seconds: fields.next()??,
+            frames: fields.next()??,
+            rate,
+        };
+        if fields.next().is_some() {
+            return None;
+        }
+        Some(timecode.to_secs())
+    }
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.rate == FrameRate::Fps2997Drop { ';' } else { ':' };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, separator, self.frames
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum TimecodeError {
+    Midi(MidiError),
+    Wav(hound::Error),
+    Audio(String),
+}
+
+impl fmt::Display for TimecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimecodeError::Midi(e) => write!(f, "{}", e),
+            TimecodeError::Wav(e) => write!(f, "{}", e),
+            TimecodeError::Audio(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Keeps an audio input feeding a [`TimecodeClock`] open until dropped
+pub struct AudioInput {
+    _stream: cpal::Stream,
+}
+
+/// Transport that chases SMPTE timecode received as MTC or LTC, freewheeling between frames.
+/// Beats are counted at a nominal tempo so beat-based loops still move.
+pub struct TimecodeClock {
+    started: Instant,
+    tempo: f64,
+    quantum: f64,
+    state: Arc<Mutex<ChaseState>>,
+}
+
+impl TimecodeClock {
+    pub fn new(tempo: f64, quantum: f64) -> TimecodeClock {
+        TimecodeClock {
+            started: Instant::now(),
+            tempo,
+            quantum,
+            state: Arc::new(Mutex::new(ChaseState::new())),
+        }
+    }
+
+    /// Microseconds since the clock was created, the time base of the `feed_*` methods and snapshots
+    pub fn now_micros(&self) -> i64 {
+        self.started.elapsed().as_micros() as i64
+    }
+
+    /// Handle raw MIDI bytes that arrived at `micros`
+    pub fn feed_mtc(&self, bytes: &[u8], micros: i64) {
+        self.state.lock().unwrap().feed_mtc(bytes, micros);
+    }
+
+    /// Handle mono LTC audio whose last sample arrived at `micros`
+    pub fn feed_ltc(&self, samples: &[f32], sample_rate: u32, micros: i64) {
+        self.state.lock().unwrap().feed_ltc(samples, sample_rate, micros);
+    }
+
+    /// Where the timecode was at `micros`, for callers feeding input with their own timestamps
+    pub fn snapshot_at(&self, micros: i64) -> TransportSnapshot {
+        let state = self.state.lock().unwrap();
+        let secs = state.secs_at(micros);
+        TransportSnapshot {
+            is_playing: state.is_running(micros),
+            timecode: Some(secs),
+            ..TransportSnapshot::at_beat(secs * self.tempo / 60.0, self.tempo, self.quantum, micros)
+        }
+    }
+
+    /// Chase MTC from the first input port whose name contains `port_name`
+    pub fn connect_mtc(&self, port_name: &str) -> Result<MidiConnection, TimecodeError> {
+        let feed = self.mtc_feeder();
+        midi_clock::connect_input(port_name, feed).map_err(TimecodeError::Midi)
+    }
+
+    /// Open a virtual MIDI port named `port_name` for other software to send MTC to
+    pub fn create_virtual_mtc(&self, port_name: &str) -> Result<MidiConnection, TimecodeError> {
+        let feed = self.mtc_feeder();
+        midi_clock::create_virtual_input(port_name, feed).map_err(TimecodeError::Midi)
+    }
+
+    /// Chase LTC on an audio input, the system default if `device_name` is `None`.
+    /// Only the first channel is decoded.
+    pub fn connect_ltc(&self, device_name: Option<&str>) -> Result<AudioInput, TimecodeError> {
+        let host = cpal::default_host();
+        let device = match device_name {
+            Some(name) => host
+                .input_devices()
+                .map_err(|e| TimecodeError::Audio(e.to_string()))?
+                .find(|device| device.name().is_ok_and(|device_name| device_name.contains(name))),
+            None => host.default_input_device(),
+        }
+        .ok_or_else(|| TimecodeError::Audio(format!("no audio input matching `{}`", device_name.unwrap_or("default"))))?;
+
+        let supported = device.default_input_config().map_err(|e| TimecodeError::Audio(e.to_string()))?;
+        let channels = supported.channels() as usize;
+        let config = supported.config();
+        info!(
+            "Chasing LTC on {} at {} Hz",
+            device.name().unwrap_or_default(),
+            config.sample_rate.0
+        );
+
+        let state = self.state.clone();
+        let started = self.started;
+        let sample_rate = config.sample_rate.0;
+        let mut mono = Vec::new();
+        let stream = match supported.sample_format() {
+            cpal::SampleFormat::F32 => device.build_input_stream(
+                &config,
+                move |data: &[f32], _: &_| {
+                    mono.clear();
+                    mono.extend(data.iter().step_by(channels));
+                    let micros = started.elapsed().as_micros() as i64;
+                    state.lock().unwrap().feed_ltc(&mono, sample_rate, micros);
+                },
+                |e| warn!("LTC input error: {}", e),
+                None,
+            ),
+            cpal::SampleFormat::I16 => device.build_input_stream(
+                &config,
+                move |data: &[i16], _: &_| {
+                    mono.clear();
+                    mono.extend(data.iter().step_by(channels).map(|&s| s as f32 / i16::MAX as f32));
+                    let micros = started.elapsed().as_micros() as i64;
+                    state.lock().unwrap().feed_ltc(&mono, sample_rate, micros);
+                },
+                |e| warn!("LTC input error: {}", e),
+                None,
+            ),
+            format => return Err(TimecodeError::Audio(format!("unsupported sample format {:?}", format))),
+        }
+        .map_err(|e| TimecodeError::Audio(e.to_string()))?;
+        stream.play().map_err(|e| TimecodeError::Audio(e.to_string()))?;
+
+        Ok(AudioInput { _stream: stream })
+    }
+
+    /// Play the LTC in a WAV file in real time on a background thread, as if it came from an input
+    pub fn play_ltc_wav(&self, path: &Path) -> Result<(), TimecodeError> {
+        let (sample_rate, samples) = ltc::read_wav(path).map_err(TimecodeError::Wav)?;
+        info!("Chasing LTC from {}", path.display());
+
+        let state = self.state.clone();
+        let started = self.started;
+        let chunk = (sample_rate as f64 * WAV_CHUNK.as_secs_f64()) as usize;
+        thread::Builder::new()
+            .name("ltc-wav".into())
+            .spawn(move || {
+                let playback_start = Instant::now();
+                for (index, samples) in samples.chunks(chunk).enumerate() {
+                    let due = playback_start + WAV_CHUNK * (index as u32 + 1);
+                    thread::sleep(due.saturating_duration_since(Instant::now()));
+                    let micros = started.elapsed().as_micros() as i64;
+                    state.lock().unwrap().feed_ltc(samples, sample_rate, micros);
+                }
+            })
+            .expect("Failed to spawn LTC playback thread");
+        Ok(())
+    }
+
+    fn mtc_feeder(&self) -> impl FnMut(&[u8]) + Send + 'static {
+        let state = self.state.clone();
+        let started = self.started;
+        move |bytes| {
+            let micros = started.elapsed().as_micros() as i64;
+            state.lock().unwrap().feed_mtc(bytes, micros);
+        }
+    }
+}
+
+impl Transport for TimecodeClock {
+    fn update_phase_and_beat(&mut self) -> TransportSnapshot {
+        self.snapshot_at(self.now_micros())
+    }
+
+    fn set_quantum(&mut self, quantum: f64) {
+        self.quantum = quantum;
+    }
+
+    fn propose_tempo(&mut self, bpm: f64) {
+        // Only changes how beats are counted, timecode runs at its own rate
+        self.tempo = bpm;
+    }
+
+    fn request_beat_at_time(&mut self, _beat: f64, _timestamp: i64) {
+        warn!("Position follows the timecode source and can't be moved here");
+    }
+}
+
+/// Latest timecode received and when, shared with the input threads
+struct ChaseState {
+    /// smoothed timecode in seconds and the time it was reached
+    anchor: Option<(f64, i64)>,
+    /// timecode is arriving continuously, not just a located position
+    running: bool,
+    mtc: MtcParser,
+    ltc: Option<LtcDecoder>,
+}
+
+impl ChaseState {
+    fn new() -> ChaseState {
+        ChaseState {
+            anchor: None,
+            running: false,
+            mtc: MtcParser::new(),
+            ltc: None,
+        }
+    }
+
+    fn feed_mtc(&mut self, bytes: &[u8], micros: i64) {
+        for &byte in bytes {
+            match self.mtc.handle_byte(byte) {
+                Some(MtcEvent::Running(secs)) => self.chase(secs, micros),
+                Some(MtcEvent::Located(timecode)) => {
+                    self.anchor = Some((timecode.to_secs(), micros));
+                    self.running = false;
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn feed_ltc(&mut self, samples: &[f32], sample_rate: u32, micros: i64) {
+        let decoder = self.ltc.get_or_insert_with(|| LtcDecoder::new(sample_rate));
+        let sample_rate = decoder.sample_rate();
+        let last_sample = decoder.samples_decoded() + samples.len() as u64;
+        for frame in decoder.decode(samples) {
+            // LTC describes the frame that just ended, so it is a frame later now
+            let secs = frame.timecode.to_secs() + 1.0 / frame.timecode.rate.fps();
+            let delay = (last_sample - frame.end_sample) as f64 / sample_rate;
+            self.chase(secs, micros - (delay * 1_000_000.0) as i64);
+        }
+    }
+
+    /// Follow a timecode that was current at `micros`
+    fn chase(&mut self, secs: f64, micros: i64) {
+        let predicted = self.secs_at(micros);
+        let secs = if self.running && (secs - predicted).abs() < RELOCATE_SECS {
+            predicted + CHASE_SMOOTHING * (secs - predicted)
+        } else {
+            secs
+        };
+        self.anchor = Some((secs, micros));
+        self.running = true;
+    }
+
+    fn is_running(&self, micros: i64) -> bool {
+        self.running && self.anchor.is_some_and(|(_, at)| micros - at < TIMEOUT_MICROS)
+    }
+
+    fn secs_at(&self, micros: i64) -> f64 {
+        match self.anchor {
+            Some((secs, at)) if self.running => {
+                // Freewheel between frames, but stop once the source has gone quiet
+                let elapsed = (micros - at).clamp(0, TIMEOUT_MICROS);
+                secs + elapsed as f64 / 1_000_000.0
+            }
+            Some((secs, _)) => secs,
+            None => 0.0,
+        }
+    }
+}
+
+enum MtcEvent {
+    /// seconds of a full timecode assembled from quarter frames
+    Running(f64),
+    /// a full frame message, sent when the source jumps while stopped
+    Located(Timecode),
+}
+
+/// Assembles timecode from MTC quarter-frame and full frame messages
+struct MtcParser {
+    /// the eight nibbles of the timecode being assembled
+    pieces: [u8; 8],
+    /// quarter frame pieces received in order since the last gap
+    received: u8,
+    /// expecting the data byte of a quarter frame
+    in_quarter_frame: bool,
+    /// bytes of a system exclusive message, if inside one
+    sysex: Option<Vec<u8>>,
+}
+
+const QUARTER_FRAME: u8 = 0xF1;
+const SYSEX_START: u8 = 0xF0;
+const SYSEX_END: u8 = 0xF7;
+/// Universal real-time sysex header of a full frame message, after F0
+const FULL_FRAME_HEADER: [u8; 4] = [0x7F, 0x7F, 0x01, 0x01];
+/// Longer sysex messages can't be full frames and are skipped
+const FULL_FRAME_LEN: usize = 8;
+
+impl MtcParser {
+    fn new() -> MtcParser {
+        MtcParser {
+            pieces: [0; 8],
+            received: 0,
+            in_quarter_frame: false,
+            sysex: None,
+        }
+    }
+
+    fn handle_byte(&mut self, byte: u8) -> Option<MtcEvent> {
+        // Real-time messages may be interleaved anywhere
+        if byte >= 0xF8 {
+            return None;
+        }
+
+        if let Some(sysex) = self.sysex.as_mut() {
+            if byte != SYSEX_END {
+                // One byte past a full frame is enough to tell it isn't one
+                if sysex.len() <= FULL_FRAME_LEN {
+                    sysex.push(byte);
+                }
+                return None;
+            }
+            let sysex = self.sysex.take().unwrap();
+            return full_frame(&sysex).map(MtcEvent::Located);
+        }
+
+        match byte {
+            QUARTER_FRAME => {
+                self.in_quarter_frame = true;
+                None
+            }
+            SYSEX_START => {
+                self.sysex = Some(Vec::new());
+                None
+            }
+            _ if byte & 0x80 != 0 => {
+                self.in_quarter_frame = false;
+                None
+            }
+            _ if self.in_quarter_frame => {
+                self.in_quarter_frame = false;
+                self.quarter_frame(byte)
+            }
+            _ => None,
+        }
+    }
+
+    fn quarter_frame(&mut self, data: u8) -> Option<MtcEvent> {
+        let piece = (data >> 4) as usize & 0x07;
+        // Pieces must arrive in order from 0, a gap or reverse play starts over
+        if piece != self.received as usize {
+            self.received = 0;
+            if piece != 0 {
+                return None;
+            }
+        }
+        self.pieces[piece] = data & 0x0F;
+        self.received += 1;
+        if self.received < 8 {
+            return None;
+        }
+
+        self.received = 0;
+        let p = &self.pieces;
+        let rate = FrameRate::from_mtc_bits(p[7] >> 1);
+        let timecode = Timecode {
+            hours: (p[7] & 0x01) << 4 | p[6],
+            minutes: p[5] << 4 | p[4],
+            seconds: p[3] << 4 | p[2],
+            frames: p[1] << 4 | p[0],
+            rate,
+        };
+        // The pieces described the frame when piece 0 was sent, two frames ago
+        Some(MtcEvent::Running(timecode.to_secs() + 2.0 / rate.fps()))
+    }
+}
+
+/// Timecode of a full frame sysex message body, without F0 and F7
+fn full_frame(sysex: &[u8]) -> Option<Timecode> {
+    let [header @ .., hours, minutes, seconds, frames] = sysex else {
+        return None;
+    };
+    if header != FULL_FRAME_HEADER {
+        return None;
+    }
+    Some(Timecode {
+        hours: hours & 0x1F,
+        minutes: *minutes,
+        seconds: *seconds,
+        frames: *frames,
+        rate: FrameRate::from_mtc_bits(hours >> 5),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timecode(hours: u8, minutes: u8, seconds: u8, frames: u8, rate: FrameRate) -> Timecode {
+        Timecode { hours, minutes, seconds, frames, rate }
+    }
+
+    fn rate_bits(rate: FrameRate) -> u8 {
+        match rate {
+            FrameRate::Fps24 => 0,
+            FrameRate::Fps25 => 1,
+            FrameRate::Fps2997Drop => 2,
+            FrameRate::Fps30 => 3,
+        }
+    }
+
+    /// The eight quarter frame messages of `timecode`, piece 0 first
+    fn quarter_frames(timecode: Timecode) -> Vec<u8> {
+        let hours = rate_bits(timecode.rate) << 5 | timecode.hours;
+        let values = [timecode.frames, timecode.seconds, timecode.minutes, hours];
+        (0..8u8)
+            .flat_map(|piece| {
+                let value = values[piece as usize / 2];
+                let nibble = if piece % 2 == 0 { value & 0x0F } else { value >> 4 };
+                [QUARTER_FRAME, piece << 4 | nibble]
+            })
+            .collect()
+    }
+
+    fn full_frame_message(timecode: Timecode) -> Vec<u8> {
+        let hours = rate_bits(timecode.rate) << 5 | timecode.hours;
+        let mut message = vec![SYSEX_START];
+        message.extend(FULL_FRAME_HEADER);
+        message.extend([hours, timecode.minutes, timecode.seconds, timecode.frames, SYSEX_END]);
+        message
+    }
+
+    /// Every event `bytes` make, running timecode as seconds
+    fn events(parser: &mut MtcParser, bytes: &[u8]) -> Vec<Result<f64, Timecode>> {
+        bytes
+            .iter()
+            .filter_map(|&byte| match parser.handle_byte(byte)? {
+                MtcEvent::Running(secs) => Some(Ok(secs)),
+                MtcEvent::Located(timecode) => Some(Err(timecode)),
+            })
+            .collect()
+    }
+
+    fn assert_secs(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn quarter_frames_make_a_timecode_two_frames_late() {
+        for rate in [FrameRate::Fps24, FrameRate::Fps25, FrameRate::Fps2997Drop, FrameRate::Fps30] {
+            let sent = timecode(17, 42, 31, 22, rate);
+            let mut parser = MtcParser::new();
+            let bytes = quarter_frames(sent);
+            // Nothing until the eighth piece
+            assert!(events(&mut parser, &bytes[..14]).is_empty());
+            let got = events(&mut parser, &bytes[14..]);
+            assert_eq!(got.len(), 1);
+            assert_secs(got[0].unwrap(), sent.to_secs() + 2.0 / rate.fps());
+        }
+    }
+
+    #[test]
+    fn a_missing_piece_starts_over() {
+        let sent = timecode(1, 0, 0, 0, FrameRate::Fps25);
+        let mut bytes = quarter_frames(sent);
+        // Drop piece 3
+        bytes.drain(6..8);
+        let mut parser = MtcParser::new();
+        assert!(events(&mut parser, &bytes).is_empty());
+        let got = events(&mut parser, &quarter_frames(sent));
+        assert_eq!(got.len(), 1);
+        assert_secs(got[0].unwrap(), 3600.0 + 2.0 / 25.0);
+    }
+
+    #[test]
+    fn real_time_bytes_in_between_are_ignored() {
+        let sent = timecode(0, 0, 10, 5, FrameRate::Fps30);
+        let bytes: Vec<u8> = quarter_frames(sent).into_iter().flat_map(|byte| [0xF8, byte]).collect();
+        let got = events(&mut MtcParser::new(), &bytes);
+        assert_eq!(got.len(), 1);
+        assert_secs(got[0].unwrap(), 10.0 + 7.0 / 30.0);
+    }
+
+    #[test]
+    fn full_frames_locate() {
+        let sent = timecode(10, 20, 30, 12, FrameRate::Fps2997Drop);
+        let got = events(&mut MtcParser::new(), &full_frame_message(sent));
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].unwrap_err(), sent);
+
+        // Other sysex messages, short or long, aren't full frames
+        let mut other = full_frame_message(sent);
+        other[3] = 0x02;
+        assert!(events(&mut MtcParser::new(), &other).is_empty());
+        let mut long = full_frame_message(sent);
+        long.insert(5, 0x00);
+        assert!(events(&mut MtcParser::new(), &long).is_empty());
+        assert!(events(&mut MtcParser::new(), &[SYSEX_START, 0x7F, SYSEX_END]).is_empty());
+    }
+
+    #[test]
+    fn a_full_frame_holds_still_and_quarter_frames_run() {
+        let clock = TimecodeClock::new(120.0, 4.0);
+        clock.feed_mtc(&full_frame_message(timecode(0, 1, 0, 0, FrameRate::Fps25)), 0);
+        let snapshot = clock.snapshot_at(100_000);
+        assert!(!snapshot.is_playing);
+        assert_secs(snapshot.timecode.unwrap(), 60.0);
+
+        clock.feed_mtc(&quarter_frames(timecode(0, 2, 0, 0, FrameRate::Fps25)), 200_000);
+        let snapshot = clock.snapshot_at(300_000);
+        assert!(snapshot.is_playing);
+        assert_secs(snapshot.timecode.unwrap(), 120.0 + 2.0 / 25.0 + 0.1);
+    }
+
+    #[test]
+    fn drop_frame_skips_two_frame_numbers_a_minute() {
+        let fps = 30000.0 / 1001.0;
+        // 00:00:59;29 is followed by 00:01:00;02
+        assert_secs(timecode(0, 0, 59, 29, FrameRate::Fps2997Drop).to_secs(), 1799.0 / fps);
+        assert_secs(timecode(0, 1, 0, 2, FrameRate::Fps2997Drop).to_secs(), 1800.0 / fps);
+        // except every tenth minute
+        assert_secs(timecode(0, 10, 0, 0, FrameRate::Fps2997Drop).to_secs(), 17982.0 / fps);
+        assert_secs(timecode(1, 0, 0, 0, FrameRate::Fps2997Drop).to_secs(), 107892.0 / fps);
+        assert!((timecode(1, 0, 0, 0, FrameRate::Fps2997Drop).to_secs() - 3600.0).abs() < 0.01);
+        assert_secs(timecode(1, 0, 0, 0, FrameRate::Fps30).to_secs(), 3600.0);
+    }
+
+    #[test]
+    fn parses_timecodes_and_seconds() {
+        assert_eq!(Timecode::parse_secs("12.5", FrameRate::Fps25), Some(12.5));
+        assert_eq!(Timecode::parse_secs("90", FrameRate::Fps25), Some(90.0));
+        assert_secs(Timecode::parse_secs("01:00:00:12", FrameRate::Fps25).unwrap(), 3600.48);
+        assert_secs(Timecode::parse_secs("00:00:01:12", FrameRate::Fps24).unwrap(), 1.5);
+        assert_secs(Timecode::parse_secs("00:01:00;02", FrameRate::Fps2997Drop).unwrap(), 1800.0 * 1001.0 / 30000.0);
+        assert_eq!(Timecode::parse_secs("01:00:00", FrameRate::Fps25), None);
+        assert_eq!(Timecode::parse_secs("01:00:00:00:00", FrameRate::Fps25), None);
+        assert_eq!(Timecode::parse_secs("01:xx:00:00", FrameRate::Fps25), None);
+        assert_eq!(Timecode::parse_secs("", FrameRate::Fps25), None);
+    }
+}